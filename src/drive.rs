@@ -1,86 +1,227 @@
-use std::collections::HashMap;
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use byteorder::{BigEndian, ReadBytesExt};
-use fatfs;
-use fscommon;
 
 use crate::error::*;
 use crate::native::*;
+use crate::progress::*;
+use crate::storage::*;
 use crate::workouts::*;
 
 pub struct Drive {
-    fs: fatfs::FileSystem<fscommon::BufStream<std::fs::File>>
+    storage: Box<dyn Storage>,
+    /// false if the drive was opened without write access, checked by every
+    /// method that changes the drive
+    writable: bool
+}
+
+/// Workout saved as favorite on the monitor, read from `Favorites.bin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Favorite {
+    /// Type code as stored on the drive
+    pub type_code: u8,
+    /// `None` if the type code isn't a known workout type
+    pub workout_type: Option<WorkoutType>,
+    /// Target of the workout, in meters for distance workouts. The unit for
+    /// other types hasn't been figured out yet.
+    pub target: u32,
+    /// Split size, in the same unit as the target
+    pub split: u32,
+}
+
+/// Files in `Concept2/Logbook` that every set up drive has
+const LOGBOOK_FILES: [&str; 3] = ["LogDataAccessTbl.bin", "LogDataStorage.bin", "UserStatic.bin"];
+
+/// User profile stored in `UserStatic.bin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UserProfile {
+    pub user_id: u16,
+    pub name: String,
+}
+
+/// Information about the monitor the drive was last used with, read from
+/// `DeviceLogInfo.bin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceInfo {
+    pub serial_number: u32,
+    pub firmware_major: u8,
+    pub firmware_minor: u8,
+}
+
+impl DeviceInfo {
+    /// Returns the firmware version as displayed by the monitor, e.g. `32.00`.
+    pub fn firmware_version(&self) -> String {
+        format!("{}.{:02}", self.firmware_major, self.firmware_minor)
+    }
+}
+
+/// Raw bytes of a workout as stored on the drive, for inspecting fields that
+/// aren't understood yet, see `native::storage_record_fields`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawWorkoutRecord {
+    /// Offset of the entry in `LogDataAccessTbl.bin`
+    pub access_table_offset: u64,
+    pub access_table_entry: Vec<u8>,
+    /// Offset of the record in `LogDataStorage.bin`
+    pub storage_offset: u64,
+    pub storage_record: Vec<u8>,
+}
+
+/// Inconsistency between an access table entry and the storage record it
+/// references, as found by `Drive::validate`. `index` is the position of the
+/// workout, as returned by `workouts`.
+#[derive(Debug)]
+pub enum Inconsistency {
+    /// The record doesn't fit into `LogDataStorage.bin`
+    OutOfBounds { index: usize, offset: u64, size: u64, file_len: u64 },
+    /// The record shares bytes with the record of another entry
+    Overlap { index: usize, offset: u64, other: usize, other_offset: u64 },
+    /// The record size in the access table doesn't match the parsed record
+    SizeMismatch { index: usize, record_size: usize, layout_size: usize },
+    /// The record couldn't be parsed at all
+    Unparseable { index: usize, error: ParserError }
+}
+
+impl Inconsistency {
+    pub fn index(&self) -> usize {
+        match self {
+            Self::OutOfBounds { index, .. } | Self::Overlap { index, .. } |
+                Self::SizeMismatch { index, .. } | Self::Unparseable { index, .. } => *index
+        }
+    }
+}
+
+impl std::fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::OutOfBounds { offset, size, file_len, .. } => {
+                write!(f, "Record at 0x{:x} with {} bytes extends past the end of LogDataStorage.bin (0x{:x} bytes).",
+                    offset, size, file_len)
+            },
+            Self::Overlap { offset, other_offset, .. } => {
+                write!(f, "Record at 0x{:x} overlaps the record at 0x{:x}.", offset, other_offset)
+            },
+            Self::SizeMismatch { record_size, layout_size, .. } => {
+                write!(f, "Access table lists {} bytes, but the record has {}.", record_size, layout_size)
+            },
+            Self::Unparseable { error, .. } => write!(f, "{}", error)
+        }
+    }
 }
 
 
 impl Drive {
+    /// Opens the drive at the given path. If the path is a directory, it is
+    /// assumed to be the mount point of the drive, otherwise it is parsed
+    /// as a FAT filesystem.
     pub fn new<P: AsRef<Path>>(drive_path: P, allow_writing: bool) -> Result<Self,std::io::Error> {
+        if drive_path.as_ref().is_dir() {
+            return Self::from_mounted(drive_path, allow_writing);
+        }
+
         let img_file = std::fs::OpenOptions::new()
             .read(true)
             .write(allow_writing)
             .open(drive_path)?;
-        let buf_stream = fscommon::BufStream::new(img_file);
-        let fs = fatfs::FileSystem::new(buf_stream, fatfs::FsOptions::new())?;
 
-        Ok(Drive { fs })
+        Self::from_stream(img_file, allow_writing)
     }
 
-    pub fn init<P: AsRef<Path>>(drive_path: P, user_name: String) -> Result<Self,std::io::Error> {
-        let mut name = user_name.into_bytes();
-        if name.len() < 1 || name.len() > 6 {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Name needs to be <= 6 characters!"));
-        }
+    /// Opens a drive from any stream containing a FAT filesystem, e.g. an
+    /// already opened device or an image held in memory. If the stream
+    /// starts with an MBR partition table, the first FAT partition is used.
+    pub fn from_stream<T: Read + Write + Seek + 'static>(stream: T, allow_writing: bool) -> Result<Self,std::io::Error> {
+        let storage = if allow_writing {
+            fat_storage(stream)?
+        } else {
+            fat_storage(ReadOnly(stream))?
+        };
+
+        Ok(Drive { storage, writable: allow_writing })
+    }
+
+    /// Opens a gzip compressed drive image, e.g. `drive.img.gz`, read-only.
+    /// The image is decompressed into memory, since the filesystem needs to
+    /// seek around in it.
+    pub fn from_gzip<P: AsRef<Path>>(path: P) -> Result<Self,std::io::Error> {
+        let mut decoder = flate2::read::GzDecoder::new(File::open(path)?);
+        let mut image = Vec::new();
+        decoder.read_to_end(&mut image)?;
+
+        Self::from_stream(std::io::Cursor::new(image), false)
+    }
+
+    /// Opens a drive that is already mounted at the given directory. Files
+    /// are accessed through the regular filesystem, so no access to the
+    /// block device is needed.
+    pub fn from_mounted<P: AsRef<Path>>(dir: P, allow_writing: bool) -> Result<Self,std::io::Error> {
+        let metadata = std::fs::metadata(dir.as_ref())?;
 
-        name.resize(6, 0x00);
+        if !metadata.is_dir() {
+            return Err(std::io::Error::other(format!("{} is not a directory.", dir.as_ref().display())));
+        }
 
-        let status = std::process::Command::new("mkfs.fat")
-            .arg(drive_path.as_ref())
-            .stdout(std::process::Stdio::null())
-            .status()?;
-        if !status.success() {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Failed to format drive."));
+        if allow_writing && metadata.permissions().readonly() {
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied,
+                format!("{} is mounted read-only.", dir.as_ref().display())));
         }
 
+        Ok(Drive { storage: Box::new(MountedStorage::new(dir.as_ref())), writable: allow_writing })
+    }
+
+    pub fn init<P: AsRef<Path>>(drive_path: P, user_name: String) -> Result<Self,std::io::Error> {
         let img_file = std::fs::OpenOptions::new()
             .read(true)
             .write(true)
             .open(drive_path)?;
-        let buf_stream = fscommon::BufStream::new(img_file);
-        let fs = fatfs::FileSystem::new(buf_stream, fatfs::FsOptions::new())?;
+
+        Self::init_stream(img_file, user_name)
+    }
+
+    /// Like `init`, but formats any stream, e.g. an image held in memory.
+    pub fn init_stream<T: Read + Write + Seek + 'static>(stream: T, user_name: String) -> Result<Self,std::io::Error> {
+        let name = user_name_bytes(user_name)?;
+
+        let mut buf_stream = fscommon::BufStream::new(stream);
+
+        fatfs::format_volume(&mut buf_stream, fatfs::FormatVolumeOptions::new())?;
+        buf_stream.seek(SeekFrom::Start(0))?;
+
+        let storage = FatStorage::new(buf_stream)?;
+
+        storage.create_dir("Concept2")?;
+        storage.create_dir("Concept2/DiagLog")?;
+        storage.create_dir("Concept2/Firmware")?;
+        storage.create_dir("Concept2/Logbook")?;
+        storage.create_dir("Concept2/Special")?;
+
+        let seed_files: [(&str, &[u8]); 8] = [
+            ("DeviceLogInfo.bin", include_bytes!("data/DeviceLogInfo.bin")),
+            ("Favorites.bin", include_bytes!("data/Favorites.bin")),
+            ("LogDataAccessTbl.bin", include_bytes!("data/LogDataAccessTbl.bin")),
+            ("LogDataStorage.bin", include_bytes!("data/LogDataStorage.bin")),
+            ("LogStrokeInfo.bin", include_bytes!("data/LogStrokeInfo.bin")),
+            ("StrokeDataAccessTbl.bin", include_bytes!("data/StrokeDataAccessTbl.bin")),
+            ("StrokeDataStorage.bin", include_bytes!("data/StrokeDataStorage.bin")),
+            ("UserDynamic.bin", include_bytes!("data/UserDynamic.bin")),
+        ];
+
+        for (name, data) in seed_files.iter() {
+            let mut file = storage.create_file(&format!("Concept2/Logbook/{}", name))?;
+            file.write_all(data)?;
+        }
 
         {
-            let root_dir = fs.root_dir();
-
-            root_dir.create_dir("Concept2")?;
-            root_dir.create_dir("Concept2/DiagLog")?;
-            root_dir.create_dir("Concept2/Firmware")?;
-            let log_book = root_dir.create_dir("Concept2/Logbook")?;
-            root_dir.create_dir("Concept2/Special")?;
-
-            let mut file = log_book.create_file("DeviceLogInfo.bin")?;
-            file.write(include_bytes!("data/DeviceLogInfo.bin"))?;
-            let mut file = log_book.create_file("Favorites.bin")?;
-            file.write(include_bytes!("data/Favorites.bin"))?;
-            let mut file = log_book.create_file("LogDataAccessTbl.bin")?;
-            file.write(include_bytes!("data/LogDataAccessTbl.bin"))?;
-            let mut file = log_book.create_file("LogDataStorage.bin")?;
-            file.write(include_bytes!("data/LogDataStorage.bin"))?;
-            let mut file = log_book.create_file("LogStrokeInfo.bin")?;
-            file.write(include_bytes!("data/LogStrokeInfo.bin"))?;
-            let mut file = log_book.create_file("StrokeDataAccessTbl.bin")?;
-            file.write(include_bytes!("data/StrokeDataAccessTbl.bin"))?;
-            let mut file = log_book.create_file("StrokeDataStorage.bin")?;
-            file.write(include_bytes!("data/StrokeDataStorage.bin"))?;
-            let mut file = log_book.create_file("UserDynamic.bin")?;
-            file.write(include_bytes!("data/UserDynamic.bin"))?;
-
-            let mut file = log_book.create_file("UserStatic.bin")?;
-            file.write(&[0x91, 0x00])?;
-            file.write(&name)?;
-            file.write(&[
+            let mut file = storage.create_file("Concept2/Logbook/UserStatic.bin")?;
+            file.write_all(&[0x91, 0x00])?;
+            file.write_all(&name)?;
+            file.write_all(&[
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
@@ -90,7 +231,6 @@ impl Drive {
                 0x00, 0x00])?;
         }
 
-        //let mut file = log_book.create_file("UserStatic.bin")?;
         //UserStatic.bin
 
         //00000000: 9100 666c 756d 6d69 0000 0000 0000 0000  ..flummi........
@@ -98,13 +238,31 @@ impl Drive {
         //00000020: 0000 0000 0000 0000 0000 00af 0000 0000  ................
         //00000030: 0000 0000 0000 0000 0000                 ..........
 
-        Ok(Drive { fs })
+        Ok(Drive { storage: Box::new(storage), writable: true })
+    }
+
+    /// Returns true if the drive has been set up by a monitor or with `init`.
+    pub fn is_concept2_drive(&self) -> bool {
+        self.check_concept2_drive().is_ok()
+    }
+
+    /// Like `is_concept2_drive`, but returns an error naming what's missing.
+    pub fn check_concept2_drive(&self) -> Result<(),std::io::Error> {
+        let missing = std::iter::once("Concept2/Logbook".to_string())
+            .chain(LOGBOOK_FILES.iter().map(|name| format!("Concept2/Logbook/{}", name)))
+            .find(|path| !self.storage.exists(path));
+
+        match missing {
+            Some(path) => Err(std::io::Error::new(std::io::ErrorKind::NotFound,
+                format!("Not a Concept2 drive, {} is missing.", path))),
+            None => Ok(())
+        }
     }
 
     /// Returns a tuple of the user id and user name that is configured
     /// on the drive.
-    pub fn user(&mut self) -> Result<(u16,String),std::io::Error> {
-        let mut user_static_file = self.fs.root_dir().open_file("Concept2/Logbook/UserStatic.bin")?;
+    pub fn user(&self) -> Result<(u16,String),std::io::Error> {
+        let mut user_static_file = self.storage.open_file("Concept2/Logbook/UserStatic.bin")?;
         let mut buffer = [0; 6];
 
         user_static_file.seek(SeekFrom::Start(0x02))?;
@@ -113,16 +271,46 @@ impl Drive {
         user_static_file.seek(SeekFrom::Start(0x2a))?;
         let user_id = user_static_file.read_u16::<BigEndian>()?;
 
-        Ok((user_id, String::from_utf8(buffer.to_vec()).unwrap()))
+        // names shorter than 6 bytes are padded with nulls. Anything that
+        // isn't valid UTF-8 wasn't written by us, so don't fail on it.
+        let len = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+        let name = String::from_utf8_lossy(&buffer[..len]).into_owned();
+
+        Ok((user_id, name))
+    }
+
+    /// Fails with a descriptive error if the drive was opened without write
+    /// access, before anything is changed.
+    fn check_writable(&self) -> Result<(),std::io::Error> {
+        if self.writable {
+            Ok(())
+        } else {
+            Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied,
+                "Drive was opened read-only, open it with write access to change it."))
+        }
+    }
+
+    /// Changes the user name, leaving the rest of the drive untouched. Like
+    /// for `init`, the name needs to be <= 6 characters.
+    pub fn set_user_name(&mut self, user_name: String) -> Result<(),std::io::Error> {
+        self.check_writable()?;
+        let name = user_name_bytes(user_name)?;
+
+        let mut user_static_file = self.storage.open_file_rw("Concept2/Logbook/UserStatic.bin")?;
+        user_static_file.seek(SeekFrom::Start(0x02))?;
+        user_static_file.write_all(&name)?;
+        user_static_file.flush()
     }
 
-    pub fn workouts(&mut self) -> Result<Vec<Workout>,ParserError> {
-        let mut access_table_file = self.fs.root_dir().open_file("Concept2/Logbook/LogDataAccessTbl.bin")?;
-        let mut storage_file = self.fs.root_dir().open_file("Concept2/Logbook/LogDataStorage.bin")?;
+    /// Reads all entries of the log data access table.
+    fn access_table_entries(&self) -> Result<Vec<LogDataAccessTableEntry>,ParserError> {
+        let mut access_table_file = self.storage.open_file("Concept2/Logbook/LogDataAccessTbl.bin")?;
         let mut access_table_entries: Vec<LogDataAccessTableEntry> = Vec::new();
 
         loop {
-            let entry = LogDataAccessTableEntry::read(&mut access_table_file)?;
+            let offset = access_table_entries.len() as u64 * 32;
+            let entry = LogDataAccessTableEntry::read(&mut access_table_file)
+                .map_err(|e| e.at("LogDataAccessTbl entry", offset))?;
 
             // 0x70 was only encountered at the end
             if entry.magic == 0xff || entry.magic == 0x70 {
@@ -132,41 +320,607 @@ impl Drive {
             access_table_entries.push(entry);
         }
 
+        Ok(access_table_entries)
+    }
+
+    /// Reads the storage record referenced by an access table entry. An
+    /// offset past the end of the storage file or a record size that doesn't
+    /// match the layout only fails this workout, so `workouts_lenient` can
+    /// skip it.
+    ///
+    /// Drag factors are only decoded if the firmware version of the monitor
+    /// that wrote the record is given.
+    fn read_workout<R: Read + Seek>(
+        storage_file: &mut R,
+        at_entry: &LogDataAccessTableEntry,
+        firmware: Option<(u8, u8)>
+    ) -> Result<Workout,ParserError> {
+        let file_len = storage_file.seek(SeekFrom::End(0))?;
+        let offset = u64::from(at_entry.record_offset);
+        if offset >= file_len {
+            return Err(ParserError::RecordOutOfBounds { offset, file_len });
+        }
+
+        storage_file.seek(SeekFrom::Start(offset))?;
+
+        let entry = LogDataStorageEntry::read_layout(storage_file, RecordLayout::for_firmware(firmware))
+            .map_err(|e| e.at("LogDataStorage record", offset))?;
+
+        // rather fail than return garbage for records in an unknown layout
+        entry.check_layout(at_entry)
+            .map_err(|e| e.at("LogDataStorage record", offset))?;
+
+        let drag_factors = firmware.map(|firmware| entry.drag_factors(firmware)).unwrap_or_default();
+        let heart_rate_ranges = firmware.map(|firmware| entry.heart_rate_ranges(firmware)).unwrap_or_default();
+
+        // the storage record doesn't know which program was picked
+        let mut workout = Workout::try_from(entry)
+            .map_err(|e| e.at("LogDataStorage record", offset))?;
+        for (frame, drag_factor) in workout.frames.iter_mut().zip(drag_factors) {
+            frame.drag_factor = drag_factor;
+        }
+        for (frame, range) in workout.frames.iter_mut().zip(heart_rate_ranges) {
+            if let Some(range) = range {
+                frame.heart_rate_min = range.min;
+                frame.heart_rate_max = range.max;
+                frame.heart_rate_avg = range.avg;
+            }
+        }
+        workout.name = at_entry.workout_name();
+        workout.rest_interval = at_entry.interval_rest_time().or(workout.rest_interval);
+        Ok(workout)
+    }
+
+    /// Returns all workouts on the drive, failing if any of them can't be
+    /// parsed.
+    pub fn workouts(&self) -> Result<Vec<Workout>,ParserError> {
+        self.workouts_iter()?.collect()
+    }
+
+    /// Returns an iterator over the workouts on the drive. Access table
+    /// entries are read as the iterator advances and storage records are
+    /// only parsed for workouts that are actually returned, so skipping
+    /// workouts with `nth` is cheap.
+    pub fn workouts_iter(&self) -> Result<WorkoutIter<'_>,ParserError> {
+        Ok(WorkoutIter::new(
+            self.storage.open_file("Concept2/Logbook/LogDataAccessTbl.bin")?,
+            self.storage.open_file("Concept2/Logbook/LogDataStorage.bin")?,
+            self.firmware()
+        ))
+    }
+
+    /// Returns the number of workouts on the drive, without parsing any of
+    /// them.
+    pub fn workout_count(&self) -> Result<usize,ParserError> {
+        Ok(self.access_table_entries()?.len())
+    }
+
+    /// Like `workouts`, but skips workouts that can't be parsed instead of
+    /// failing. Returns the parsed workouts along with the errors for the
+    /// skipped ones. Errors in the access table itself are still fatal.
+    pub fn workouts_lenient(&self) -> Result<(Vec<Workout>,Vec<ParserError>),ParserError> {
+        let access_table_entries = self.access_table_entries()?;
+        let mut storage_file = self.storage.open_file("Concept2/Logbook/LogDataStorage.bin")?;
+
+        let firmware = self.firmware();
+
         let mut workouts = Vec::with_capacity(access_table_entries.len());
+        let mut errors = Vec::new();
 
         for at_entry in access_table_entries {
-            storage_file.seek(SeekFrom::Start(at_entry.record_offset.into()))?;
+            match Self::read_workout(&mut storage_file, &at_entry, firmware) {
+                Ok(workout) => workouts.push(workout),
+                Err(e) => errors.push(e)
+            }
+        }
+
+        Ok((workouts, errors))
+    }
+
+    /// Returns the most recent workout, only decoding its storage record
+    /// instead of the entire logbook.
+    pub fn last_workout(&self) -> Result<Option<Workout>,ParserError> {
+        let at_entry = match self.access_table_entries()?.pop() {
+            Some(entry) => entry,
+            None => return Ok(None)
+        };
+
+        let mut storage_file = self.storage.open_file("Concept2/Logbook/LogDataStorage.bin")?;
+        Ok(Some(Self::read_workout(&mut storage_file, &at_entry, self.firmware())?))
+    }
+
+    /// Returns the unparsed access table entry and storage record of the
+    /// workout at the given position (as returned by `workouts`). Only the
+    /// access table has to be valid, so this also works for records that
+    /// fail to parse.
+    pub fn raw_workout_record(&self, index: usize) -> Result<RawWorkoutRecord,ParserError> {
+        let entries = self.access_table_entries()?;
+        let at_entry = entries.get(index).ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput, format!("No workout at position {}.", index)))?;
+
+        let access_table = self.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin")?;
+        let storage = self.read_file_bytes("Concept2/Logbook/LogDataStorage.bin")?;
+
+        let access_table_offset = index * LogDataAccessTableEntry::SIZE;
+        let start = at_entry.record_offset as usize;
+        let end = start + at_entry.record_size as usize;
+        if end > storage.len() {
+            return Err(ParserError::UnexpectedEof.at("LogDataStorage record", start as u64));
+        }
+
+        Ok(RawWorkoutRecord {
+            access_table_offset: access_table_offset as u64,
+            access_table_entry: access_table[access_table_offset..(access_table_offset + LogDataAccessTableEntry::SIZE)].to_vec(),
+            storage_offset: start as u64,
+            storage_record: storage[start..end].to_vec(),
+        })
+    }
+
+    /// Cross-checks the access table against the storage records it
+    /// references, without changing anything. Returns the inconsistencies
+    /// found, ordered by workout; none means the logbook is consistent.
+    /// Errors in the access table itself are still fatal.
+    pub fn validate(&self) -> Result<Vec<Inconsistency>,ParserError> {
+        let entries = self.access_table_entries()?;
+        let storage = self.read_file_bytes("Concept2/Logbook/LogDataStorage.bin")?;
+        let layout = RecordLayout::for_firmware(self.firmware());
+
+        let mut issues = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            let offset = entry.record_offset as usize;
+            let record_size = entry.record_size as usize;
+            if offset + record_size > storage.len() {
+                issues.push(Inconsistency::OutOfBounds {
+                    index,
+                    offset: offset as u64,
+                    size: record_size as u64,
+                    file_len: storage.len() as u64
+                });
+                continue;
+            }
+
+            // parse up to the end of the file, the size in the access table
+            // might be the wrong one
+            match LogDataStorageEntry::read_layout(&mut &storage[offset..], layout) {
+                // fields like the timestamp are only checked when converting
+                // the record
+                Ok(record) => match record.check_layout(entry).and_then(|_| Workout::try_from(record)) {
+                    Err(ParserError::LayoutMismatch { record_size, layout_size }) => {
+                        issues.push(Inconsistency::SizeMismatch { index, record_size, layout_size });
+                    },
+                    Err(error) => issues.push(Inconsistency::Unparseable {
+                        index,
+                        error: error.at("LogDataStorage record", offset as u64)
+                    }),
+                    Ok(_) => {}
+                },
+                Err(error) => issues.push(Inconsistency::Unparseable {
+                    index,
+                    error: error.at("LogDataStorage record", offset as u64)
+                })
+            }
+        }
+
+        // compare each record to the one reaching furthest before it
+        let mut ranges: Vec<(usize, usize, usize)> = entries.iter().enumerate()
+            .map(|(i, e)| (e.record_offset as usize, e.record_offset as usize + e.record_size as usize, i))
+            .filter(|(start, end, _)| end > start)
+            .collect();
+        ranges.sort();
 
-            let entry = LogDataStorageEntry::read(&mut storage_file)?;
-            let workout = entry.into();
-            workouts.push(workout);
+        let mut furthest: Option<(usize, usize, usize)> = None;
+        for (start, end, index) in ranges {
+            if let Some((other_start, other_end, other)) = furthest {
+                if start < other_end {
+                    issues.push(Inconsistency::Overlap {
+                        index,
+                        offset: start as u64,
+                        other,
+                        other_offset: other_start as u64
+                    });
+                }
+            }
+            if furthest.map(|(_, other_end, _)| end > other_end).unwrap_or(true) {
+                furthest = Some((start, end, index));
+            }
         }
 
-        Ok(workouts)
+        issues.sort_by_key(|issue| issue.index());
+        Ok(issues)
     }
 
-    pub fn export_workouts<P: AsRef<Path>>(&mut self, _csv_path: P) -> Result<(),std::io::Error> {
-        todo!();
+    /// Returns the user profile stored on the drive.
+    ///
+    /// The body weight isn't part of it: the monitor asks for it, but where
+    /// it ends up in `UserStatic.bin` isn't known. The u16 at 0x2a (175 on
+    /// freshly initialized drives) looks like the monitor's default weight
+    /// in pounds, but it's also what `user` returns as user id, so it can't
+    /// be trusted to be either. Weight-corrected calories have to use an
+    /// `AthleteProfile` instead. Age and sex don't seem to be stored at all.
+    pub fn user_profile(&self) -> Result<UserProfile,std::io::Error> {
+        let (user_id, name) = self.user()?;
+
+        Ok(UserProfile { user_id, name })
     }
 
-    // TODO: clear, write workouts
+    /// Returns serial number and firmware version of the monitor.
+    ///
+    /// `DeviceLogInfo.bin` starts with the serial number at 0x00 (u32, big
+    /// endian), followed by the major and minor firmware version at 0x04 and
+    /// 0x05 (one byte each). The rest of the file is not understood yet.
+    pub fn device_info(&self) -> Result<DeviceInfo,std::io::Error> {
+        let mut device_file = self.storage.open_file("Concept2/Logbook/DeviceLogInfo.bin")?;
 
-    pub fn firmwares(&mut self) -> Result<Vec<String>,std::io::Error> {
-        let firmware_dir = self.fs.root_dir().open_dir("Concept2/Firmware");
-        if let Err(_) = firmware_dir {
-            return Ok(Vec::new());
+        let serial_number = device_file.read_u32::<BigEndian>()?;
+        let firmware_major = device_file.read_u8()?;
+        let firmware_minor = device_file.read_u8()?;
+
+        Ok(DeviceInfo { serial_number, firmware_major, firmware_minor })
+    }
+
+    /// Firmware version of the monitor as (major, minor), if the drive has
+    /// been used with one.
+    fn firmware(&self) -> Option<(u8, u8)> {
+        self.device_info().ok().map(|info| (info.firmware_major, info.firmware_minor))
+    }
+
+    /// Returns the lifetime meters as counted by the monitor, which include
+    /// workouts that are no longer stored on the drive.
+    ///
+    /// `UserDynamic.bin` consists of three 74 byte records, each starting
+    /// with `03 7f 82 00` and ending with `ff ff ff`, presumably one per
+    /// machine type. The lifetime meters of the rower are read as u32 (big
+    /// endian) at 0x04 of the first record. That offset is inferred from the
+    /// layout of the other files and hasn't been confirmed on a drive with
+    /// a known total yet; freshly initialized drives hold 0 there.
+    pub fn lifetime_meters(&self) -> Result<u32,std::io::Error> {
+        let mut user_dynamic_file = self.storage.open_file("Concept2/Logbook/UserDynamic.bin")?;
+
+        let mut magic = [0; 4];
+        user_dynamic_file.read_exact(&mut magic)?;
+        if magic != [0x03, 0x7f, 0x82, 0x00] {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                "Unexpected contents of UserDynamic.bin."));
         }
 
-        let mut firmwares = Vec::new();
+        user_dynamic_file.read_u32::<BigEndian>()
+    }
+
+    /// Returns the favorite workouts stored on the drive, in the order the
+    /// monitor lists them.
+    pub fn favorites(&self) -> Result<Vec<Favorite>,ParserError> {
+        let mut favorites_file = self.storage.open_file("Concept2/Logbook/Favorites.bin")?;
+        favorites_file.seek(SeekFrom::Start(FavoritesEntry::HEADER_SIZE))?;
+
+        let mut favorites = Vec::new();
+        loop {
+            let offset = FavoritesEntry::HEADER_SIZE + favorites.len() as u64 * FavoritesEntry::SIZE;
+            let entry = match FavoritesEntry::read(&mut favorites_file) {
+                // the table may fill the entire file
+                Err(ParserError::UnexpectedEof) => break,
+                result => result.map_err(|e| e.at("Favorites entry", offset))?
+            };
+
+            if entry.magic == 0xff {
+                break;
+            }
+
+            favorites.push(Favorite {
+                type_code: entry.workout_type,
+                workout_type: entry.workout_type.try_into().ok(),
+                target: entry.target,
+                split: entry.split,
+            });
+        }
+
+        Ok(favorites)
+    }
+
+    /// Returns the stroke samples recorded for the given workout. Returns an
+    /// empty list if the monitor didn't store any strokes for it.
+    pub fn stroke_data(&self, workout: &Workout) -> Result<Vec<Stroke>,ParserError> {
+        // older drives may not have the info file, assume the known layout
+        if self.storage.exists("Concept2/Logbook/LogStrokeInfo.bin") {
+            let info = self.stroke_info()?;
+            if !info.is_supported() {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                    "Unsupported stroke data layout ({} byte entries, {} byte records).",
+                    info.access_table_entry_size, info.storage_record_size
+                )).into());
+            }
+        }
+
+        let mut access_table_file = self.storage.open_file("Concept2/Logbook/StrokeDataAccessTbl.bin")?;
 
-        for fw in firmware_dir.unwrap().iter() {
-            let name = fw?.file_name();
+        let mut offset = 0;
+        let at_entry = loop {
+            let entry = StrokeDataAccessTableEntry::read(&mut access_table_file)
+                .map_err(|e| e.at("StrokeDataAccessTbl entry", offset))?;
+            offset += StrokeDataAccessTableEntry::SIZE as u64;
 
-            if name.chars().nth(0).unwrap() == '.' {
+            if entry.magic == 0xff {
+                return Ok(Vec::new());
+            }
+
+            if entry.serial_number == workout.serial_number && entry.record_id == workout.record_id {
+                break entry;
+            }
+        };
+
+        let mut storage_file = self.storage.open_file("Concept2/Logbook/StrokeDataStorage.bin")?;
+        storage_file.seek(SeekFrom::Start(at_entry.record_offset.into()))?;
+
+        let mut strokes = Vec::with_capacity(at_entry.num_strokes.into());
+        for i in 0..at_entry.num_strokes as u64 {
+            let offset = at_entry.record_offset as u64 + i * StrokeDataStorageEntry::SIZE as u64;
+            let entry = StrokeDataStorageEntry::read(&mut storage_file)
+                .map_err(|e| e.at("StrokeDataStorage record", offset))?;
+            strokes.push(entry.into());
+        }
+
+        Ok(strokes)
+    }
+
+    /// Reads `LogStrokeInfo.bin`, which describes the layout of the stroke
+    /// data files.
+    pub fn stroke_info(&self) -> Result<LogStrokeInfo,ParserError> {
+        let mut info_file = self.storage.open_file("Concept2/Logbook/LogStrokeInfo.bin")?;
+        LogStrokeInfo::read(&mut info_file).map_err(|e| e.at("LogStrokeInfo", 0))
+    }
+
+    /// Returns the raw contents of a file on the drive, given its path
+    /// relative to the root, e.g. `Concept2/Logbook/StrokeDataStorage.bin`.
+    pub fn read_file_bytes(&self, path: &str) -> Result<Vec<u8>,std::io::Error> {
+        let mut file = self.storage.open_file(path)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Writes all workouts on the drive as CSV to the given writer.
+    pub fn export_workouts<W: Write>(&self, writer: W) -> Result<(),ParserError> {
+        let workouts = self.workouts()?;
+        crate::export::write_csv(&workouts, writer)?;
+        Ok(())
+    }
+
+    /// Writes all workouts on the drive as a JSON array to the given writer,
+    /// see `export::write_json`. Workouts are parsed and written one at a
+    /// time.
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    pub fn export_workouts_json<W: Write>(&self, writer: W) -> Result<(),ParserError> {
+        crate::export::write_json_array(self.workouts_iter()?, writer)
+    }
+
+    /// Removes the workout at the given position (as returned by `workouts`)
+    /// from the drive. The storage records after it are moved up and their
+    /// offsets in the access table adjusted, the freed space at the end of
+    /// both files is filled with 0xff, so file sizes stay the same.
+    pub fn delete_workout(&mut self, index: usize) -> Result<(),ParserError> {
+        self.check_writable()?;
+        if index >= self.workout_count()? {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                format!("No workout at position {}.", index)).into());
+        }
+
+        self.remove_workouts(&[index])
+    }
+
+    /// Removes all workouts recorded before the given date, like
+    /// `delete_workout`, but moving the remaining records only once. Records
+    /// that can't be parsed are kept. Returns the number of removed workouts.
+    pub fn prune_before(&mut self, date: chrono::NaiveDate) -> Result<usize,ParserError> {
+        self.check_writable()?;
+
+        let entries = self.access_table_entries()?;
+        let mut storage_file = self.storage.open_file("Concept2/Logbook/LogDataStorage.bin")?;
+
+        let indices: Vec<usize> = entries.iter().enumerate()
+            .filter(|(_, entry)| matches!(Self::read_workout(&mut storage_file, entry, None),
+                Ok(workout) if workout.datetime.date() < date))
+            .map(|(i, _)| i)
+            .collect();
+        drop(storage_file);
+
+        if !indices.is_empty() {
+            self.remove_workouts(&indices)?;
+        }
+
+        Ok(indices.len())
+    }
+
+    /// Removes the workouts at the given positions and compacts the storage
+    /// file, see `delete_workout`.
+    fn remove_workouts(&mut self, indices: &[usize]) -> Result<(),ParserError> {
+        self.check_writable()?;
+
+        let entries = self.access_table_entries()?;
+        let num_entries = entries.len();
+
+        let access_table = self.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin")?;
+        let mut storage = self.read_file_bytes("Concept2/Logbook/LogDataStorage.bin")?;
+        let storage_size = storage.len();
+
+        let (removed, mut kept): (Vec<_>, Vec<_>) = entries.into_iter().enumerate()
+            .partition(|(i, _)| indices.contains(i));
+
+        // storage ranges of the removed records, from back to front, so
+        // draining one doesn't move the others
+        let mut ranges: Vec<(usize, usize)> = Vec::with_capacity(removed.len());
+        for (_, entry) in &removed {
+            let start = entry.record_offset as usize;
+            let end = start + entry.record_size as usize;
+            if end > storage.len() {
+                return Err(ParserError::UnexpectedEof.at("LogDataStorage record", start as u64));
+            }
+            ranges.push((start, end));
+        }
+        ranges.sort_unstable_by(|a, b| b.cmp(a));
+
+        for (start, end) in &ranges {
+            storage.drain(*start..*end);
+        }
+        storage.resize(storage_size, 0xff);
+
+        for (_, entry) in kept.iter_mut() {
+            let shift: usize = ranges.iter()
+                .filter(|(_, end)| *end <= entry.record_offset as usize)
+                .map(|(start, end)| end - start)
+                .sum();
+            entry.record_offset -= shift as u16;
+        }
+
+        // keep the terminating entry and anything after it untouched
+        let mut new_access_table = Vec::with_capacity(access_table.len());
+        for (_, entry) in &kept {
+            entry.write(&mut new_access_table)?;
+        }
+        new_access_table.extend_from_slice(&access_table[num_entries * LogDataAccessTableEntry::SIZE..]);
+        new_access_table.resize(access_table.len(), 0xff);
+
+        self.storage.create_file("Concept2/Logbook/LogDataStorage.bin")?.write_all(&storage)?;
+        self.storage.create_file("Concept2/Logbook/LogDataAccessTbl.bin")?.write_all(&new_access_table)?;
+
+        Ok(())
+    }
+
+    /// Adds a workout to the drive. The storage record is written after the
+    /// last existing one and a matching entry is appended to the access
+    /// table. Only single workouts can be written so far.
+    pub fn write_workout(&mut self, workout: &Workout) -> Result<(),ParserError> {
+        self.check_writable()?;
+        let entries = self.access_table_entries()?;
+        let access_table = self.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin")?;
+        let mut storage = self.read_file_bytes("Concept2/Logbook/LogDataStorage.bin")?;
+
+        let mut record = Vec::new();
+        LogDataStorageEntry::from_workout(workout)?.write(&mut record)?;
+
+        // deleting workouts leaves 0xff padding at the end of the file, so
+        // the file size doesn't say where the records end
+        let start = entries.iter()
+            .map(|e| e.record_offset as usize + e.record_size as usize)
+            .max()
+            .unwrap_or(0);
+        let end = start + record.len();
+        if start > u16::MAX as usize || record.len() > u16::MAX as usize {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                "Workout doesn't fit into the workout storage.").into());
+        }
+
+        if storage.len() < end {
+            storage.resize(end, 0xff);
+        }
+        storage[start..end].copy_from_slice(&record);
+
+        let at_entry = LogDataAccessTableEntry::new(
+            workout.workout_type,
+            workout.name,
+            workout.frames.len() as u16,
+            start as u16,
+            record.len() as u16,
+            entries.last().map(|e| e.index + 1).unwrap_or(0)
+        );
+
+        // the new entry replaces the terminating one, which moves back
+        let mut new_access_table = Vec::with_capacity(access_table.len() + LogDataAccessTableEntry::SIZE);
+        for entry in &entries {
+            entry.write(&mut new_access_table)?;
+        }
+        at_entry.write(&mut new_access_table)?;
+        new_access_table.extend_from_slice(&access_table[entries.len() * LogDataAccessTableEntry::SIZE..]);
+        new_access_table.truncate(access_table.len().max((entries.len() + 2) * LogDataAccessTableEntry::SIZE));
+
+        self.storage.create_file("Concept2/Logbook/LogDataStorage.bin")?.write_all(&storage)?;
+        self.storage.create_file("Concept2/Logbook/LogDataAccessTbl.bin")?.write_all(&new_access_table)?;
+
+        Ok(())
+    }
+
+    /// Writes all files in `Concept2/Logbook` to a zip archive, using their
+    /// paths relative to the root of the drive. Returns the number of files
+    /// written.
+    pub fn backup<W: Write + Seek>(&self, writer: W) -> Result<usize,std::io::Error> {
+        let mut zip = zip::ZipWriter::new(writer);
+        let options = zip::write::FileOptions::default();
+
+        let mut names = self.storage.list_dir("Concept2/Logbook")?;
+        names.sort();
+
+        let mut count = 0;
+        for name in names {
+            let path = format!("Concept2/Logbook/{}", name);
+
+            // subdirectories aren't used by the monitor
+            if self.storage.is_dir(&path) {
                 continue;
             }
+            let mut file = self.storage.open_file(&path)?;
+
+            zip.start_file(path.as_str(), options)?;
+            std::io::copy(&mut file, &mut zip)?;
+            count += 1;
+        }
+
+        zip.finish()?;
+        Ok(count)
+    }
+
+    /// Writes the logbook files from a backup created with `backup` back to
+    /// the drive, overwriting existing files. Entries outside of
+    /// `Concept2/Logbook` are ignored. Returns the number of files restored.
+    pub fn restore<R: Read + Seek>(&mut self, reader: R) -> Result<usize,std::io::Error> {
+        self.check_writable()?;
+        let mut zip = zip::ZipArchive::new(reader)?;
+
+        // check the whole archive before overwriting anything
+        let mut paths = Vec::new();
+        for i in 0..zip.len() {
+            let entry = zip.by_index(i)?;
+            let name = match entry.name().strip_prefix("Concept2/Logbook/") {
+                Some(name) if entry.is_file() => name,
+                _ => continue
+            };
+
+            if name.is_empty() || name.contains('/') || name.contains('\\') || name == ".." {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                    format!("Invalid path in backup: {}", entry.name())));
+            }
+
+            paths.push((i, entry.name().to_string()));
+        }
+
+        if paths.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                "Backup doesn't contain any logbook files."));
+        }
+
+        if !self.storage.exists("Concept2") {
+            self.storage.create_dir("Concept2")?;
+        }
+        if !self.storage.exists("Concept2/Logbook") {
+            self.storage.create_dir("Concept2/Logbook")?;
+        }
+
+        for (i, path) in &paths {
+            let mut entry = zip.by_index(*i)?;
+            let mut target = self.storage.create_file(path)?;
+            std::io::copy(&mut entry, &mut target)?;
+        }
+
+        Ok(paths.len())
+    }
+
+    pub fn firmwares(&self) -> Result<Vec<String>,std::io::Error> {
+        let firmware_dir = self.storage.list_dir("Concept2/Firmware");
+        if firmware_dir.is_err() {
+            return Ok(Vec::new());
+        }
 
-            if &name[(name.len()-3)..name.len()] != ".7z" {
+        let mut firmwares = Vec::new();
+
+        for name in firmware_dir.unwrap() {
+            if name.starts_with('.') || !name.ends_with(".7z") {
                 continue;
             }
 
@@ -176,78 +930,1751 @@ impl Drive {
         Ok(firmwares)
     }
 
-    pub fn clear_firmwares(&mut self) -> Result<(),std::io::Error> {
-        if let Err(_) = self.fs.root_dir().open_dir("Concept2/Firmware") {
-            self.fs.root_dir().create_dir("Concept2/Firmware")?;
+    /// Lists the firmware files and their sizes in a firmware archive stored
+    /// on the drive, as returned by `firmwares`, without extracting them.
+    pub fn firmware_contents(&self, name: &str) -> Result<Vec<(String, u64)>,std::io::Error> {
+        if name.contains('/') || name.contains('\\') || name.starts_with('.') {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                format!("Invalid firmware archive name {}.", name)));
         }
 
-        let firmware_dir = self.fs.root_dir().open_dir("Concept2/Firmware")?;
+        let mut file = self.storage.open_file(&format!("Concept2/Firmware/{}", name))?;
+        let len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let reader = sevenz_rust::SevenZReader::new(file, len, sevenz_rust::Password::empty())
+            .map_err(std::io::Error::other)?;
 
-        for fw in firmware_dir.iter() {
-            let name = fw?.file_name();
+        Ok(firmware_entries(reader.archive()))
+    }
+
+    pub fn clear_firmwares(&mut self) -> Result<(),std::io::Error> {
+        self.check_writable()?;
+        if !self.storage.exists("Concept2/Firmware") {
+            self.storage.create_dir("Concept2/Firmware")?;
+        }
 
+        for name in self.storage.list_dir("Concept2/Firmware")? {
             if name.chars().nth(0) == Some('.') {
                 continue;
             }
 
-            firmware_dir.remove(&name)?;
+            self.storage.remove(&format!("Concept2/Firmware/{}", name))?;
         }
 
         Ok(())
     }
 
-    pub fn write_firmware_callback<P: AsRef<Path>, F: Fn(u64,u64) -> ()>(
+    /// Writes the firmware files in the given archive and the archive itself
+    /// to the drive, reporting the number of bytes written to `progress`.
+    pub fn write_firmware_callback<P: AsRef<Path>>(
         &mut self,
         archive: P,
-        progress_callback: F
+        progress: &mut impl ProgressReporter
     ) -> Result<(), std::io::Error> {
-        let firmware_dir = self.fs.root_dir().open_dir("Concept2/Firmware")?;
+        self.check_writable()?;
         let archive_size: u64 = archive.as_ref().metadata()?.len();
 
-        let output = std::process::Command::new("7z")
-            .arg("l").arg("-ba").arg(archive.as_ref())
-            .output()?.stdout;
-        let output = String::from_utf8(output).unwrap();
+        let mut reader = sevenz_rust::SevenZReader::open(archive.as_ref(), sevenz_rust::Password::empty())
+            .map_err(std::io::Error::other)?;
 
-        let mut files: HashMap<String,u64> = HashMap::new();
-        let regex = regex::Regex::new(r"[^\s]+\.bin").unwrap();
+        let files_size: u64 = reader.archive().files.iter()
+            .filter(|e| is_firmware_entry(e))
+            .map(|e| e.size())
+            .sum();
 
-        for line in output.split("\n") {
-            if line.len() == 0 { break; }
+        let mut written: u64 = 0;
+        let total_size: u64 = archive_size + files_size;
+        progress.start(total_size);
 
-            let size = line.split_whitespace().nth(3).unwrap().parse().unwrap();
-            let name = regex.find(line).unwrap().as_str().to_string();
-            files.insert(name, size);
-        }
+        // path, size and checksum of every written file
+        let mut written_files: Vec<(String, u64, u64)> = Vec::new();
 
-        let mut written: u64 = 0;
-        let total_size: u64 = archive_size + files.values().sum::<u64>();
-
-        for (name, size) in &files {
-            progress_callback(written, total_size);
-            let extracted = std::process::Command::new("7z")
-                .arg("x").arg("-so").arg(archive.as_ref()).arg(name)
-                .output()?.stdout;
-            let mut cursor = Cursor::new(extracted);
-            let mut target = firmware_dir.create_file(name)?;
-            target.truncate()?;
-            std::io::copy(&mut cursor, &mut target)?;
+        let storage = &self.storage;
+        reader.for_each_entries(|entry, data| {
+            if !is_firmware_entry(entry) {
+                return Ok(true);
+            }
+
+            let path = format!("Concept2/Firmware/{}", entry.name());
+            let mut target = storage.create_file(&path)?;
+            let (size, checksum) = copy_with_progress(data, &mut target, |n| progress.update(written + n))?;
             written += size;
-        }
+            written_files.push((path, size, checksum));
+            Ok(true)
+        }).map_err(std::io::Error::other)?;
 
         let archive_name = archive.as_ref().file_name().unwrap();
+        let path = format!("Concept2/Firmware/{}", archive_name.to_str().unwrap());
         let mut f = File::open(archive.as_ref())?;
-        let mut target = firmware_dir.create_file(archive_name.to_str().unwrap())?;
-        target.truncate()?;
-        std::io::copy(&mut f, &mut target)?;
-        written += archive_size;
+        let mut target = self.storage.create_file(&path)?;
+        let (size, checksum) = copy_with_progress(&mut f, &mut target, |n| progress.update(written + n))?;
+        written_files.push((path, size, checksum));
+        drop(target);
+
+        for (path, size, checksum) in &written_files {
+            self.verify_file(path, *size, *checksum)?;
+        }
+
+        progress.finish();
+        Ok(())
+    }
+
+    /// Reads back a file written to the drive and checks that size and
+    /// checksum match what was written.
+    fn verify_file(&self, path: &str, size: u64, checksum: u64) -> Result<(),std::io::Error> {
+        let mut file = self.storage.open_file(path)?;
+        let (read_size, read_checksum) = copy_with_progress(&mut file, &mut std::io::sink(), |_| {})?;
 
-        progress_callback(written, total_size);
+        if read_size != size {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                "Verification of {} failed: wrote {} bytes, but read back {}.", path, size, read_size)));
+        }
+
+        if read_checksum != checksum {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                "Verification of {} failed: contents don't match what was written.", path)));
+        }
 
         Ok(())
     }
 
     pub fn write_firmware<P: AsRef<Path>>(&mut self, archive: P) -> Result<(),std::io::Error> {
-        self.write_firmware_callback(archive, |_,_| {})
+        self.write_firmware_callback(archive, &mut NoProgress)
+    }
+}
+
+/// Iterator over the workouts on a drive, see `Drive::workouts_iter`. Stops
+/// after the first error in the access table.
+pub struct WorkoutIter<'a> {
+    access_table_file: Box<dyn ReadSeek + 'a>,
+    storage_file: Box<dyn ReadSeek + 'a>,
+    firmware: Option<(u8, u8)>,
+    entries_read: u64,
+    done: bool
+}
+
+impl<'a> WorkoutIter<'a> {
+    fn new<A: Read + Seek + 'a, S: Read + Seek + 'a>(access_table: A, storage: S, firmware: Option<(u8, u8)>) -> Self {
+        Self {
+            access_table_file: Box::new(access_table),
+            storage_file: Box::new(storage),
+            firmware,
+            entries_read: 0,
+            done: false
+        }
+    }
+
+    /// Reads the next access table entry, returning `None` at the end of the
+    /// table.
+    fn next_entry(&mut self) -> Option<Result<LogDataAccessTableEntry,ParserError>> {
+        if self.done {
+            return None;
+        }
+
+        let offset = self.entries_read * LogDataAccessTableEntry::SIZE as u64;
+        match LogDataAccessTableEntry::read(&mut self.access_table_file) {
+            // 0x70 was only encountered at the end
+            Ok(entry) if entry.magic == 0xff || entry.magic == 0x70 => {
+                self.done = true;
+                None
+            },
+            Ok(entry) => {
+                self.entries_read += 1;
+                Some(Ok(entry))
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e.at("LogDataAccessTbl entry", offset)))
+            }
+        }
+    }
+}
+
+impl Iterator for WorkoutIter<'_> {
+    type Item = Result<Workout,ParserError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_entry()? {
+            Ok(at_entry) => Some(Drive::read_workout(&mut self.storage_file, &at_entry, self.firmware)),
+            Err(e) => Some(Err(e))
+        }
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        // skipped workouts only need their access table entry
+        for _ in 0..n {
+            if let Err(e) = self.next_entry()? {
+                return Some(Err(e));
+            }
+        }
+
+        self.next()
+    }
+}
+
+/// Parses the workouts from the contents of `LogDataAccessTbl.bin` and
+/// `LogDataStorage.bin`, e.g. when only those files were copied off a drive.
+/// Works the same as `Drive::workouts`, except that drag factors aren't
+/// decoded, since the firmware version is stored in another file.
+pub fn parse_logbook<A: Read + Seek, S: Read + Seek>(access_table: A, storage: S) -> Result<Vec<Workout>,ParserError> {
+    WorkoutIter::new(access_table, storage, None).collect()
+}
+
+/// Returns the user name as stored in `UserStatic.bin`, padded to 6 bytes.
+/// Only printable ASCII is accepted, since that's all the monitor can
+/// display.
+fn user_name_bytes(user_name: String) -> Result<Vec<u8>,std::io::Error> {
+    if !user_name.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+        return Err(std::io::Error::other("Name can only contain ASCII letters, digits and punctuation!"));
+    }
+
+    let mut name = user_name.into_bytes();
+    if name.is_empty() || name.len() > 6 {
+        return Err(std::io::Error::other("Name needs to be <= 6 characters!"));
+    }
+
+    name.resize(6, 0x00);
+    Ok(name)
+}
+
+fn is_firmware_entry(entry: &sevenz_rust::SevenZArchiveEntry) -> bool {
+    !entry.is_directory() && entry.name().ends_with(".bin")
+}
+
+/// Names and uncompressed sizes of the firmware files in an archive
+fn firmware_entries(archive: &sevenz_rust::Archive) -> Vec<(String, u64)> {
+    archive.files.iter()
+        .filter(|e| is_firmware_entry(e))
+        .map(|e| (e.name().to_string(), e.size()))
+        .collect()
+}
+
+/// Returns the names of the firmware files in a firmware archive, without
+/// extracting them.
+pub fn firmware_archive_files<P: AsRef<Path>>(archive: P) -> Result<Vec<String>,std::io::Error> {
+    let reader = sevenz_rust::SevenZReader::open(archive.as_ref(), sevenz_rust::Password::empty())
+        .map_err(std::io::Error::other)?;
+
+    Ok(firmware_entries(reader.archive()).into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// Directories removable drives get mounted in, e.g. `/media/<user>/<label>`
+/// or `/Volumes/<label>` on macOS. Mount points from `/proc/mounts` are
+/// checked as well.
+const MOUNT_ROOTS: [&str; 3] = ["/media", "/run/media", "/Volumes"];
+
+/// Filesystem types in `/proc/mounts` that can hold a Concept2 drive
+const MOUNT_FS_TYPES: [&str; 3] = ["vfat", "msdos", "fuseblk"];
+
+/// Returns true if the directory looks like the root of a mounted Concept2
+/// drive, see `Drive::is_concept2_drive`.
+pub fn is_concept2_mount<P: AsRef<Path>>(dir: P) -> bool {
+    Drive::from_mounted(dir, false)
+        .map(|drive| drive.is_concept2_drive())
+        .unwrap_or(false)
+}
+
+/// Returns the mount points of all Concept2 drives that are currently
+/// mounted, sorted by path.
+pub fn find_concept2_drives() -> Vec<PathBuf> {
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    let roots: Vec<&Path> = MOUNT_ROOTS.iter().map(Path::new).collect();
+    find_concept2_drives_in(&mounts, &roots)
+}
+
+/// Like `find_concept2_drives`, with the contents of `/proc/mounts` and the
+/// directories holding mount points given.
+fn find_concept2_drives_in(mounts: &str, roots: &[&Path]) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    for line in mounts.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() > 2 && MOUNT_FS_TYPES.contains(&fields[2]) {
+            // spaces and the like are escaped as octal, e.g. `\040`
+            candidates.push(PathBuf::from(unescape_mount_path(fields[1])));
+        }
+    }
+
+    // labels may be nested below a directory named after the user
+    for root in roots {
+        for dir in subdirectories(root) {
+            candidates.extend(subdirectories(&dir));
+            candidates.push(dir);
+        }
+    }
+
+    candidates.sort();
+    candidates.dedup();
+    candidates.retain(|dir| is_concept2_mount(dir));
+    candidates
+}
+
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect())
+        .unwrap_or_default()
+}
+
+fn unescape_mount_path(path: &str) -> String {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        let octal = tail.get(..3)
+            .and_then(|o| std::str::from_utf8(o).ok())
+            .and_then(|o| u8::from_str_radix(o, 8).ok());
+
+        match (b, octal) {
+            (b'\\', Some(c)) => {
+                bytes.push(c);
+                rest = &tail[3..];
+            },
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// MBR partition types used for FAT filesystems
+const FAT_PARTITION_TYPES: [u8; 6] = [0x01, 0x04, 0x06, 0x0b, 0x0c, 0x0e];
+
+/// Error for volumes that aren't FAT, naming the filesystem if it could be
+/// detected.
+fn unsupported_filesystem(name: Option<&str>) -> std::io::Error {
+    let found = match name {
+        Some(name) => format!("Unsupported filesystem ({})", name),
+        None => "Unrecognized filesystem".to_string()
+    };
+
+    std::io::Error::new(std::io::ErrorKind::InvalidData,
+        format!("{}, Concept2 monitors only read FAT drives. Use init to format the drive.", found))
+}
+
+/// Guesses the filesystem of the volume starting at `offset` from the
+/// signatures in its first sectors. Returns `None` for FAT or if nothing is
+/// recognized.
+fn detect_filesystem<T: Read + Seek>(stream: &mut T, offset: u64) -> Result<Option<&'static str>,std::io::Error> {
+    let mut start = [0; 2048];
+    stream.seek(SeekFrom::Start(offset))?;
+    let read = stream.read(&mut start)?;
+    stream.seek(SeekFrom::Start(0))?;
+
+    if read < 512 {
+        return Ok(None);
+    }
+
+    let name = match (&start[3..11], &start[0x438..0x43a], &start[0x400..0x402]) {
+        (b"EXFAT   ", _, _) => Some("exFAT"),
+        (b"NTFS    ", _, _) => Some("NTFS"),
+        (_, [0x53, 0xef], _) if read >= 0x43a => Some("ext2/3/4"),
+        (_, _, b"H+") | (_, _, b"HX") if read >= 0x402 => Some("HFS+"),
+        _ => None
+    };
+
+    Ok(name)
+}
+
+/// Returns the byte range of the first FAT partition if the stream starts
+/// with an MBR, or `None` if it is a bare FAT volume.
+fn fat_partition<T: Read + Seek>(stream: &mut T) -> Result<Option<(u64,u64)>,std::io::Error> {
+    let mut sector = [0; 512];
+    stream.seek(SeekFrom::Start(0))?;
+    stream.read_exact(&mut sector)?;
+    stream.seek(SeekFrom::Start(0))?;
+
+    // FAT boot sectors carry the same signature, but also the filesystem
+    // type string, either at 0x36 (FAT12/16) or at 0x52 (FAT32)
+    if sector[510..512] != [0x55, 0xaa] || &sector[0x36..0x39] == b"FAT" || &sector[0x52..0x55] == b"FAT" {
+        return Ok(None);
+    }
+
+    for entry in sector[0x1be..0x1fe].chunks(16) {
+        if !FAT_PARTITION_TYPES.contains(&entry[4]) {
+            continue;
+        }
+
+        let start = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+        let sectors = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as u64;
+        return Ok(Some((start * 512, (start + sectors) * 512)));
+    }
+
+    // name whatever the first partition contains instead
+    let name = match sector[0x1be..0x1fe].chunks(16).find(|e| e[4] != 0) {
+        Some(entry) if entry[4] == 0xee => Some("GPT partition table"),
+        Some(entry) => {
+            let start = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+            detect_filesystem(stream, start * 512)?
+        },
+        None => None
+    };
+
+    Err(unsupported_filesystem(name))
+}
+
+/// Opens the FAT filesystem in the given stream, skipping the partition
+/// table if there is one.
+fn fat_storage<T: Read + Write + Seek + 'static>(mut stream: T) -> Result<Box<dyn Storage>,std::io::Error> {
+    // fatfs' own errors for other filesystems are rather cryptic. Volumes
+    // like exFAT also carry the boot sector signature, so check before
+    // looking for a partition table.
+    if let Some(name) = detect_filesystem(&mut stream, 0)? {
+        return Err(unsupported_filesystem(Some(name)));
+    }
+
+    let partition = fat_partition(&mut stream)?;
+
+    if let Some(name) = detect_filesystem(&mut stream, partition.map(|(start, _)| start).unwrap_or(0))? {
+        return Err(unsupported_filesystem(Some(name)));
+    }
+
+    let storage = match partition {
+        Some((start, end)) => {
+            let slice = fscommon::StreamSlice::new(stream, start, end)?;
+            FatStorage::new(fscommon::BufStream::new(slice)).map(|s| Box::new(s) as Box<dyn Storage>)
+        },
+        None => FatStorage::new(fscommon::BufStream::new(stream)).map(|s| Box::new(s) as Box<dyn Storage>)
+    };
+
+    storage.map_err(|e| match e.kind() {
+        // fatfs reports invalid boot sectors as `Other`
+        std::io::ErrorKind::Other | std::io::ErrorKind::UnexpectedEof => unsupported_filesystem(None),
+        _ => e
+    })
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Copies everything from `reader` to `writer` through a fixed size buffer,
+/// calling `progress` with the number of bytes copied so far after every
+/// chunk. Returns the total number of bytes copied and their FNV-1a hash.
+fn copy_with_progress<R: Read + ?Sized, W: Write + ?Sized, F: FnMut(u64)>(
+    reader: &mut R,
+    writer: &mut W,
+    mut progress: F
+) -> Result<(u64,u64),std::io::Error> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut copied: u64 = 0;
+    let mut checksum = FNV_OFFSET_BASIS;
+
+    progress(copied);
+
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)
+        };
+
+        writer.write_all(&buffer[..n])?;
+        checksum = buffer[..n].iter().fold(checksum, |h, b| (h ^ *b as u64).wrapping_mul(FNV_PRIME));
+        copied += n as u64;
+        progress(copied);
+    }
+
+    Ok((copied, checksum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Writes `bytes` to a file in the temp directory, so it can be opened
+    /// with `Drive::new`.
+    fn temp_image(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("concept2drive-{}-{}.img", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn init_in_memory() {
+        let image = init_image("flummi");
+
+        let path = temp_image("init", &image);
+        let drive = Drive::new(&path, false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(drive.is_concept2_drive());
+        assert_eq!(drive.user().unwrap().1, "flummi");
+        assert_eq!(drive.workout_count().unwrap(), 0);
+
+        let drive = Drive::from_stream(std::io::Cursor::new(image), false).unwrap();
+        assert!(drive.is_concept2_drive());
+        assert!(drive.workouts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn gzip_image() {
+        let workouts = [
+            distance_workout(datetime(2024, 3, 10, 9, 30), 4),
+            distance_workout(datetime(2024, 3, 11, 9, 30), 2)
+        ];
+        let image = SharedImage::from_vec(init_image("flummi"));
+        let mut drive = Drive::from_stream(image.clone(), true).unwrap();
+        for workout in &workouts {
+            drive.write_workout(workout).unwrap();
+        }
+        drop(drive);
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&image.to_vec()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < IMAGE_SIZE / 10);
+
+        let path = temp_image("gzip.img.gz", &compressed);
+        let drive = Drive::from_gzip(&path);
+        let plain = temp_image("not-gzip.img.gz", &image.to_vec());
+        let error = Drive::from_gzip(&plain);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&plain).unwrap();
+
+        let mut drive = drive.unwrap();
+        assert!(drive.is_concept2_drive());
+        assert_eq!(drive.user().unwrap().1, "flummi");
+        let read = drive.workouts().unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].datetime, workouts[0].datetime);
+        assert_eq!(read[1].total_distance, 1000);
+        assert_eq!(read[1].frames, workouts[1].frames);
+
+        assert!(matches!(drive.write_workout(&workouts[0]), Err(ParserError::Io(e)) if e.kind() == std::io::ErrorKind::PermissionDenied));
+        assert!(error.is_err());
+        assert!(Drive::from_gzip("/nonexistent/drive.img.gz").is_err());
+    }
+
+    /// Offsets of fields in an access table entry
+    const WORKOUT_TYPE_OFFSET: usize = 1;
+    const RECORD_OFFSET_OFFSET: usize = 16;
+    const RECORD_SIZE_OFFSET: usize = 24;
+
+    fn two_workouts() -> Drive {
+        drive_with(&[
+            distance_workout(datetime(2024, 3, 10, 9, 30), 4),
+            distance_workout(datetime(2024, 3, 11, 9, 30), 4)
+        ])
+    }
+
+    #[test]
+    fn size_mismatch_is_skipped() {
+        let drive = two_workouts();
+        let record_size = drive.access_table_entries().unwrap()[0].record_size;
+        // as if the firmware had dropped 4 bytes of the header
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", RECORD_SIZE_OFFSET, &(record_size - 4).to_le_bytes());
+
+        let (workouts, errors) = drive.workouts_lenient().unwrap();
+        assert_eq!(workouts.len(), 1);
+        assert_eq!(workouts[0].datetime, datetime(2024, 3, 11, 9, 30));
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].inner(), ParserError::LayoutMismatch { record_size: 174, layout_size: 178 }));
+
+        let issues = drive.validate().unwrap();
+        assert!(matches!(issues[..], [Inconsistency::SizeMismatch { index: 0, record_size: 174, layout_size: 178 }]));
+    }
+
+    #[test]
+    fn type_mismatch_is_skipped() {
+        let drive = two_workouts();
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin",
+            LogDataAccessTableEntry::SIZE + WORKOUT_TYPE_OFFSET, &[WorkoutType::SingleTime as u8]);
+
+        let (workouts, errors) = drive.workouts_lenient().unwrap();
+        assert_eq!(workouts.len(), 1);
+        assert!(matches!(errors[0].inner(), ParserError::FieldMismatch { field: "workout type", access_table: 0x05, record: 0x03 }));
+
+        let issues = drive.validate().unwrap();
+        assert!(matches!(issues[..], [Inconsistency::Unparseable { index: 1, .. }]));
+    }
+
+    #[test]
+    fn lifetime_meters() {
+        let drive = drive_with(&[]);
+        assert_eq!(drive.lifetime_meters().unwrap(), 0);
+
+        patch_file(&drive, "Concept2/Logbook/UserDynamic.bin", 0x04, &[0x00, 0x12, 0xd6, 0x87]);
+        assert_eq!(drive.lifetime_meters().unwrap(), 1234567);
+
+        patch_file(&drive, "Concept2/Logbook/UserDynamic.bin", 0x00, &[0xff]);
+        assert!(drive.lifetime_meters().is_err());
+    }
+
+    #[test]
+    fn user_profile() {
+        let drive = Drive::from_stream(std::io::Cursor::new(init_image("flummi")), false).unwrap();
+        assert_eq!(drive.user_profile().unwrap(), UserProfile { user_id: 175, name: "flummi".to_string() });
+    }
+
+    #[test]
+    fn user_profile_unset() {
+        let drive = drive_with(&[]);
+        patch_file(&drive, "Concept2/Logbook/UserStatic.bin", 0x02, &[0; 6]);
+        patch_file(&drive, "Concept2/Logbook/UserStatic.bin", 0x2a, &[0; 2]);
+
+        assert_eq!(drive.user_profile().unwrap(), UserProfile { user_id: 0, name: String::new() });
+    }
+
+    /// Overwrites part of a logbook file, bypassing all checks.
+    fn patch_file(drive: &Drive, path: &str, offset: usize, bytes: &[u8]) {
+        let mut contents = drive.read_file_bytes(path).unwrap();
+        contents[offset..(offset + bytes.len())].copy_from_slice(bytes);
+        write_file(drive, path, &contents);
+    }
+
+    fn write_file(drive: &Drive, path: &str, contents: &[u8]) {
+        drive.storage.create_file(path).unwrap().write_all(contents).unwrap();
+    }
+
+    /// Position of the timestamp in a storage record
+    const TIMESTAMP_OFFSET: usize = 8;
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    fn export_json() {
+        let mut json = Vec::new();
+        drive_with(&[]).export_workouts_json(&mut json).unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&json).unwrap(), serde_json::json!([]));
+
+        let (drive, workouts) = three_workouts();
+        let mut json = Vec::new();
+        drive.export_workouts_json(&mut json).unwrap();
+        // one workout per line
+        assert_eq!(json.iter().filter(|b| **b == b'\n').count(), 5);
+
+        let parsed: Vec<serde_json::Value> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed.len(), 3);
+        for (value, workout) in parsed.iter().zip(&workouts) {
+            assert_eq!(value, &serde_json::to_value(workout).unwrap());
+            assert_eq!(value["frames"].as_array().unwrap().len(), workout.frames.len());
+        }
+
+        // stops at the first record that can't be parsed
+        let corrupt = drive.access_table_entries().unwrap()[1].record_offset as usize;
+        patch_file(&drive, "Concept2/Logbook/LogDataStorage.bin", corrupt + TIMESTAMP_OFFSET, &[0x30, 0x00, 0x09, 0x1e]);
+        let mut json = Vec::new();
+        assert!(matches!(drive.export_workouts_json(&mut json).unwrap_err().inner(), ParserError::InvalidTimestamp(_)));
+    }
+
+    #[test]
+    fn record_offset_past_end() {
+        let (drive, workouts) = three_workouts();
+        let file_len = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap().len() as u64;
+        assert!(file_len < 0xfff0);
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", 32 + RECORD_OFFSET_OFFSET, &[0xf0, 0xff]);
+
+        let error = drive.workouts().unwrap_err();
+        assert!(matches!(error.inner(), ParserError::RecordOutOfBounds { offset: 0xfff0, file_len: len } if *len == file_len), "{:?}", error);
+        assert_eq!(error.to_string(), format!("Record offset 0xfff0 is past the end of the file (0x{:x} bytes).", file_len));
+
+        // only that workout is lost
+        let (parsed, errors) = drive.workouts_lenient().unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!((&parsed[0], &parsed[1]), (&workouts[0], &workouts[2]));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].offset(), Some(0xfff0));
+
+        let results: Vec<bool> = drive.workouts_iter().unwrap().map(|w| w.is_ok()).collect();
+        assert_eq!(results, [true, false, true]);
+        assert!(matches!(drive.validate().unwrap()[..], [Inconsistency::OutOfBounds { index: 1, offset: 0xfff0, .. }]));
+
+        // right at the end of the file
+        let offset = (file_len as u16).to_le_bytes();
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", 32 + RECORD_OFFSET_OFFSET, &offset);
+        let (parsed, errors) = drive.workouts_lenient().unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert!(matches!(errors[0], ParserError::RecordOutOfBounds { offset, .. } if offset == file_len));
+    }
+
+    #[test]
+    fn corrupt_timestamp_is_reported() {
+        let drive = two_workouts();
+        let corrupt = drive.access_table_entries().unwrap()[1].record_offset as usize;
+        // month and day 0
+        patch_file(&drive, "Concept2/Logbook/LogDataStorage.bin", corrupt + TIMESTAMP_OFFSET, &[0x30, 0x00, 0x09, 0x1e]);
+
+        let (workouts, errors) = drive.workouts_lenient().unwrap();
+        assert_eq!(workouts.len(), 1);
+        assert_eq!(workouts[0].datetime, datetime(2024, 3, 10, 9, 30));
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].inner(), ParserError::InvalidTimestamp(0x3000091e)));
+        assert_eq!(errors[0].offset(), Some(corrupt as u64));
+
+        assert!(drive.workouts().is_err());
+        assert!(drive.last_workout().is_err());
+
+        let issues = drive.validate().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(issues[0], Inconsistency::Unparseable { index: 1, .. }));
+
+        let access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+        let storage = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap();
+        let error = parse_logbook(std::io::Cursor::new(access_table), std::io::Cursor::new(storage)).unwrap_err();
+        assert!(matches!(error.inner(), ParserError::InvalidTimestamp(_)));
+    }
+
+    /// Copies the `Concept2` directory tree of a drive to `dir`, as if the
+    /// drive was mounted there.
+    fn mount(drive: &Drive, dir: &Path) {
+        for sub in &["Concept2", "Concept2/DiagLog", "Concept2/Firmware", "Concept2/Logbook", "Concept2/Special"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for name in drive.storage.list_dir("Concept2/Logbook").unwrap() {
+            let path = format!("Concept2/Logbook/{}", name);
+            std::fs::write(dir.join(&path), drive.read_file_bytes(&path).unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn mounted() {
+        let dir = TempDir::new("mounted");
+        let workouts = [
+            distance_workout(datetime(2024, 3, 10, 9, 30), 4),
+            distance_workout(datetime(2024, 3, 11, 9, 30), 2)
+        ];
+        mount(&drive_with(&workouts), dir.path());
+
+        let drive = Drive::from_mounted(dir.path(), false).unwrap();
+        assert!(drive.is_concept2_drive());
+        assert_eq!(drive.user().unwrap().1, "test");
+        assert_eq!(drive.workouts().unwrap(), workouts);
+
+        // Drive::new uses the mounted directory instead of parsing it as FAT
+        assert_eq!(Drive::new(dir.path(), false).unwrap().workouts().unwrap(), workouts);
+
+        let file = dir.path().join("Concept2/Logbook/UserStatic.bin");
+        assert!(Drive::from_mounted(&file, false).is_err());
+
+        // host file names aren't limited to 8.3 names
+        for name in &["ab", "ä", "été.7z", ".hidden.7z", "PM5v33.7z"] {
+            std::fs::write(dir.path().join("Concept2/Firmware").join(name), b"").unwrap();
+        }
+        let mut firmwares = drive.firmwares().unwrap();
+        firmwares.sort();
+        assert_eq!(firmwares, ["PM5v33.7z", "été.7z"]);
+    }
+
+    #[test]
+    fn mounted_writes() {
+        let dir = TempDir::new("mounted-writes");
+        mount(&drive_with(&[]), dir.path());
+
+        let workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        let mut drive = Drive::from_mounted(dir.path(), true).unwrap();
+        drive.set_user_name("rower".to_string()).unwrap();
+        drive.write_workout(&workout).unwrap();
+        drop(drive);
+
+        let mut drive = Drive::from_mounted(dir.path(), false).unwrap();
+        assert_eq!(drive.user().unwrap().1, "rower");
+        assert_eq!(drive.workouts().unwrap(), [workout]);
+
+        let error = drive.set_user_name("other".to_string()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(drive.user().unwrap().1, "rower");
+    }
+
+    #[test]
+    fn last_workout() {
+        assert_eq!(drive_with(&[]).last_workout().unwrap(), None);
+
+        let drive = two_workouts();
+        let last = drive.last_workout().unwrap().unwrap();
+        assert_eq!(Some(&last), drive.workouts().unwrap().last());
+        assert_eq!(last.datetime, datetime(2024, 3, 11, 9, 30));
+    }
+
+    #[test]
+    fn read_file_bytes() {
+        let drive = two_workouts();
+        let access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+        assert_eq!(access_table[0], 0xf0);
+        assert_eq!(access_table[32], 0xf0);
+
+        let error = drive.read_file_bytes("Concept2/Logbook/Missing.bin").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn export_workouts() {
+        let mut csv = Vec::new();
+        two_workouts().export_workouts(&mut csv).unwrap();
+
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "Date,Type,Distance,Work Time,Rest Time,SPM,Pace,Heart Rate,Watts,Cal/Hr");
+        assert_eq!(lines[1], "2024-03-10 09:30,Distance,2000,8:00.0,,24,2:00.0,,203,997");
+    }
+
+    const FIRMWARE_ARCHIVE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/firmware.7z");
+
+    /// Size of `PM5v1.bin` in the test archive, where byte `i` is `i * 7 % 251`.
+    const FIRMWARE_SIZE: usize = 3 * 1024 * 1024;
+
+    #[test]
+    fn write_firmware() {
+        assert_eq!(firmware_archive_files(FIRMWARE_ARCHIVE).unwrap(), ["PM5v1.bin", "PM5v2.bin"]);
+
+        let mut drive = drive_with(&[]);
+        drive.clear_firmwares().unwrap();
+        drive.write_firmware(FIRMWARE_ARCHIVE).unwrap();
+
+        assert_eq!(drive.firmwares().unwrap(), ["firmware.7z"]);
+        assert_eq!(drive.firmware_contents("firmware.7z").unwrap(), [
+            ("PM5v1.bin".to_string(), FIRMWARE_SIZE as u64),
+            ("PM5v2.bin".to_string(), 5)
+        ]);
+        assert_eq!(drive.read_file_bytes("Concept2/Firmware/PM5v2.bin").unwrap(), b"small");
+        let expected: Vec<u8> = (0..FIRMWARE_SIZE).map(|i| (i * 7 % 251) as u8).collect();
+        assert!(drive.read_file_bytes("Concept2/Firmware/PM5v1.bin").unwrap() == expected);
+        // only firmware files are extracted
+        assert!(!drive.storage.exists("Concept2/Firmware/readme.txt"));
+        assert_eq!(drive.read_file_bytes("Concept2/Firmware/firmware.7z").unwrap(),
+            std::fs::read(FIRMWARE_ARCHIVE).unwrap());
+
+        drive.clear_firmwares().unwrap();
+        assert!(drive.firmwares().unwrap().is_empty());
+    }
+
+    #[test]
+    fn firmware_contents() {
+        let drive = drive_with(&[]);
+        // staged without extracting it
+        write_file(&drive, "Concept2/Firmware/staged.7z", &std::fs::read(FIRMWARE_ARCHIVE).unwrap());
+
+        assert_eq!(drive.firmware_contents("staged.7z").unwrap(), [
+            ("PM5v1.bin".to_string(), FIRMWARE_SIZE as u64),
+            ("PM5v2.bin".to_string(), 5)
+        ]);
+        assert!(!drive.storage.exists("Concept2/Firmware/PM5v1.bin"));
+
+        assert_eq!(drive.firmware_contents("missing.7z").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        for name in &["../Favorites.bin", "a\\b.7z", ".hidden.7z"] {
+            let error = drive.firmware_contents(name).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            assert_eq!(error.to_string(), format!("Invalid firmware archive name {}.", name));
+        }
+
+        write_file(&drive, "Concept2/Firmware/broken.7z", b"not an archive");
+        assert!(drive.firmware_contents("broken.7z").is_err());
+    }
+
+    /// Records every progress update and the order of all reporter calls.
+    #[derive(Default)]
+    struct Updates {
+        total: u64,
+        done: Vec<u64>,
+        finished: bool,
+        calls: Vec<&'static str>
+    }
+
+    impl ProgressReporter for Updates {
+        fn start(&mut self, total: u64) {
+            self.total = total;
+            self.calls.push("start");
+        }
+
+        fn update(&mut self, done: u64) {
+            self.done.push(done);
+            self.calls.push("update");
+        }
+
+        fn finish(&mut self) {
+            self.finished = true;
+            self.calls.push("finish");
+        }
+    }
+
+    #[test]
+    fn write_firmware_progress() {
+        let mut drive = drive_with(&[]);
+        let mut updates = Updates::default();
+        drive.write_firmware_callback(FIRMWARE_ARCHIVE, &mut updates).unwrap();
+
+        let archive_size = std::fs::metadata(FIRMWARE_ARCHIVE).unwrap().len();
+        assert_eq!(updates.total, FIRMWARE_SIZE as u64 + 5 + archive_size);
+        assert_eq!(updates.done.last(), Some(&updates.total));
+        assert!(updates.finished);
+
+        // files are copied in chunks as they are extracted, never more than
+        // the copy buffer at once
+        assert!(updates.done.windows(2).all(|w| w[0] <= w[1] && w[1] - w[0] <= 64 * 1024));
+        assert!(updates.done.len() > FIRMWARE_SIZE / (64 * 1024));
+    }
+
+    #[test]
+    fn copy_with_progress_checksum() {
+        let data: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+        let mut copy = Vec::new();
+        let mut done = Vec::new();
+        let (size, checksum) = copy_with_progress(&mut &data[..], &mut copy, |n| done.push(n)).unwrap();
+
+        assert_eq!(size, 200_000);
+        assert_eq!(copy, data);
+        assert_eq!(done, [0, 65536, 131072, 196608, 200_000]);
+
+        let (_, same) = copy_with_progress(&mut &data[..], &mut std::io::sink(), |_| {}).unwrap();
+        let (_, other) = copy_with_progress(&mut &data[1..], &mut std::io::sink(), |_| {}).unwrap();
+        assert_eq!(checksum, same);
+        assert_ne!(checksum, other);
+    }
+
+    #[test]
+    fn device_info() {
+        // from the DeviceLogInfo.bin written by init, which was taken from a
+        // PM5 drive
+        let drive = drive_with(&[]);
+        let info = drive.device_info().unwrap();
+        assert_eq!(info, DeviceInfo { serial_number: 41671817, firmware_major: 13, firmware_minor: 0 });
+        assert_eq!(info.firmware_version(), "13.00");
+        assert_eq!(drive.firmware(), Some((13, 0)));
+
+        patch_file(&drive, "Concept2/Logbook/DeviceLogInfo.bin", 0, &[0x19, 0xa1, 0x47, 0x81, 32, 5]);
+        let info = drive.device_info().unwrap();
+        assert_eq!(info.serial_number, 430000001);
+        assert_eq!(info.firmware_version(), "32.05");
+    }
+
+    fn stroke_sample(time: u32, distance: u32, pace: u16, spm: u8, heart_rate: u8) -> Vec<u8> {
+        let mut sample = Vec::new();
+        sample.extend_from_slice(&time.to_le_bytes());
+        sample.extend_from_slice(&distance.to_le_bytes());
+        sample.extend_from_slice(&pace.to_le_bytes());
+        sample.extend_from_slice(&[spm, heart_rate, 0, 0, 0, 0]);
+        sample
+    }
+
+    #[test]
+    fn stroke_data() {
+        let first = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        let mut second = distance_workout(datetime(2024, 3, 11, 9, 30), 4);
+        second.record_id = 2;
+        let mut unrecorded = distance_workout(datetime(2024, 3, 12, 9, 30), 4);
+        unrecorded.record_id = 3;
+        let drive = drive_with(&[]);
+
+        // the second workout's strokes are stored first
+        let mut access_table = vec![0xf0, 0, 0x00, 0x02, 0x19, 0xa1, 0x47, 0x80, 0, 0, 0, 0, 1, 0, 0, 0];
+        access_table.extend_from_slice(&[0xf0, 0, 0x00, 0x01, 0x19, 0xa1, 0x47, 0x80, 16, 0, 0, 0, 2, 0, 0, 0]);
+        access_table.extend_from_slice(&[0xff; 16]);
+        write_file(&drive, "Concept2/Logbook/StrokeDataAccessTbl.bin", &access_table);
+
+        let mut storage = stroke_sample(25, 98, 1230, 22, 0);
+        storage.extend(stroke_sample(25, 102, 1180, 24, 140));
+        storage.extend(stroke_sample(51, 205, 1195, 25, 142));
+        write_file(&drive, "Concept2/Logbook/StrokeDataStorage.bin", &storage);
+
+        let strokes = drive.stroke_data(&first).unwrap();
+        assert_eq!(strokes.len(), 2);
+        assert_eq!(strokes[0].time, std::time::Duration::from_millis(2500));
+        assert_eq!(strokes[0].distance, 10);
+        assert_eq!(strokes[0].pace, std::time::Duration::from_secs(118));
+        assert_eq!(strokes[0].spm, 24);
+        assert_eq!(strokes[0].heart_rate, Some(140));
+        assert_eq!(strokes[1].distance, 20);
+
+        let strokes = drive.stroke_data(&second).unwrap();
+        assert_eq!(strokes.len(), 1);
+        assert_eq!(strokes[0].heart_rate, None);
+
+        assert!(drive.stroke_data(&unrecorded).unwrap().is_empty());
+    }
+
+    #[test]
+    fn from_stream() {
+        let image = SharedImage::from_vec(init_image("flummi"));
+
+        let workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        let mut drive = Drive::from_stream(image.clone(), true).unwrap();
+        drive.write_workout(&workout).unwrap();
+        drop(drive);
+
+        let mut drive = Drive::from_stream(image.clone(), false).unwrap();
+        assert_eq!(drive.user().unwrap().1, "flummi");
+        assert_eq!(drive.workouts().unwrap(), [workout]);
+        assert!(drive.write_workout(&distance_workout(datetime(2024, 3, 11, 9, 30), 4)).is_err());
+
+        assert!(Drive::from_stream(std::io::Cursor::new(vec![0; IMAGE_SIZE]), false).is_err());
+    }
+
+    /// Returns an image with an MBR and a single partition of the given type
+    /// 1 MiB into the disk, containing `volume`.
+    fn partitioned(partition_type: u8, volume: &[u8]) -> Vec<u8> {
+        const START: usize = 2048;
+
+        let mut image = vec![0; START * 512];
+        image[0x1be + 4] = partition_type;
+        image[0x1be + 8..0x1be + 12].copy_from_slice(&(START as u32).to_le_bytes());
+        image[0x1be + 12..0x1be + 16].copy_from_slice(&((volume.len() / 512) as u32).to_le_bytes());
+        image[510..512].copy_from_slice(&[0x55, 0xaa]);
+        image.extend_from_slice(volume);
+        image
+    }
+
+    #[test]
+    fn mbr_partition() {
+        let image = partitioned(0x06, &init_image("flummi"));
+        let drive = Drive::from_stream(std::io::Cursor::new(image), false).unwrap();
+        assert!(drive.is_concept2_drive());
+        assert_eq!(drive.user().unwrap().1, "flummi");
+
+        // a bare volume isn't mistaken for a partition table
+        let mut image = std::io::Cursor::new(init_image("flummi"));
+        assert_eq!(fat_partition(&mut image).unwrap(), None);
+    }
+
+    #[test]
+    fn unsupported_partitions() {
+        let error = |image: Vec<u8>| Drive::from_stream(std::io::Cursor::new(image), false).err().unwrap().to_string();
+
+        let mut exfat = vec![0; 1024 * 1024];
+        exfat[3..11].copy_from_slice(b"EXFAT   ");
+        exfat[510..512].copy_from_slice(&[0x55, 0xaa]);
+        assert!(error(exfat.clone()).starts_with("Unsupported filesystem (exFAT)"));
+        assert!(error(partitioned(0x07, &exfat)).starts_with("Unsupported filesystem (exFAT)"));
+        assert!(error(partitioned(0xee, &exfat)).starts_with("Unsupported filesystem (GPT partition table)"));
+        // a FAT partition type, but no FAT volume in it
+        assert!(error(partitioned(0x0c, &[0; 1024 * 1024])).starts_with("Unrecognized filesystem"));
+    }
+
+    #[test]
+    fn bad_magic_is_located() {
+        let drive = two_workouts();
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", 32, &[0x12]);
+
+        let error = drive.workouts().unwrap_err();
+        assert_eq!(error.offset(), Some(0x20));
+        assert!(matches!(error.inner(), ParserError::BadMagic { found: 0x12, offset: Some(0x20) }));
+        let message = error.to_string();
+        assert!(message.contains("LogDataAccessTbl entry at 0x20"), "{}", message);
+        assert!(message.contains("Invalid magic 0x12 at 0x20"), "{}", message);
+    }
+
+    #[test]
+    fn verify_firmware() {
+        let drive = drive_with(&[]);
+        let path = "Concept2/Firmware/PM5v2.bin";
+        write_file(&drive, path, b"small");
+        let (size, checksum) = copy_with_progress(&mut &b"small"[..], &mut std::io::sink(), |_| {}).unwrap();
+        drive.verify_file(path, size, checksum).unwrap();
+
+        write_file(&drive, path, b"sma");
+        let error = drive.verify_file(path, size, checksum).unwrap_err().to_string();
+        assert_eq!(error, "Verification of Concept2/Firmware/PM5v2.bin failed: wrote 5 bytes, but read back 3.");
+
+        write_file(&drive, path, b"smell");
+        let error = drive.verify_file(path, size, checksum).unwrap_err().to_string();
+        assert!(error.ends_with("contents don't match what was written."), "{}", error);
+    }
+
+    /// Offsets and sizes of the records in the access table
+    fn record_ranges(drive: &Drive) -> Vec<(u16, u16)> {
+        drive.access_table_entries().unwrap().iter().map(|e| (e.record_offset, e.record_size)).collect()
+    }
+
+    fn three_workouts() -> (Drive, Vec<Workout>) {
+        let workouts = vec![
+            distance_workout(datetime(2024, 3, 10, 9, 30), 4),
+            distance_workout(datetime(2024, 3, 11, 9, 30), 2),
+            distance_workout(datetime(2024, 3, 12, 9, 30), 3)
+        ];
+        (drive_with(&workouts), workouts)
+    }
+
+    #[test]
+    fn validate() {
+        let (drive, _) = three_workouts();
+        assert!(drive.validate().unwrap().is_empty());
+
+        // the second record has 2 splits, not 1
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", 32 + RECORD_SIZE_OFFSET, &82u16.to_le_bytes());
+        let issues = drive.validate().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(issues[0], Inconsistency::SizeMismatch { index: 1, record_size: 82, layout_size: 114 }));
+        assert_eq!(issues[0].to_string(), "Access table lists 82 bytes, but the record has 114.");
+
+        // the third entry references the second record as well
+        let (drive, _) = three_workouts();
+        let mut access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+        access_table.copy_within(32..(32 + RECORD_SIZE_OFFSET + 2), 64);
+        write_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", &access_table);
+        let issues = drive.validate().unwrap();
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(issues[0], Inconsistency::Overlap { index: 2, offset: 178, other: 1, other_offset: 178 }));
+        assert_eq!(issues[0].to_string(), "Record at 0xb2 overlaps the record at 0xb2.");
+        // both still parse
+        assert_eq!(drive.workouts().unwrap().len(), 3);
+
+        // issues are reported per workout, ordered by index
+        let (drive, _) = three_workouts();
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", RECORD_SIZE_OFFSET, &200u16.to_le_bytes());
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", 64 + RECORD_OFFSET_OFFSET, &250u16.to_le_bytes());
+        let issues = drive.validate().unwrap();
+        assert_eq!(issues.iter().map(|i| i.index()).collect::<Vec<_>>(), [0, 1, 2, 2]);
+        assert!(matches!(issues[0], Inconsistency::SizeMismatch { index: 0, record_size: 200, layout_size: 178 }));
+        assert!(matches!(issues[1], Inconsistency::Overlap { index: 1, other: 0, .. }));
+        assert!(issues[2..].iter().any(|i| matches!(i, Inconsistency::Overlap { index: 2, offset: 250, .. })));
+        assert!(issues[2..].iter().any(|i| matches!(i, Inconsistency::Unparseable { index: 2, .. })));
+
+        // nothing was changed
+        assert_eq!(drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap()[64 + RECORD_OFFSET_OFFSET], 250);
+    }
+
+    #[test]
+    fn delete_workout() {
+        let (mut drive, mut workouts) = three_workouts();
+        assert_eq!(record_ranges(&drive), [(0, 178), (178, 114), (292, 146)]);
+        let storage_size = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap().len();
+        let access_table_size = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap().len();
+
+        drive.delete_workout(1).unwrap();
+        workouts.remove(1);
+        assert_eq!(record_ranges(&drive), [(0, 178), (178, 146)]);
+        assert_eq!(drive.workouts().unwrap(), workouts);
+        assert!(drive.validate().unwrap().is_empty());
+
+        // file sizes stay the same, the freed space is padded
+        let storage = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap();
+        assert_eq!(storage.len(), storage_size);
+        assert!(storage[324..].iter().all(|b| *b == 0xff));
+        assert_eq!(drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap().len(), access_table_size);
+
+        drive.delete_workout(0).unwrap();
+        workouts.remove(0);
+        assert_eq!(record_ranges(&drive), [(0, 146)]);
+        assert_eq!(drive.workouts().unwrap(), workouts);
+
+        assert!(drive.delete_workout(1).is_err());
+        drive.delete_workout(0).unwrap();
+        assert!(drive.workouts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn prune_before() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 11).unwrap();
+        // not in chronological order, e.g. after restoring a backup
+        let workouts = vec![
+            distance_workout(datetime(2024, 3, 8, 9, 30), 4),
+            distance_workout(datetime(2024, 3, 12, 9, 30), 2),
+            distance_workout(datetime(2024, 3, 9, 9, 30), 1),
+            distance_workout(datetime(2024, 3, 14, 9, 30), 3),
+            distance_workout(datetime(2024, 3, 10, 23, 59), 2)
+        ];
+        let mut drive = drive_with(&workouts);
+        assert_eq!(record_ranges(&drive), [(0, 178), (178, 114), (292, 82), (374, 146), (520, 114)]);
+        let storage_size = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap().len();
+        let access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+
+        assert_eq!(drive.prune_before(date).unwrap(), 3);
+        assert_eq!(record_ranges(&drive), [(0, 114), (114, 146)]);
+        let remaining = drive.workouts().unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert_eq!((&remaining[0], &remaining[1]), (&workouts[1], &workouts[3]));
+        assert!(drive.validate().unwrap().is_empty());
+
+        let storage = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap();
+        assert_eq!(storage.len(), storage_size);
+        assert!(storage[260..].iter().all(|b| *b == 0xff));
+        let new_access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+        assert_eq!(new_access_table.len(), access_table.len());
+        // the end of the table moved up with the entries
+        assert_eq!(new_access_table[64..96], access_table[160..192]);
+        assert!(new_access_table[96..].iter().all(|b| *b == 0xff));
+
+        // nothing left to prune, nothing is written
+        let image = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap();
+        assert_eq!(drive.prune_before(date).unwrap(), 0);
+        assert_eq!(drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap(), image);
+
+        // new workouts go right after the remaining ones
+        drive.write_workout(&workouts[0]).unwrap();
+        assert_eq!(record_ranges(&drive), [(0, 114), (114, 146), (260, 178)]);
+        assert_eq!(drive.workouts().unwrap()[2], workouts[0]);
+
+        assert_eq!(drive.prune_before(chrono::NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()).unwrap(), 3);
+        assert!(drive.workouts().unwrap().is_empty());
+        assert!(drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap().iter().all(|b| *b == 0xff));
+    }
+
+    #[test]
+    fn prune_keeps_unparseable_records() {
+        let workouts = vec![
+            distance_workout(datetime(2024, 3, 8, 9, 30), 4),
+            distance_workout(datetime(2024, 3, 9, 9, 30), 1),
+            distance_workout(datetime(2024, 3, 12, 9, 30), 2)
+        ];
+        let mut drive = drive_with(&workouts);
+        // month and day 0
+        patch_file(&drive, "Concept2/Logbook/LogDataStorage.bin", 178 + TIMESTAMP_OFFSET, &[0x30, 0x00, 0x09, 0x1e]);
+        let corrupt = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap()[178..260].to_vec();
+
+        assert_eq!(drive.prune_before(chrono::NaiveDate::from_ymd_opt(2024, 3, 11).unwrap()).unwrap(), 1);
+        assert_eq!(record_ranges(&drive), [(0, 82), (82, 114)]);
+        assert_eq!(drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap()[..82], corrupt[..]);
+
+        let (parsed, errors) = drive.workouts_lenient().unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0], workouts[2]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].offset(), Some(0));
+    }
+
+    #[test]
+    fn delete_workout_read_only() {
+        let image = SharedImage::from_vec(init_image("test"));
+        let mut drive = Drive::from_stream(image.clone(), true).unwrap();
+        drive.write_workout(&distance_workout(datetime(2024, 3, 10, 9, 30), 4)).unwrap();
+        drop(drive);
+
+        let mut drive = Drive::from_stream(image, false).unwrap();
+        assert!(matches!(drive.delete_workout(0), Err(ParserError::Io(e)) if e.kind() == std::io::ErrorKind::PermissionDenied));
+        assert_eq!(drive.workout_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn write_workout_round_trip() {
+        let mut timed = distance_workout(datetime(2024, 3, 11, 18, 0), 3);
+        timed.workout_type = WorkoutType::SingleTime;
+        for (frame, distance) in timed.frames.iter_mut().zip(&[510, 505, 498]) {
+            frame.distance = *distance;
+        }
+        timed.total_distance = 1513;
+        timed.record_id = 2;
+        timed.spm = Some(26);
+        let workouts = [distance_workout(datetime(2024, 3, 10, 9, 30), 4), timed];
+
+        let image = SharedImage::from_vec(init_image("test"));
+        let mut drive = Drive::from_stream(image.clone(), true).unwrap();
+        for workout in &workouts {
+            drive.write_workout(workout).unwrap();
+        }
+        drop(drive);
+
+        let drive = Drive::from_stream(image, false).unwrap();
+        assert_eq!(drive.workouts().unwrap(), workouts);
+        assert_eq!(record_ranges(&drive), [(0, 178), (178, 146)]);
+        assert_eq!(drive.access_table_entries().unwrap().iter().map(|e| e.index).collect::<Vec<_>>(), [0, 1]);
+        assert!(drive.validate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn write_workout_after_delete() {
+        let (mut drive, mut workouts) = three_workouts();
+        drive.delete_workout(2).unwrap();
+        workouts.remove(2);
+
+        // the record goes right after the last one, not at the end of the file
+        let workout = distance_workout(datetime(2024, 3, 13, 9, 30), 1);
+        drive.write_workout(&workout).unwrap();
+        workouts.push(workout);
+        assert_eq!(record_ranges(&drive), [(0, 178), (178, 114), (292, 82)]);
+        assert_eq!(drive.workouts().unwrap(), workouts);
+    }
+
+    #[test]
+    fn write_workout_rejects_unsupported() {
+        let mut drive = drive_with(&[]);
+
+        let mut interval = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        interval.workout_type = WorkoutType::DistanceInterval;
+        assert!(drive.write_workout(&interval).is_err());
+
+        // the totals don't match the splits
+        let mut inconsistent = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        inconsistent.total_distance = 3000;
+        assert!(drive.write_workout(&inconsistent).is_err());
+
+        assert_eq!(drive.workout_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn set_user_name() {
+        let (mut drive, workouts) = three_workouts();
+        let profile = drive.user_profile().unwrap();
+
+        drive.set_user_name("Erg".to_string()).unwrap();
+        assert_eq!(drive.user().unwrap(), (profile.user_id, "Erg".to_string()));
+        assert_eq!(drive.workouts().unwrap(), workouts);
+
+        // a shorter name doesn't leave parts of the old one behind
+        drive.set_user_name("rowing".to_string()).unwrap();
+        drive.set_user_name("r".to_string()).unwrap();
+        assert_eq!(drive.user().unwrap().1, "r");
+
+        assert!(drive.set_user_name("flummi2".to_string()).is_err());
+        assert!(drive.set_user_name(String::new()).is_err());
+        assert!(drive.set_user_name("Jörg".to_string()).is_err());
+        assert_eq!(drive.user().unwrap().1, "r");
+    }
+
+    #[test]
+    fn workout_name() {
+        let mut named = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        named.name = WorkoutName::from_bytes([2, 5]);
+        let drive = drive_with(&[named, distance_workout(datetime(2024, 3, 11, 9, 30), 4)]);
+
+        let workouts = drive.workouts().unwrap();
+        assert_eq!(workouts[0].name, Some(WorkoutName { list: 2, index: 5 }));
+        assert_eq!(workouts[1].name, None);
+    }
+
+    #[test]
+    fn progress_reporter_calls() {
+        let mut drive = drive_with(&[]);
+        let mut updates = Updates::default();
+        drive.write_firmware_callback(FIRMWARE_ARCHIVE, &mut updates).unwrap();
+
+        assert_eq!(updates.calls.first(), Some(&"start"));
+        assert_eq!(updates.calls.last(), Some(&"finish"));
+        assert_eq!(updates.calls.iter().filter(|c| **c == "start").count(), 1);
+        assert_eq!(updates.calls.iter().filter(|c| **c == "finish").count(), 1);
+
+        // nothing is reported if the firmware can't be written at all
+        let mut drive = Drive::from_stream(SharedImage::from_vec(init_image("test")), false).unwrap();
+        let mut updates = Updates::default();
+        assert!(drive.write_firmware_callback(FIRMWARE_ARCHIVE, &mut updates).is_err());
+        assert!(updates.calls.is_empty());
+
+        let mut drive = drive_with(&[]);
+        let mut updates = Updates::default();
+        assert!(drive.write_firmware_callback("testdata/missing.7z", &mut updates).is_err());
+        assert!(updates.calls.is_empty());
+    }
+
+    /// Reader that counts the bytes read from it.
+    struct Counting<R>(R, Rc<Cell<usize>>);
+
+    impl<R: Read> Read for Counting<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.read(buf)?;
+            self.1.set(self.1.get() + n);
+            Ok(n)
+        }
+    }
+
+    impl<R: Seek> Seek for Counting<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn workouts_iter_is_lazy() {
+        let (drive, workouts) = three_workouts();
+        let access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+        let storage = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap();
+
+        let iter = |storage_read: &Rc<Cell<usize>>| WorkoutIter::new(
+            std::io::Cursor::new(access_table.clone()),
+            Counting(std::io::Cursor::new(storage.clone()), storage_read.clone()),
+            None
+        );
+
+        // nothing is read before the iterator is advanced
+        let storage_read = Rc::new(Cell::new(0));
+        let mut lazy = iter(&storage_read);
+        assert_eq!(storage_read.get(), 0);
+
+        // only the record of the first workout is parsed
+        assert_eq!(lazy.next().unwrap().unwrap(), workouts[0]);
+        assert_eq!(storage_read.get(), 178);
+
+        // skipped workouts only need their access table entry
+        let storage_read = Rc::new(Cell::new(0));
+        assert_eq!(iter(&storage_read).nth(2).unwrap().unwrap(), workouts[2]);
+        assert_eq!(storage_read.get(), 146);
+
+        let storage_read = Rc::new(Cell::new(0));
+        assert_eq!(iter(&storage_read).count(), 3);
+        assert_eq!(storage_read.get(), 178 + 114 + 146);
+    }
+
+    /// Formats an image with an empty FAT filesystem, without any of the
+    /// Concept2 folders.
+    fn plain_fat_image() -> Vec<u8> {
+        let mut image = std::io::Cursor::new(vec![0; IMAGE_SIZE]);
+        fatfs::format_volume(&mut image, fatfs::FormatVolumeOptions::new()).unwrap();
+        image.into_inner()
+    }
+
+    #[test]
+    fn backup_restore() {
+        let (mut drive, workouts) = three_workouts();
+        drive.set_user_name("Erg".to_string()).unwrap();
+
+        let mut backup = std::io::Cursor::new(Vec::new());
+        let count = drive.backup(&mut backup).unwrap();
+        assert_eq!(count, drive.storage.list_dir("Concept2/Logbook").unwrap().len());
+
+        let mut fresh = drive_with(&[]);
+        backup.set_position(0);
+        assert_eq!(fresh.restore(&mut backup).unwrap(), count);
+        assert_eq!(fresh.workouts().unwrap(), workouts);
+        assert_eq!(fresh.user().unwrap().1, "Erg");
+
+        // the logbook folder is created if the drive doesn't have one yet
+        let mut empty = Drive::from_stream(std::io::Cursor::new(plain_fat_image()), true).unwrap();
+        backup.set_position(0);
+        assert_eq!(empty.restore(&mut backup).unwrap(), count);
+        assert!(empty.is_concept2_drive());
+        assert_eq!(empty.workouts().unwrap(), workouts);
+
+        let mut read_only = Drive::from_stream(std::io::Cursor::new(init_image("test")), false).unwrap();
+        backup.set_position(0);
+        assert_eq!(read_only.restore(&mut backup).unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn backup_mounted() {
+        let dir = TempDir::new("backup-mounted");
+        let (drive, workouts) = three_workouts();
+        mount(&drive, dir.path());
+        let logbook = dir.path().join("Concept2/Logbook");
+        std::fs::create_dir(logbook.join("sub")).unwrap();
+
+        // subdirectories are skipped
+        let mounted = Drive::from_mounted(dir.path(), false).unwrap();
+        let mut backup = std::io::Cursor::new(Vec::new());
+        let count = mounted.backup(&mut backup).unwrap();
+        assert_eq!(count, drive.storage.list_dir("Concept2/Logbook").unwrap().len());
+
+        let mut fresh = drive_with(&[]);
+        backup.set_position(0);
+        assert_eq!(fresh.restore(&mut backup).unwrap(), count);
+        assert_eq!(fresh.workouts().unwrap(), workouts);
+
+        // files that can't be read fail the backup instead of being left out
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(logbook.join("missing.bin"), logbook.join("Broken.bin")).unwrap();
+            let error = mounted.backup(std::io::Cursor::new(Vec::new())).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        }
+    }
+
+    /// Creates a zip archive with the given entries.
+    fn zip_archive(entries: &[(&str, &[u8])]) -> std::io::Cursor<Vec<u8>> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        let mut archive = zip.finish().unwrap();
+        archive.set_position(0);
+        archive
+    }
+
+    #[test]
+    fn restore_rejects_invalid_backups() {
+        let (mut drive, workouts) = three_workouts();
+
+        let error = drive.restore(zip_archive(&[("readme.txt", b"hello")])).unwrap_err();
+        assert_eq!(error.to_string(), "Backup doesn't contain any logbook files.");
+
+        // nothing is written if any of the paths is invalid
+        let error = drive.restore(zip_archive(&[
+            ("Concept2/Logbook/UserStatic.bin", b"broken"),
+            ("Concept2/Logbook/../Firmware/PM5v1.bin", b"broken"),
+        ])).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid path in backup: Concept2/Logbook/../Firmware/PM5v1.bin");
+        assert_eq!(drive.user().unwrap().1, "test");
+        assert_eq!(drive.workouts().unwrap(), workouts);
+    }
+
+    #[test]
+    fn check_concept2_drive() {
+        let drive = Drive::from_stream(std::io::Cursor::new(plain_fat_image()), true).unwrap();
+        assert!(!drive.is_concept2_drive());
+        assert_eq!(drive.check_concept2_drive().unwrap_err().to_string(),
+            "Not a Concept2 drive, Concept2/Logbook is missing.");
+
+        drive.storage.create_dir("Concept2").unwrap();
+        drive.storage.create_dir("Concept2/Logbook").unwrap();
+        let error = drive.check_concept2_drive().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), "Not a Concept2 drive, Concept2/Logbook/LogDataAccessTbl.bin is missing.");
+
+        // the drive is usable with just the logbook files
+        for name in &LOGBOOK_FILES {
+            drive.storage.create_file(&format!("Concept2/Logbook/{}", name)).unwrap();
+        }
+        assert!(drive.is_concept2_drive());
+
+        let dir = TempDir::new("check-mount");
+        assert!(!is_concept2_mount(dir.path()));
+        mount(&drive_with(&[]), dir.path());
+        assert!(is_concept2_mount(dir.path()));
+        std::fs::remove_file(dir.path().join("Concept2/Logbook/UserStatic.bin")).unwrap();
+        assert!(!is_concept2_mount(dir.path()));
+    }
+
+    #[test]
+    fn favorites() {
+        // the Favorites.bin written by init, which was taken from a PM5 drive
+        let drive = drive_with(&[]);
+        let favorite = |workout_type, target, split| Favorite {
+            type_code: workout_type as u8,
+            workout_type: Some(workout_type),
+            target,
+            split
+        };
+        assert_eq!(drive.favorites().unwrap(), [
+            favorite(WorkoutType::TimeInterval, 30, 0),
+            favorite(WorkoutType::VariableInterval, 60, 0),
+            favorite(WorkoutType::VariableInterval, 2000, 0),
+            favorite(WorkoutType::VariableInterval, 100, 0),
+            favorite(WorkoutType::SingleDistance, 42195, 2000),
+        ]);
+
+        // entries can fill the whole file, unknown types are kept
+        let path = "Concept2/Logbook/Favorites.bin";
+        let mut contents = drive.read_file_bytes(path).unwrap();
+        contents.truncate(FavoritesEntry::HEADER_SIZE as usize + FavoritesEntry::SIZE as usize);
+        contents[0x81] = 0x42;
+        write_file(&drive, path, &contents);
+        assert_eq!(drive.favorites().unwrap(), [
+            Favorite { type_code: 0x42, workout_type: None, target: 30, split: 0 }
+        ]);
+
+        contents[0x80] = 0x12;
+        write_file(&drive, path, &contents);
+        assert_eq!(drive.favorites().unwrap_err().to_string(),
+            "Error encountered during parsing of Favorites entry at 0x80: Invalid magic 0x12 at 0x80.");
+    }
+
+    #[test]
+    fn interval_rest_time() {
+        let drive = drive_with(&[]);
+
+        // 1:30 rest programmed on the monitor, the record says 1:00
+        let mut access_table = vec![0xff; 64];
+        access_table[..32].fill(0);
+        access_table[..4].copy_from_slice(&[0xf0, 0x06, 90, 0x00]);
+        access_table[12] = 3;
+        access_table[24] = TIME_INTERVAL_RECORD.len() as u8;
+        write_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", &access_table);
+        write_file(&drive, "Concept2/Logbook/LogDataStorage.bin", &TIME_INTERVAL_RECORD);
+
+        let workouts = drive.workouts().unwrap();
+        assert_eq!(workouts.len(), 1);
+        assert_eq!(workouts[0].workout_type, WorkoutType::TimeInterval);
+        assert_eq!(workouts[0].rest_interval, Some(std::time::Duration::from_secs(90)));
+        // the rests that were actually taken are still those of the record
+        assert_eq!(workouts[0].frames[0].rest_duration, Some(std::time::Duration::from_secs(60)));
+
+        // single workouts have no rest interval, whatever the entry says
+        let drive = drive_with(&[distance_workout(datetime(2024, 3, 10, 9, 30), 4)]);
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", 2, &[90, 0]);
+        assert_eq!(drive.workouts().unwrap()[0].rest_interval, None);
+    }
+
+    #[test]
+    fn detect_filesystems() {
+        let detect = |patch: &dyn Fn(&mut Vec<u8>)| {
+            let mut image = vec![0; 4096];
+            patch(&mut image);
+            detect_filesystem(&mut std::io::Cursor::new(image), 0).unwrap()
+        };
+
+        assert_eq!(detect(&|i| i[3..11].copy_from_slice(b"NTFS    ")), Some("NTFS"));
+        assert_eq!(detect(&|i| i[0x438..0x43a].copy_from_slice(&[0x53, 0xef])), Some("ext2/3/4"));
+        assert_eq!(detect(&|i| i[0x400..0x402].copy_from_slice(b"H+")), Some("HFS+"));
+        assert_eq!(detect(&|_| ()), None);
+        assert_eq!(detect_filesystem(&mut std::io::Cursor::new(init_image("test")), 0).unwrap(), None);
+        // too short to tell
+        assert_eq!(detect_filesystem(&mut std::io::Cursor::new(vec![0; 100]), 0).unwrap(), None);
+
+        // opening the image the usual way gives the same explanation
+        let mut ntfs = vec![0; 1024 * 1024];
+        ntfs[3..11].copy_from_slice(b"NTFS    ");
+        ntfs[510..512].copy_from_slice(&[0x55, 0xaa]);
+        let path = temp_image("ntfs", &ntfs);
+        let error = Drive::new(&path, false).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(),
+            "Unsupported filesystem (NTFS), Concept2 monitors only read FAT drives. Use init to format the drive.");
+    }
+
+    #[test]
+    fn find_drives() {
+        let dir = TempDir::new("find-drives");
+        let path = |p: &str| dir.path().join(p);
+        let drive = drive_with(&[]);
+
+        // labels directly below the root and below a user directory
+        mount(&drive, &path("media/flummi/PM5"));
+        mount(&drive, &path("Volumes/C2"));
+        std::fs::create_dir_all(path("media/flummi/USB STICK")).unwrap();
+        // only the logbook folder, but none of its files
+        std::fs::create_dir_all(path("Volumes/Broken/Concept2/Logbook")).unwrap();
+        // mounted elsewhere, only found through the mount table
+        mount(&drive, &path("mnt/rowing erg"));
+        mount(&drive, &path("srv/ext4"));
+
+        let mounts = format!(
+            "/dev/sda1 / ext4 rw 0 0\n/dev/sdb1 {} vfat rw 0 0\n/dev/sdc1 {} ext4 rw 0 0\nbroken line\n",
+            path("mnt/rowing erg").display().to_string().replace(' ', "\\040"),
+            path("srv/ext4").display()
+        );
+        let roots = [path("media"), path("Volumes"), path("missing")];
+        let roots: Vec<&Path> = roots.iter().map(|r| r.as_path()).collect();
+
+        assert_eq!(find_concept2_drives_in(&mounts, &roots), [
+            path("Volumes/C2"),
+            path("media/flummi/PM5"),
+            path("mnt/rowing erg"),
+        ]);
+        assert!(find_concept2_drives_in("", &[]).is_empty());
+    }
+
+    #[test]
+    fn user_name_encoding() {
+        assert_eq!(user_name_bytes("Erg".to_string()).unwrap(), b"Erg\0\0\0");
+        assert_eq!(user_name_bytes("C2 Fan".to_string()).unwrap(), b"C2 Fan");
+        // fewer characters than bytes
+        assert!(user_name_bytes("José".to_string()).is_err());
+        assert!(user_name_bytes("Müller".to_string()).is_err());
+        assert!(user_name_bytes("tab\t".to_string()).is_err());
+        assert!(user_name_bytes("rowing".to_string() + "!").is_err());
+
+        let error = Drive::init_stream(SharedImage::new(IMAGE_SIZE), "José".to_string()).err().unwrap();
+        assert_eq!(error.to_string(), "Name can only contain ASCII letters, digits and punctuation!");
+
+        // trailing nulls are cut off, anything else is decoded lossily
+        let drive = drive_with(&[]);
+        patch_file(&drive, "Concept2/Logbook/UserStatic.bin", 2, b"Li\0\0\0\0");
+        assert_eq!(drive.user().unwrap().1, "Li");
+        patch_file(&drive, "Concept2/Logbook/UserStatic.bin", 2, &[b'L', 0xff, b'i', 0, 0, 0]);
+        assert_eq!(drive.user().unwrap().1, "L\u{fffd}i");
+    }
+
+    #[test]
+    fn parse_logbook_files() {
+        let (drive, workouts) = three_workouts();
+        let access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+        let storage = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap();
+        assert_eq!(parse_logbook(std::io::Cursor::new(&access_table), std::io::Cursor::new(&storage)).unwrap(), workouts);
+
+        // files put together by hand, without any drive
+        let mut access_table = vec![0; 32];
+        access_table[..2].copy_from_slice(&[0xf0, 0x06]);
+        access_table[24] = TIME_INTERVAL_RECORD.len() as u8;
+        access_table.extend_from_slice(&[0xff; 32]);
+        let parsed = parse_logbook(std::io::Cursor::new(&access_table), std::io::Cursor::new(&TIME_INTERVAL_RECORD[..])).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].workout_type, WorkoutType::TimeInterval);
+        assert_eq!(parsed[0].total_distance, 915);
+
+        // the table has to end with a marker, like the ones on the drive
+        let mut truncated = access_table.clone();
+        truncated.truncate(32);
+        let error = parse_logbook(std::io::Cursor::new(&truncated), std::io::Cursor::new(&TIME_INTERVAL_RECORD[..])).unwrap_err();
+        assert_eq!(error.offset(), Some(32));
+        assert!(matches!(error.inner(), ParserError::UnexpectedEof));
+
+        let empty = parse_logbook(std::io::Cursor::new(vec![0xff; 64]), std::io::Cursor::new(Vec::new())).unwrap();
+        assert!(empty.is_empty());
+
+        // the entry points past the end of the storage
+        let error = parse_logbook(std::io::Cursor::new(&access_table), std::io::Cursor::new(Vec::new())).unwrap_err();
+        assert!(matches!(error, ParserError::RecordOutOfBounds { .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn unsupported_stroke_layout() {
+        let drive = drive_with(&[distance_workout(datetime(2024, 3, 10, 9, 30), 4)]);
+        let workout = drive.last_workout().unwrap().unwrap();
+        assert!(drive.stroke_data(&workout).unwrap().is_empty());
+
+        // records of another size would be read at the wrong offsets
+        patch_file(&drive, "Concept2/Logbook/LogStrokeInfo.bin", 8, &[0x00, 0x20]);
+        assert_eq!(drive.stroke_data(&workout).unwrap_err().to_string(),
+            "Unsupported stroke data layout (16 byte entries, 32 byte records).");
+
+        // older drives without the file use the known layout
+        drive.storage.remove("Concept2/Logbook/LogStrokeInfo.bin").unwrap();
+        assert!(drive.stroke_data(&workout).unwrap().is_empty());
+    }
+
+    #[test]
+    fn shared_reads() {
+        let (drive, workouts) = three_workouts();
+        let (shared, other): (&Drive, &Drive) = (&drive, &drive);
+        let user = || shared.user().unwrap();
+
+        // readers don't disturb each other's position
+        let mut first = shared.workouts_iter().unwrap();
+        let mut second = other.workouts_iter().unwrap();
+        assert_eq!(first.next().unwrap().unwrap(), workouts[0]);
+        assert_eq!(second.next().unwrap().unwrap(), workouts[0]);
+        assert_eq!(user().1, "test");
+        assert_eq!(first.next().unwrap().unwrap(), workouts[1]);
+        assert!(other.firmwares().unwrap().is_empty());
+        assert_eq!(other.workouts().unwrap(), workouts);
+        assert_eq!(first.next().unwrap().unwrap(), workouts[2]);
+        assert_eq!(second.next().unwrap().unwrap(), workouts[1]);
+        assert!(first.next().is_none());
+        assert_eq!(second.count(), 1);
+    }
+
+    #[test]
+    fn read_only_drive() {
+        let image = SharedImage::from_vec(init_image("flummi"));
+        let mut drive = Drive::from_stream(image.clone(), true).unwrap();
+        drive.write_firmware(FIRMWARE_ARCHIVE).unwrap();
+        drive.write_workout(&distance_workout(datetime(2024, 3, 10, 9, 30), 4)).unwrap();
+        drop(drive);
+        let before = image.to_vec();
+
+        let mut drive = Drive::from_stream(image.clone(), false).unwrap();
+        let error = drive.clear_firmwares().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(error.to_string(), "Drive was opened read-only, open it with write access to change it.");
+        assert_eq!(drive.firmwares().unwrap(), ["firmware.7z"]);
+
+        let denied = |e: std::io::Error| e.kind() == std::io::ErrorKind::PermissionDenied;
+        let parser_denied = |e: ParserError| matches!(e, ParserError::Io(e) if e.kind() == std::io::ErrorKind::PermissionDenied);
+        assert!(denied(drive.set_user_name("other".to_string()).unwrap_err()));
+        assert!(denied(drive.write_firmware(FIRMWARE_ARCHIVE).unwrap_err()));
+        assert!(denied(drive.restore(zip_archive(&[("Concept2/Logbook/UserStatic.bin", b"broken")])).unwrap_err()));
+        assert!(parser_denied(drive.write_workout(&distance_workout(datetime(2024, 3, 11, 9, 30), 4)).unwrap_err()));
+        assert!(parser_denied(drive.prune_before(chrono::NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()).unwrap_err()));
+
+        drop(drive);
+        assert!(image.to_vec() == before);
+    }
+
+    #[test]
+    fn raw_workout_record() {
+        let (drive, _) = three_workouts();
+        let access_table = drive.read_file_bytes("Concept2/Logbook/LogDataAccessTbl.bin").unwrap();
+        let storage = drive.read_file_bytes("Concept2/Logbook/LogDataStorage.bin").unwrap();
+
+        let record = drive.raw_workout_record(1).unwrap();
+        assert_eq!((record.access_table_offset, record.storage_offset), (32, 178));
+        assert_eq!(record.access_table_entry, &access_table[32..64]);
+        assert_eq!(record.storage_record, &storage[178..292]);
+        let parsed = LogDataStorageEntry::read(&mut &record.storage_record[..]).unwrap();
+        assert_eq!(Workout::try_from(parsed).unwrap(), drive.workouts().unwrap()[1]);
+
+        assert!(drive.raw_workout_record(3).is_err());
+        // the record size is past the end of the storage
+        patch_file(&drive, "Concept2/Logbook/LogDataAccessTbl.bin", 64 + RECORD_SIZE_OFFSET, &[0xf0, 0xff]);
+        assert!(matches!(drive.raw_workout_record(2).unwrap_err().inner(), ParserError::UnexpectedEof));
     }
 }
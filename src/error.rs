@@ -1,24 +1,137 @@
-#[derive(Debug, Default)]
-pub struct ParserError {
-    child: Option<std::io::Error>
+#[derive(Debug)]
+pub enum ParserError {
+    /// The file ended in the middle of a record
+    UnexpectedEof,
+    /// A record started with an unexpected magic byte. The offset is the
+    /// position of the record in its file, if known.
+    BadMagic { found: u8, offset: Option<u64> },
+    UnknownWorkoutType(u8),
+    /// An access table entry references a record starting at or after the
+    /// end of the storage file, e.g. because the entry is stale or corrupt.
+    RecordOutOfBounds { offset: u64, file_len: u64 },
+    /// A record has a different size than the known layout for its workout
+    /// type, so its fields can't be trusted.
+    LayoutMismatch { record_size: usize, layout_size: usize },
+    /// A record disagrees with its access table entry about a field both
+    /// store, so it was probably read in the wrong layout.
+    FieldMismatch { field: &'static str, access_table: u32, record: u32 },
+    /// A record timestamp doesn't encode a valid date and time, e.g. because
+    /// the record is corrupt.
+    InvalidTimestamp(u32),
+    Io(std::io::Error),
+    /// Another error, annotated with the record that was being parsed and its
+    /// byte offset in the file
+    At { what: String, offset: u64, source: Box<ParserError> }
+}
+
+impl ParserError {
+    /// Records where the error occurred, e.g. `at("LogDataStorage record",
+    /// 0x4120)`. Keeps the innermost location if called more than once.
+    pub fn at<S: Into<String>>(self, what: S, offset: u64) -> Self {
+        match self {
+            Self::At { .. } => self,
+            // the magic byte is always the first byte of a record
+            Self::BadMagic { found, offset: None } => Self::At {
+                what: what.into(),
+                offset,
+                source: Box::new(Self::BadMagic { found, offset: Some(offset) })
+            },
+            _ => Self::At { what: what.into(), offset, source: Box::new(self) }
+        }
+    }
+
+    /// Byte offset of the record that couldn't be parsed, if known.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Self::At { offset, .. } => Some(*offset),
+            Self::BadMagic { offset, .. } => *offset,
+            Self::RecordOutOfBounds { offset, .. } => Some(*offset),
+            _ => None
+        }
+    }
+
+    /// Returns the underlying error without location information, for
+    /// matching on the kind of error.
+    pub fn inner(&self) -> &ParserError {
+        match self {
+            Self::At { source, .. } => source.inner(),
+            _ => self
+        }
+    }
 }
 
 impl std::fmt::Display for ParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        if let Some(error) = &self.child {
-            write!(f, "Error encountered during parsing:\n{}", error)
-        } else {
-            write!(f, "Error encountered during parsing.")
+        match self {
+            Self::UnexpectedEof => write!(f, "Unexpected end of file."),
+            Self::BadMagic { found, offset: Some(offset) } => {
+                write!(f, "Invalid magic 0x{:02x} at 0x{:x}.", found, offset)
+            },
+            Self::BadMagic { found, offset: None } => write!(f, "Invalid magic 0x{:02x}.", found),
+            Self::UnknownWorkoutType(workout_type) => write!(f, "Unknown workout type 0x{:02x}.", workout_type),
+            Self::RecordOutOfBounds { offset, file_len } => {
+                write!(f, "Record offset 0x{:x} is past the end of the file (0x{:x} bytes).", offset, file_len)
+            },
+            Self::LayoutMismatch { record_size, layout_size } => {
+                write!(f, "Record has {} bytes, but the known layout has {}. It was probably written by an unsupported firmware.",
+                    record_size, layout_size)
+            },
+            Self::FieldMismatch { field, access_table, record } => {
+                write!(f, "Record has {} 0x{:02x}, but the access table has 0x{:02x}. It was probably written by an unsupported firmware.",
+                    field, record, access_table)
+            },
+            Self::InvalidTimestamp(timestamp) => write!(f, "Invalid timestamp 0x{:08x}.", timestamp),
+            Self::Io(error) => write!(f, "{}", error),
+            Self::At { what, offset, source } => {
+                write!(f, "Error encountered during parsing of {} at 0x{:x}: {}", what, offset, source)
+            }
         }
     }
 }
 
-impl std::error::Error for ParserError {}
+impl std::error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::At { source, .. } => Some(source.as_ref()),
+            _ => None
+        }
+    }
+}
 
 impl From<std::io::Error> for ParserError {
     fn from(error: std::io::Error) -> Self {
-        ParserError {
-            child: Some(error)
+        if error.kind() == std::io::ErrorKind::UnexpectedEof {
+            ParserError::UnexpectedEof
+        } else {
+            ParserError::Io(error)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn innermost_location_is_kept() {
+        let error = ParserError::UnknownWorkoutType(0x42)
+            .at("LogDataStorage record", 0x4120)
+            .at("LogDataAccessTbl entry", 0x40);
+
+        assert_eq!(error.offset(), Some(0x4120));
+        assert!(matches!(error.inner(), ParserError::UnknownWorkoutType(0x42)));
+        assert_eq!(error.to_string(),
+            "Error encountered during parsing of LogDataStorage record at 0x4120: Unknown workout type 0x42.");
+    }
+
+    #[test]
+    fn from_io_error() {
+        let eof = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "eof");
+        assert!(matches!(ParserError::from(eof), ParserError::UnexpectedEof));
+
+        let other = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert!(matches!(ParserError::from(other), ParserError::Io(_)));
+        assert_eq!(ParserError::UnexpectedEof.offset(), None);
+    }
+}
@@ -0,0 +1,367 @@
+use std::io::Write;
+
+use crate::workouts::*;
+
+const CSV_HEADER: [&str; 10] = [
+    "Date", "Type", "Distance", "Work Time", "Rest Time", "SPM", "Pace",
+    "Heart Rate", "Watts", "Cal/Hr"
+];
+
+/// Quotes a CSV field if necessary.
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') || s.contains('\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn write_csv_row<W: Write>(writer: &mut W, fields: &[String]) -> Result<(),std::io::Error> {
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    writeln!(writer, "{}", fields.join(","))
+}
+
+/// Writes workouts as CSV, one row per workout, with the same columns as
+/// list-workouts.
+pub fn write_csv<W: Write>(workouts: &[Workout], mut writer: W) -> Result<(),std::io::Error> {
+    let header: Vec<String> = CSV_HEADER.iter().map(|h| h.to_string()).collect();
+    write_csv_row(&mut writer, &header)?;
+
+    for workout in workouts {
+        write_csv_row(&mut writer, &[
+            workout.datetime.format("%Y-%m-%d %H:%M").to_string(),
+            workout.workout_type.to_string(),
+            workout.total_distance.to_string(),
+            workout.work_duration_string(),
+            workout.rest_duration_string(),
+            workout.spm.map(|s| s.to_string()).unwrap_or_default(),
+            workout.pace_string(),
+            workout.heart_rate().map(|h| h.to_string()).unwrap_or_default(),
+            format!("{:.0}", workout.watts()),
+            format!("{:.0}", workout.cal_hr()),
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// Columns of the season export on log.concept2.com, which is also what the
+/// logbook and ErgData accept for imports.
+pub const CONCEPT2_CSV_HEADER: [&str; 23] = [
+    "Log ID", "Date", "Description", "Work Time (Formatted)", "Work Time (Seconds)",
+    "Rest Time (Formatted)", "Rest Time (Seconds)", "Work Distance", "Rest Distance",
+    "Stroke Rate/Cadence", "Stroke Count", "Pace", "Avg Watts", "Cal/Hour", "Total Cal",
+    "Avg Heart Rate", "Drag Factor", "Age", "Weight", "Type", "Ranked", "Comments",
+    "Date Entered"
+];
+
+/// Writes a row in the official format, where every field is quoted.
+fn write_concept2_csv_row<W: Write>(writer: &mut W, fields: &[String]) -> Result<(),std::io::Error> {
+    let fields: Vec<String> = fields.iter()
+        .map(|f| format!("\"{}\"", f.replace('"', "\"\"")))
+        .collect();
+    writeln!(writer, "{}", fields.join(","))
+}
+
+impl Workout {
+    /// Short description of the workout, as the logbook shows it, e.g.
+    /// "2000m row" or "4x500m/1:00r".
+    fn concept2_description(&self) -> String {
+        match (self.workout_type, self.frames.first(), self.rest_interval) {
+            (WorkoutType::SingleTime, _, _) =>
+                format!("{} row", duration_to_string_precision(&self.total_work_duration, 0)),
+            (WorkoutType::TimeInterval, Some(frame), Some(rest)) =>
+                format!("{}x{}/{}r", self.frames.len(),
+                    duration_to_string_precision(&frame.work_duration, 0),
+                    duration_to_string_precision(&rest, 0)),
+            (WorkoutType::DistanceInterval, Some(frame), Some(rest)) =>
+                format!("{}x{}m/{}r", self.frames.len(), frame.distance,
+                    duration_to_string_precision(&rest, 0)),
+            (WorkoutType::TimeInterval, _, _) | (WorkoutType::DistanceInterval, _, _) |
+                (WorkoutType::VariableInterval, _, _) => "v row".to_string(),
+            _ => format!("{}m row", self.total_distance)
+        }
+    }
+
+    /// Returns the workout as a row matching `CONCEPT2_CSV_HEADER`. Fields
+    /// the drive doesn't store, like the log id or drag factor, are empty.
+    pub fn to_concept2_csv_row(&self) -> Vec<String> {
+        let rest_distance: u32 = self.frames.iter().filter_map(|f| f.rest_distance).sum();
+
+        vec![
+            String::new(),
+            self.datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+            self.concept2_description(),
+            self.work_duration_string(),
+            format!("{:.1}", self.total_work_duration.as_secs_f64()),
+            self.rest_duration_string(),
+            self.total_rest_duration.map(|d| format!("{:.1}", d.as_secs_f64())).unwrap_or_default(),
+            self.total_distance.to_string(),
+            self.total_rest_duration.map(|_| rest_distance.to_string()).unwrap_or_default(),
+            self.spm.or_else(|| self.average_spm()).map(|s| s.to_string()).unwrap_or_default(),
+            String::new(),
+            duration_to_string(&self.average_pace_500m()),
+            format!("{:.0}", self.watts()),
+            format!("{:.0}", self.cal_hr()),
+            format!("{:.0}", self.total_calories()),
+            self.heart_rate().map(|h| h.to_string()).unwrap_or_default(),
+            String::new(),
+            String::new(),
+            String::new(),
+            self.machine.to_string(),
+            "No".to_string(),
+            String::new(),
+            String::new(),
+        ]
+    }
+}
+
+/// Writes workouts in the CSV layout of the official Concept2 logbook, see
+/// `CONCEPT2_CSV_HEADER`.
+pub fn write_concept2_csv<W: Write>(workouts: &[Workout], mut writer: W) -> Result<(),std::io::Error> {
+    let header: Vec<String> = CONCEPT2_CSV_HEADER.iter().map(|h| h.to_string()).collect();
+    write_concept2_csv_row(&mut writer, &header)?;
+
+    for workout in workouts {
+        write_concept2_csv_row(&mut writer, &workout.to_concept2_csv_row())?;
+    }
+
+    Ok(())
+}
+
+/// Writes workouts as a JSON array, one workout per line. Workouts are
+/// serialized as they come, so the document is never held in memory as a
+/// whole.
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+pub(crate) fn write_json_array<W, B, E, I>(workouts: I, mut writer: W) -> Result<(),E>
+where
+    W: Write,
+    B: std::borrow::Borrow<Workout>,
+    E: From<std::io::Error>,
+    I: IntoIterator<Item = Result<B,E>>
+{
+    writer.write_all(b"[")?;
+
+    for (i, workout) in workouts.into_iter().enumerate() {
+        let workout = workout?;
+        writer.write_all(if i == 0 { b"\n" } else { b",\n" })?;
+        serde_json::to_writer(&mut writer, workout.borrow()).map_err(std::io::Error::from)?;
+    }
+
+    writer.write_all(b"\n]\n")?;
+    Ok(writer.flush()?)
+}
+
+/// Writes workouts as a JSON array, including their frames, with the same
+/// structure as show-workouts --json.
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+pub fn write_json<W: Write>(workouts: &[Workout], writer: W) -> Result<(),std::io::Error> {
+    write_json_array(workouts.iter().map(Ok), writer)
+}
+
+/// Escapes text for use in XML.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn tcx_time(datetime: &chrono::NaiveDateTime) -> String {
+    datetime.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+fn tcx_trackpoint(
+    tcx: &mut String,
+    time: &chrono::NaiveDateTime,
+    distance: u32,
+    heart_rate: Option<u32>,
+    cadence: u32
+) {
+    tcx.push_str("          <Trackpoint>\n");
+    tcx.push_str(&format!("            <Time>{}</Time>\n", tcx_time(time)));
+    tcx.push_str(&format!("            <DistanceMeters>{}</DistanceMeters>\n", distance));
+    if let Some(hr) = heart_rate {
+        tcx.push_str(&format!("            <HeartRateBpm><Value>{}</Value></HeartRateBpm>\n", hr));
+    }
+    tcx.push_str(&format!("            <Cadence>{}</Cadence>\n", cadence));
+    tcx.push_str("          </Trackpoint>\n");
+}
+
+/// Appends a TCX `Lap` for the given frame, starting at `start` with
+/// `distance` meters rowed before it. The machine is needed for the
+/// calories.
+fn tcx_lap(tcx: &mut String, start: chrono::NaiveDateTime, distance: u32, frame: &WorkoutFrame, machine: MachineType) {
+    let end = start + chrono::Duration::from_std(frame.work_duration).unwrap_or_else(|_| chrono::Duration::zero());
+
+    tcx.push_str(&format!("      <Lap StartTime=\"{}\">\n", tcx_time(&start)));
+    tcx.push_str(&format!("        <TotalTimeSeconds>{:.1}</TotalTimeSeconds>\n", frame.work_duration.as_secs_f64()));
+    tcx.push_str(&format!("        <DistanceMeters>{}</DistanceMeters>\n", frame.distance));
+    tcx.push_str(&format!("        <Calories>{:.0}</Calories>\n", frame.calories(machine)));
+    if let Some(hr) = frame.work_heart_rate {
+        tcx.push_str(&format!("        <AverageHeartRateBpm><Value>{}</Value></AverageHeartRateBpm>\n", hr));
+    }
+    tcx.push_str("        <Intensity>Active</Intensity>\n");
+    tcx.push_str(&format!("        <Cadence>{}</Cadence>\n", frame.spm));
+    tcx.push_str("        <TriggerMethod>Manual</TriggerMethod>\n");
+    tcx.push_str("        <Track>\n");
+    tcx_trackpoint(tcx, &start, distance, frame.work_heart_rate, frame.spm);
+    tcx_trackpoint(tcx, &end, distance + frame.distance, frame.work_heart_rate, frame.spm);
+    tcx.push_str("        </Track>\n");
+    tcx.push_str("      </Lap>\n");
+}
+
+impl Workout {
+    /// Returns the workout as a TCX `Activity` element, with one lap per
+    /// frame.
+    fn tcx_activity(&self) -> String {
+        let mut tcx = String::new();
+
+        tcx.push_str("    <Activity Sport=\"Other\">\n");
+        tcx.push_str(&format!("      <Id>{}</Id>\n", tcx_time(&self.datetime)));
+
+        if self.frames.is_empty() {
+            // TCX requires at least one lap
+            tcx_lap(&mut tcx, self.datetime, 0, &WorkoutFrame {
+                distance: self.total_distance,
+                work_duration: self.total_work_duration,
+                rest_duration: None,
+                rest_distance: None,
+                spm: self.spm.unwrap_or_default(),
+                work_heart_rate: None,
+                rest_heart_rate: None,
+                heart_rate_min: None,
+                heart_rate_max: None,
+                heart_rate_avg: None,
+                drag_factor: None,
+            }, self.machine);
+        }
+
+        let mut time = self.datetime;
+        let mut distance = 0;
+
+        for frame in &self.frames {
+            tcx_lap(&mut tcx, time, distance, frame, self.machine);
+
+            distance += frame.distance;
+            time += chrono::Duration::from_std(frame.work_duration + frame.rest_duration.unwrap_or_default())
+                .unwrap_or_else(|_| chrono::Duration::zero());
+        }
+
+        tcx.push_str(&format!("      <Notes>{}</Notes>\n", xml_escape(&self.workout_type.to_string())));
+        tcx.push_str("    </Activity>\n");
+        tcx
+    }
+
+    /// Returns the workout as a TCX document.
+    pub fn to_tcx(&self) -> String {
+        let mut tcx = String::new();
+        tcx.push_str(TCX_HEADER);
+        tcx.push_str(&self.tcx_activity());
+        tcx.push_str(TCX_FOOTER);
+        tcx
+    }
+}
+
+const TCX_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<TrainingCenterDatabase xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\">
+  <Activities>
+";
+
+const TCX_FOOTER: &str = "  </Activities>
+</TrainingCenterDatabase>
+";
+
+/// Writes workouts as a TCX document, with one activity per workout.
+pub fn write_tcx<W: Write>(workouts: &[Workout], mut writer: W) -> Result<(),std::io::Error> {
+    writer.write_all(TCX_HEADER.as_bytes())?;
+
+    for workout in workouts {
+        writer.write_all(workout.tcx_activity().as_bytes())?;
+    }
+
+    writer.write_all(TCX_FOOTER.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    /// Checks that opening and closing tags match, which is all the XML the
+    /// TCX export uses besides the declaration.
+    fn assert_well_formed(xml: &str) {
+        let mut open = Vec::new();
+        for tag in xml.split('<').skip(1).map(|t| &t[..t.find('>').unwrap()]) {
+            if tag.starts_with('?') || tag.ends_with('/') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name), "unexpected </{}>", name);
+            } else {
+                open.push(tag.split(' ').next().unwrap());
+            }
+        }
+        assert!(open.is_empty(), "unclosed {:?}", open);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field("Distance"), "Distance");
+        assert_eq!(csv_field("4x500m, 1:00r"), "\"4x500m, 1:00r\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn tcx() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        workout.frames[1].work_heart_rate = Some(150);
+
+        let tcx = workout.to_tcx();
+        assert_well_formed(&tcx);
+        assert_eq!(tcx.matches("<Lap ").count(), 4);
+        assert_eq!(tcx.matches("<Trackpoint>").count(), 8);
+        assert!(tcx.contains("<Lap StartTime=\"2024-03-10T09:32:00\">"));
+        assert!(tcx.contains("<DistanceMeters>1000</DistanceMeters>"));
+        assert_eq!(tcx.matches("<HeartRateBpm><Value>150</Value></HeartRateBpm>").count(), 2);
+        assert!(tcx.contains("<Cadence>24</Cadence>"));
+
+        // workouts without frames still get a lap
+        workout.frames.clear();
+        let tcx = workout.to_tcx();
+        assert_well_formed(&tcx);
+        assert_eq!(tcx.matches("<Lap ").count(), 1);
+
+        let mut written = Vec::new();
+        write_tcx(&[workout, distance_workout(datetime(2024, 3, 11, 9, 30), 2)], &mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert_well_formed(&written);
+        assert_eq!(written.matches("<Activity ").count(), 2);
+    }
+
+    #[test]
+    fn concept2_csv() {
+        use std::convert::TryFrom;
+        use crate::native::LogDataStorageEntry;
+
+        let interval = Workout::try_from(LogDataStorageEntry::read(&mut &TIME_INTERVAL_RECORD[..]).unwrap()).unwrap();
+        let workouts = [distance_workout(datetime(2024, 3, 10, 9, 30), 4), interval];
+
+        let mut csv = Vec::new();
+        write_concept2_csv(&workouts, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        // the header of the season export on log.concept2.com, kept apart
+        // from `CONCEPT2_CSV_HEADER` so it can be checked against a download
+        let official = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/concept2-season-header.csv"));
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], official.trim_end());
+        assert_eq!(official.trim_end().split(',').count(), CONCEPT2_CSV_HEADER.len());
+        assert_eq!(lines[1], r#""","2024-03-10 09:30:00","2000m row","8:00.0","480.0","","","2000","","24","","2:00.0","203","997","133","","","","","RowErg","No","","""#);
+        // the pace only counts the work portions, the stroke rate comes from
+        // the intervals
+        assert_eq!(lines[2], r#""","2024-03-12 06:15:00","3x2:00/1:00r","6:00.0","360.0","2:00.0","120.0","915","53","30","","3:16.7","46","458","46","169","","","","RowErg","No","","""#);
+    }
+}
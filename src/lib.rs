@@ -5,8 +5,16 @@
 
 pub mod drive;
 pub mod error;
+pub mod export;
 pub mod native;
+pub mod profile;
+pub mod progress;
+pub mod storage;
+pub mod units;
 pub mod workouts;
 
+#[cfg(test)]
+mod test_util;
+
 pub use drive::*;
 pub use workouts::*;
@@ -4,62 +4,215 @@ use std::path::{Path,PathBuf};
 
 use colored::*;
 use docopt::Docopt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use concept2drive::*;
+use concept2drive::error::*;
+use concept2drive::native::RecordField;
+use concept2drive::profile::AthleteProfile;
+use concept2drive::progress::ProgressReporter;
 
 mod api;
-mod drive;
-mod error;
-mod native;
-mod workouts;
+mod table;
 
 use api::*;
-use drive::*;
-use error::*;
+use table::{Align, Table};
 
-const VERSION: &'static str = "v0.1";
-const USAGE: &'static str = "
+const VERSION: &str = "v0.1";
+const USAGE: &str = "
 Usage:
-    concept2drive info <device>
-    concept2drive init <device> [<username>]
-    concept2drive list-workouts <device> [-n <num>]
-    concept2drive show-workouts <device> [<workout>]
-    concept2drive update-firmware <device> [--beta]
+    concept2drive detect
+    concept2drive info <device> [--weight <kg>] [--imperial] [--json]
+    concept2drive init <device> [<username>] [--yes]
+    concept2drive set-name <device> <name>
+    concept2drive list-workouts <device> [-n <num>] [--desc] [--since <date>] [--until <date>] [--type <name>] [--imperial] [--pace-per-mile]
+    concept2drive show-workouts <device> [<workout>] [--max-hr <bpm>] [--json]
+    concept2drive compare <device> <workout> <other>
+    concept2drive inspect <device> [<workout>]
+    concept2drive check <device>
+    concept2drive favorites <device> [--json]
+    concept2drive stats <device> [--by <period>] [--since <date>] [--until <date>] [--type <name>] [--imperial] [--pace-per-mile]
+    concept2drive export <device> <file> [--format <format>]
+    concept2drive export <device> <workout> <file> [--format <format>]
+    concept2drive merge <devices>... <file>
+    concept2drive upload <device> [--token <token>] [--yes]
+    concept2drive backup <device> <file>
+    concept2drive restore <device> <file> [--yes]
+    concept2drive prune <device> <date> [--yes]
+    concept2drive update-firmware <device> [--beta] [--monitor <pm>] [--no-clear] [--yes]
+    concept2drive install-firmware <device> <archive> [--no-clear] [--yes]
+    concept2drive show-firmware <device> <name>
     concept2drive (-h | --help)
     concept2drive --version
 
 Commands:
+    detect              List the Concept2 drives that are currently mounted.
+                        Pass auto as <device> to any other command to use
+                        the drive found, if there is exactly one.
     info                Show general information about the flash drive.
     init                Set up a new drive at the given path. If no user name
-                        is given, $USER is used. Name must be <= 6 characters.
+                        is given, $USER is used. Name must be <= 6 ASCII
+                        characters.
+    set-name            Change the user name without touching any other data.
     list-workouts       List the workouts stored on the drive.
-    show-workout        Show detailed information about a specific workout.
+    show-workouts       Show detailed information about a specific workout.
                         The workout can be identified either with the ID listed
                         in the output of list-workouts, or by date.
                         If no workout is given, the last one is displayed.
+    compare             Compare a workout to another one of the same type,
+                        showing the differences in totals and per split.
+    inspect             Dump the raw bytes of a workout in hex, labeled with
+                        the fields they belong to. Unknown bytes are
+                        highlighted.
+    check               Check that the workout index matches the stored
+                        records, e.g. after a drive was corrupted, without
+                        changing anything.
+    favorites           List the favorite workouts saved on the monitor.
+    stats               Show distance, time and pace per week or month, and
+                        the distribution of paces over all workouts.
+    export              Export all workouts on the drive to a file, or only
+                        the given one, selected as for show-workouts.
+    merge               Export the workouts of several drives to one file,
+                        sorted by date and without duplicates. The format is
+                        picked by the extension of <file> (.csv, .json or
+                        .tcx).
+    upload              Upload workouts to the Concept2 online logbook,
+                        skipping those already present.
+    backup              Save all logbook files on the drive to a zip archive.
+    restore             Write the logbook files from a backup to the drive.
+    prune               Delete all workouts before <date> (YYYY-MM-DD) from
+                        the drive to free up space.
     update-firmware     Update firmwares on the drive.
+    install-firmware    Install a firmware archive (.7z) from a local file.
+    show-firmware       List the files in a firmware archive on the drive, as
+                        listed by info.
 
 Options:
     -h --help           Show usage information.
     --version           Show version.
     -n --last=<num>     Only show <num> latest workouts.
+    --desc              Show the newest workouts first.
+    --since=<date>      Only show workouts on or after <date> (YYYY-MM-DD).
+    --until=<date>      Only show workouts on or before <date> (YYYY-MM-DD).
+    --type=<name>       Only show workouts of the given type, e.g. \"Distance
+                        Interval\".
+    --by=<period>       Group statistics by week or month [default: week].
+    --weight=<kg>       Body weight in kg (lbs with --imperial), used for
+                        calorie calculations. Overrides profile.toml.
+    --max-hr=<bpm>      Maximum heart rate, used to show the time spent in
+                        each heart rate zone. Overrides profile.toml.
+    --imperial          Show distances in miles/yards and weights in lbs.
+    --pace-per-mile     Show pace per mile instead of per 500m.
+    --format=<format>   Export format, csv, concept2 (CSV as exported by the
+                        Concept2 logbook), tcx or json [default: csv].
+    --json              Output JSON instead of formatted text.
     --beta              Include beta firmwares.
+    --monitor=<pm>      Monitor to install firmwares for, pm3, pm4, pm5 or
+                        pm5v3. Detected from the drive by default.
+    --no-clear          Keep firmwares already stored on the drive instead of
+                        removing them first.
+    --token=<token>     Access token for the online logbook, stored for later
+                        uploads.
+    -y --yes            Don't ask for confirmation, e.g. for scripts.
+
+Exit Codes:
+    0                   Success.
+    1                   Invalid usage or other errors.
+    2                   The drive or a file couldn't be accessed, or the drive
+                        isn't a Concept2 drive.
+    3                   Request to the Concept2 servers failed.
+    4                   Data on the drive or in a file couldn't be parsed.
+
+With --json, errors are printed as JSON as well, with the message, the kind
+(other, device, network or parse) and the exit code.
+
+Environment:
+    CONCEPT2DRIVE_FIRMWARE_URL     Firmware list to use for update-firmware
+                                   instead of the official one, e.g. a mirror.
+    CONCEPT2DRIVE_FIRMWARE_AUTH    Authorization header sent with the request
+                                   for the firmware list. Empty to send none.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
+    cmd_detect: bool,
     cmd_info: bool,
     cmd_init: bool,
+    cmd_set_name: bool,
     cmd_list_workouts: bool,
     cmd_show_workouts: bool,
+    cmd_favorites: bool,
+    cmd_compare: bool,
+    cmd_inspect: bool,
+    cmd_check: bool,
+    cmd_stats: bool,
+    cmd_export: bool,
+    cmd_merge: bool,
+    cmd_upload: bool,
+    cmd_backup: bool,
+    cmd_restore: bool,
+    cmd_prune: bool,
     cmd_update_firmware: bool,
+    cmd_install_firmware: bool,
+    cmd_show_firmware: bool,
+    arg_archive: Option<String>,
+    arg_date: Option<String>,
     arg_device: Option<String>,
+    arg_devices: Vec<String>,
+    arg_file: Option<String>,
+    arg_name: Option<String>,
+    arg_other: Option<String>,
     arg_username: Option<String>,
+    arg_workout: Option<String>,
     flag_last: Option<usize>,
+    flag_desc: bool,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
+    flag_type: Option<String>,
+    flag_weight: Option<f64>,
+    flag_max_hr: Option<u32>,
+    flag_by: String,
+    flag_format: String,
+    flag_json: bool,
+    flag_imperial: bool,
+    flag_pace_per_mile: bool,
     flag_beta: bool,
+    flag_monitor: Option<String>,
+    flag_no_clear: bool,
+    flag_token: Option<String>,
+    flag_yes: bool,
+}
+
+/// Category of an error, used as exit code so scripts can tell them apart.
+/// Usage errors reported by docopt exit with 1 as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    #[default]
+    Other = 1,
+    /// The drive or another file couldn't be accessed, or the drive isn't a
+    /// Concept2 drive
+    Device = 2,
+    /// Request to the Concept2 servers failed
+    Network = 3,
+    /// Data on the drive or in a file couldn't be parsed
+    Parse = 4
+}
+
+impl ErrorKind {
+    fn from_io(error: &std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => Self::Parse,
+            // invalid arguments, e.g. an unknown workout type or position
+            std::io::ErrorKind::InvalidInput => Self::Other,
+            _ => Self::Device
+        }
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct CliError {
+    kind: ErrorKind,
     msg: String
 }
 
@@ -72,55 +225,225 @@ impl std::fmt::Display for CliError {
 impl std::error::Error for CliError {}
 
 macro_rules! error_from {
-    ( $t:ty ) => {
+    ( $t:ty, $kind:expr ) => {
         impl From<$t> for CliError {
             fn from(error: $t) -> Self {
-                CliError { msg: format!("{}", error) }
+                let kind: fn(&$t) -> ErrorKind = $kind;
+                CliError { kind: kind(&error), msg: format!("{}", error) }
             }
         }
     }
 }
 
-error_from!(ParserError);
-error_from!(std::io::Error);
-error_from!(reqwest::Error);
-error_from!(xdg::BaseDirectoriesError);
+error_from!(ParserError, |e| match e.inner() {
+    ParserError::Io(e) => ErrorKind::from_io(e),
+    _ => ErrorKind::Parse
+});
+error_from!(std::io::Error, ErrorKind::from_io);
+error_from!(reqwest::Error, |_| ErrorKind::Network);
+error_from!(xdg::BaseDirectoriesError, |_| ErrorKind::Other);
+error_from!(serde_json::Error, |_| ErrorKind::Parse);
+
+/// Output of errors with --json
+#[derive(Serialize)]
+struct JsonError<'a> {
+    error: &'a str,
+    kind: ErrorKind,
+    code: i32
+}
+
+/// Validators of a cached download, stored next to the cached file and sent
+/// with the next request, so files are only downloaded again if they changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheMetadata {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl CacheMetadata {
+    fn path(cached_file: &Path) -> PathBuf {
+        let mut name = cached_file.as_os_str().to_owned();
+        name.push(".meta");
+        PathBuf::from(name)
+    }
+
+    /// Reads the metadata for a cached file. Returns `None` if either the
+    /// file or its metadata are missing.
+    fn read(cached_file: &Path) -> Option<Self> {
+        if !cached_file.is_file() {
+            return None;
+        }
+
+        let data = std::fs::read(Self::path(cached_file)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn write(&self, cached_file: &Path) -> Result<(),CliError> {
+        std::fs::write(Self::path(cached_file), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        let header = |name| headers.get(name)
+            .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        Self {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        }
+    }
+
+    /// Adds conditional request headers for the stored validators.
+    fn apply(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+}
+
+/// Number of attempts for each download before giving up
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// Time without receiving any data after which a download is aborted
+const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Time to wait after the first failed attempt, doubled after every further
+/// one
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Runs a download up to `DOWNLOAD_ATTEMPTS` times, waiting a bit longer after
+/// each failed attempt.
+async fn with_retries<T, E, F, Fut>(what: &str, download: F) -> Result<T,CliError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T,E>>,
+    E: Into<CliError>
+{
+    retry_after(what, RETRY_DELAY, download).await
+}
+
+async fn retry_after<T, E, F, Fut>(what: &str, delay: std::time::Duration, mut download: F) -> Result<T,CliError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T,E>>,
+    E: Into<CliError>
+{
+    let mut attempt = 1;
+
+    loop {
+        let error: CliError = match download().await {
+            Ok(result) => return Ok(result),
+            Err(e) => e.into()
+        };
+
+        if attempt >= DOWNLOAD_ATTEMPTS {
+            return Err(CliError {
+                kind: error.kind,
+                msg: format!("Failed to download {} after {} attempts: {}", what, attempt, error)
+            });
+        }
+
+        eprintln!("{} Failed to download {}, retrying: {}", "warning:".bold().yellow(), what, error);
+        tokio::time::delay_for(delay * (1 << (attempt - 1))).await;
+        attempt += 1;
+    }
+}
+
+/// Writes the response body to the target file, failing if no data is
+/// received for `READ_TIMEOUT`.
+async fn copy_response(
+    resp: &mut reqwest::Response,
+    target: &mut std::fs::File,
+    pb: &indicatif::ProgressBar
+) -> Result<(),CliError> {
+    loop {
+        let chunk = tokio::time::timeout(READ_TIMEOUT, resp.chunk()).await
+            .map_err(|_| CliError { kind: ErrorKind::Network, msg: "Download stalled.".to_string() })??;
+
+        match chunk {
+            Some(chunk) => {
+                target.write_all(&chunk)?;
+                pb.inc(chunk.len() as u64);
+            },
+            None => return Ok(())
+        }
+    }
+}
+
+/// Returns the size announced in the `Content-Length` header, if the server
+/// sent a valid one. Chunked responses don't have one.
+fn content_length(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    headers.get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
 
-/// Download firmware file to target path while printing progress bar.
+/// Download firmware file to target path while printing progress bar. If the
+/// file is cached already, it is only downloaded again if the server reports
+/// a change. Failed downloads are retried.
 async fn download_file_progress(
     file: &FirmwareFile,
     target_path: PathBuf
 ) -> Result<(),CliError> {
-    let client = reqwest::Client::new();
+    with_retries(&file.name, || download_file_attempt(file, &target_path)).await
+}
+
+async fn download_file_attempt(
+    file: &FirmwareFile,
+    target_path: &Path
+) -> Result<(),CliError> {
+    let client = api::client()?;
+
+    let mut request = client.get(&file.path);
+    if let Some(metadata) = CacheMetadata::read(target_path) {
+        request = metadata.apply(request);
+    }
 
-    // Send HEAD request to get file size
-    let resp = client.head(&file.path).send().await?;
-    let size: usize = resp.headers()
-        .get(reqwest::header::CONTENT_LENGTH)
-        .and_then(|ct_len| ct_len.to_str().ok())
-        .and_then(|ct_len| ct_len.parse().ok())
-        .unwrap();
+    let mut resp = request.send().await?;
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(());
+    }
+    resp.error_for_status_ref()?;
 
-    // Setup progress bar
+    // Remove old metadata first, a partially written file must not be
+    // considered up-to-date
+    let _ = std::fs::remove_file(CacheMetadata::path(target_path));
+    let metadata = CacheMetadata::from_headers(resp.headers());
+
+    // Setup progress bar, without a total if the server didn't announce one
     let mut template = "{spinner:.bold.green} ".to_string();
     template += &format!("{:47}", file.name);
-    template += " [{bar:40.bold.green/white}] {bytes}/{total_bytes} ({eta})";
 
-    let pb = indicatif::ProgressBar::new(size as u64);
+    let pb = match content_length(resp.headers()) {
+        Some(length) => {
+            template += " [{bar:40.bold.green/white}] {bytes}/{total_bytes} ({eta})";
+            indicatif::ProgressBar::new(length)
+        },
+        None => {
+            template += " {bytes} ({bytes_per_sec})";
+            indicatif::ProgressBar::new_spinner()
+        }
+    };
     pb.set_style(indicatif::ProgressStyle::default_bar()
          .template(&template)
          .progress_chars("##-"));
 
-    // GET file using a wrapped reader to update progress bar
-    let resp = client.get(&file.path).send().await?;
-    let bytes = resp.bytes().await?;
-    let mut reader = pb.wrap_read(&*bytes);
     let mut target = std::fs::File::create(target_path)?;
+    let result = copy_response(&mut resp, &mut target, &pb).await;
 
-    std::io::copy(&mut reader, &mut target)?;
-    pb.finish();
+    // clear the progress bar on failure, so a retry starts on a clean line
+    match result {
+        Ok(()) => pb.finish(),
+        Err(_) => pb.finish_and_clear()
+    }
+    result?;
 
-    Ok(())
+    metadata.write(target_path)
 }
 
 /// Check available versions and download those not present in the local cache
@@ -128,10 +451,16 @@ async fn download_file_progress(
 fn update_firmware_cache() -> Result<Vec<FirmwareVersion>,CliError> {
     let mut rt = tokio::runtime::Runtime::new().unwrap();
 
-    // Request list of versions
-    let versions = rt.block_on(FirmwareVersions::download())?;
+    // Request list of versions. The default credentials are only sent to
+    // the official API.
+    let (url, auth) = match std::env::var("CONCEPT2DRIVE_FIRMWARE_URL") {
+        Ok(url) => (url, std::env::var("CONCEPT2DRIVE_FIRMWARE_AUTH").ok()),
+        Err(_) => (FIRMWARE_API.to_string(), Some(std::env::var("CONCEPT2DRIVE_FIRMWARE_AUTH")
+            .unwrap_or_else(|_| FIRMWARE_AUTH.to_string())))
+    };
+    let versions = rt.block_on(with_retries("firmware list",
+        || FirmwareVersions::download_from(&url, auth.as_deref().filter(|a| !a.is_empty()))))?;
 
-    let mut updated = false;
     for version in &versions.data {
         // Get default file of firmware version
         let file = version.files.iter().find(|f| f.default);
@@ -147,17 +476,7 @@ fn update_firmware_cache() -> Result<Vec<FirmwareVersion>,CliError> {
             .join("firmware")
             .join(&file.name))?;
 
-        // Skip file if present already
-        if local_path.is_file() {
-            continue;
-        }
-
-        if !updated {
-            println!("Downloading firmwares...");
-            updated = true;
-        }
-
-        // Download firmware
+        // Download firmware, unless the cached file is still current
         rt.block_on(download_file_progress(file, local_path))?;
     }
 
@@ -166,10 +485,15 @@ fn update_firmware_cache() -> Result<Vec<FirmwareVersion>,CliError> {
     Ok(versions.data)
 }
 
-/// Ask user for confirmation.
-fn confirm(msg: String) -> Result<bool,CliError> {
+/// Ask user for confirmation. With `yes`, the question is still printed,
+/// but answered without reading from stdin.
+fn confirm(msg: String, yes: bool) -> Result<bool,CliError> {
     let mut stdout = std::io::stdout();
     print!("{} ({}/{}): ", msg, "y".bold().green(), "N".bold().red());
+    if yes {
+        println!("y");
+        return Ok(true);
+    }
     stdout.flush()?;
 
     let mut input = String::new();
@@ -177,30 +501,184 @@ fn confirm(msg: String) -> Result<bool,CliError> {
     Ok(input.to_lowercase() == "y\n")
 }
 
-/// info command
+/// Opens the drive given on the command line, making sure it has been set up
+/// as a Concept2 drive.
+fn open_drive(device: String, allow_writing: bool) -> Result<Drive,CliError> {
+    let device = resolve_device(device)?;
+    let drive = if device.ends_with(".gz") {
+        if allow_writing {
+            return Err(CliError { kind: ErrorKind::Other, msg: "Compressed images can only be read.".to_string() });
+        }
+        Drive::from_gzip(&device)
+    } else {
+        Drive::new(&device, allow_writing)
+    };
+    // e.g. if it isn't FAT formatted, which isn't a parse error of its data
+    let drive = drive.map_err(|e| CliError { kind: ErrorKind::Device, msg: e.to_string() })?;
+
+    if let Err(e) = drive.check_concept2_drive() {
+        return Err(CliError { kind: ErrorKind::Device, msg: format!("{}: {}\nUse init to set up a new drive.", device, e) });
+    }
+
+    Ok(drive)
+}
+
+/// Replaces `auto` with the only mounted Concept2 drive.
+fn resolve_device(device: String) -> Result<String,CliError> {
+    if device != "auto" {
+        return Ok(device);
+    }
+
+    let drives = find_concept2_drives();
+    match drives.len() {
+        0 => Err(CliError { kind: ErrorKind::Device, msg: "No mounted Concept2 drive found.".to_string() }),
+        1 => Ok(drives[0].to_string_lossy().into_owned()),
+        _ => {
+            let paths: Vec<String> = drives.iter().map(|d| format!("    {}", d.display())).collect();
+            Err(CliError { kind: ErrorKind::Device, msg: format!("Found multiple Concept2 drives, pass one of them instead of auto:\n{}",
+                paths.join("\n")) })
+        }
+    }
+}
+
+/// Read workouts from drive, printing warnings for those that can't be parsed.
+fn read_workouts(drive: &Drive) -> Result<Vec<Workout>,CliError> {
+    let (workouts, errors) = drive.workouts_lenient()?;
+
+    for error in errors {
+        eprintln!("{} Skipped workout: {}", "warning:".bold().yellow(), error);
+    }
+
+    Ok(workouts)
+}
+
+/// Output of the info command with --json
+#[derive(Serialize)]
+struct Info {
+    user: UserProfile,
+    workouts: usize,
+    /// sum over the workouts on the drive
+    lifetime_meters: u32,
+    /// as counted by the monitor, from an offset in UserDynamic.bin that
+    /// hasn't been confirmed yet
+    unconfirmed_device_lifetime_meters: Option<u32>,
+    lifetime_kwh: f64,
+    lifetime_kcal: f64,
+    /// in seconds, rest only counts for intervals
+    total_work_time: f64,
+    total_rest_time: f64,
+    first_workout: Option<String>,
+    last_workout: Option<String>,
+    device: Option<DeviceInfo>,
+    firmwares: Vec<String>,
+}
+
+/// Energy of all workouts, skipping any with a non-finite power.
+fn lifetime_kwh(workouts: &[Workout]) -> f64 {
+    workouts.iter()
+        .map(|w| w.watts() * w.total_work_duration.as_secs() as f64 / 3600000.0)
+        .filter(|kwh| kwh.is_finite())
+        // sum() of nothing is -0.0, which would be printed as "-0.000"
+        .fold(0.0, |sum, kwh| sum + kwh)
+}
+
+/// Work and rest time of all workouts, rest only counts for intervals.
+fn total_times(workouts: &[Workout]) -> (std::time::Duration, std::time::Duration) {
+    (workouts.iter().map(|w| w.total_work_duration).sum(), workouts.iter().filter_map(|w| w.total_rest_duration).sum())
+}
+
 fn cmd_info(args: Args) -> Result<(),CliError> {
-    let mut drive = Drive::new(args.arg_device.unwrap(), false)?;
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
 
-    let (user_id, user_name) = drive.user()?;
-    let workouts = drive.workouts()?;
+    let profile = drive.user_profile()?;
+    let workouts = read_workouts(&drive)?;
     let firmwares = drive.firmwares()?;
+    let device_info = drive.device_info().ok();
+    // zero on drives that were set up with init and never used in a monitor
+    let device_lifetime_meters = drive.lifetime_meters().ok().filter(|m| *m > 0);
+
+    let bests = personal_bests(&workouts);
+
+    let lifetime_meters = workouts.iter().map(|w| w.total_distance).sum::<u32>();
+    let lifetime_kwh = lifetime_kwh(&workouts);
+    let imperial = args.flag_imperial;
+    let athlete = AthleteProfile {
+        weight_kg: args.flag_weight.map(|w| if imperial { units::lbs_to_kg(w) } else { w }),
+        ..AthleteProfile::default()
+    }.or(athlete_profile()?);
+    let calorie_model = athlete.calorie_model();
+    let lifetime_kcal = workouts.iter()
+        .map(|w| match &calorie_model {
+            Some(model) => w.calories(model),
+            None => w.total_calories()
+        })
+        .filter(|kcal| kcal.is_finite())
+        .fold(0.0, |sum, kcal| sum + kcal);
+    let (total_work_time, total_rest_time) = total_times(&workouts);
+
+    if args.flag_json {
+        let info = Info {
+            user: profile,
+            workouts: workouts.len(),
+            lifetime_meters,
+            unconfirmed_device_lifetime_meters: device_lifetime_meters,
+            lifetime_kwh,
+            lifetime_kcal,
+            total_work_time: total_work_time.as_secs_f64(),
+            total_rest_time: total_rest_time.as_secs_f64(),
+            first_workout: workouts.first().map(|w| w.datetime.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            last_workout: workouts.last().map(|w| w.datetime.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            device: device_info,
+            firmwares,
+        };
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
 
-    // TODO: include personal bests?
-
-    println!("{:<24}{}", "User Name:".bold().green(), user_name);
-    println!("{:<24}{}", "User ID:".bold().green(), user_id);
+    println!("{:<24}{}", "User Name:".bold().green(), profile.name);
+    println!("{:<24}{}", "User ID:".bold().green(), profile.user_id);
     println!("{:<24}{}", "Workouts:".bold().green(), workouts.len());
-    println!("{:<24}{}", "Lifetime Meters:".bold().green(), workouts.iter().map(|w| w.total_distance).sum::<u32>());
-    println!("{:<24}{:.3}", "Lifetime kWh:".bold().green(), workouts.iter().map(|w| w.watts() * w.total_work_duration.as_secs() as f64 / 3600000.0).sum::<f64>());
-    println!("{:<24}{:.0}", "Lifetime kcal:".bold().green(), workouts.iter().map(|w| w.cal_hr() * w.total_work_duration.as_secs() as f64 / 3600.0).sum::<f64>());
+    if args.flag_imperial {
+        println!("{:<24}{:.2}", "Lifetime Miles:".bold().green(), units::meters_to_miles(lifetime_meters as f64));
+    } else {
+        println!("{:<24}{}", "Lifetime Meters:".bold().green(), lifetime_meters);
+    }
+    // the monitor's count includes workouts that are no longer on the drive,
+    // but where it's stored is only a guess so far
+    if let Some(meters) = device_lifetime_meters {
+        if args.flag_imperial {
+            println!("{:<24}{:.2} (unconfirmed)", "Monitor Miles:".bold().green(), units::meters_to_miles(meters as f64));
+        } else {
+            println!("{:<24}{} (unconfirmed)", "Monitor Meters:".bold().green(), meters);
+        }
+    }
+    println!("{:<24}{:.3}", "Lifetime kWh:".bold().green(), lifetime_kwh);
+    println!("{:<24}{:.0}", "Lifetime kcal:".bold().green(), lifetime_kcal);
+    println!("{:<24}{}", "Total Work Time:".bold().green(), duration_to_string(&total_work_time));
+    println!("{:<24}{}", "Total Rest Time:".bold().green(), duration_to_string(&total_rest_time));
 
-    if workouts.len() > 0 {
+    if !workouts.is_empty() {
         println!("{:<24}{}", "First Workout:".bold().green(), workouts[0].datetime.format("%Y-%m-%d %H:%M"));
         println!("{:<24}{}", "Last Workout:".bold().green(), workouts[workouts.len()-1].datetime.format("%Y-%m-%d %H:%M"));
     }
 
-    if firmwares.len() == 0 {
-        println!("{:<24}{}", "Installed Firmwares:".bold().green(), "none");
+    for (i, best) in bests.iter().enumerate() {
+        let workout = &workouts[best.index];
+        let result = match best.category {
+            PersonalBestCategory::Distance(_) => workout.work_duration_string(),
+            PersonalBestCategory::Time(_) => format!("{}m", workout.total_distance),
+        };
+        println!("{:<24}- {:<8} {:>9} ({})", if i == 0 { "Personal Bests:" } else { "" }.bold().green(),
+            best.category.to_string(), result, workout.datetime.format("%Y-%m-%d"));
+    }
+
+    if let Some(device_info) = device_info {
+        println!("{:<24}{}", "Serial Number:".bold().green(), device_info.serial_number);
+        println!("{:<24}{}", "Current Firmware:".bold().green(), device_info.firmware_version());
+    }
+
+    if firmwares.is_empty() {
+        println!("{:<24}none", "Installed Firmwares:".bold().green());
     }
 
     for (i, firmware) in firmwares.iter().enumerate() {
@@ -224,7 +702,7 @@ fn cmd_init(args: Args) -> Result<(),CliError> {
 
     println!("About to overwrite all data on {}!", &device);
 
-    if !confirm("Proceed?".to_string())? {
+    if !confirm("Proceed?".to_string(), args.flag_yes)? {
         println!("Aborted.");
         return Ok(());
     }
@@ -235,145 +713,1920 @@ fn cmd_init(args: Args) -> Result<(),CliError> {
     Ok(())
 }
 
+fn cmd_set_name(args: Args) -> Result<(),CliError> {
+    let mut drive = open_drive(args.arg_device.unwrap(), true)?;
+    drive.set_user_name(args.arg_name.unwrap())?;
+
+    println!("{}", "Successfully changed user name.".bold().green());
+    Ok(())
+}
+
+/// Parses a date given on the command line.
+fn parse_date(s: &str) -> Result<chrono::NaiveDate,CliError> {
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| CliError { kind: ErrorKind::Other, msg: format!("Invalid date \"{}\", expected YYYY-MM-DD.", s) })
+}
+
+/// Formats a distance in meters, or in yards if `imperial` is set.
+fn distance_string(meters: u32, imperial: bool) -> String {
+    if imperial {
+        format!("{:.0}", units::meters_to_yards(meters as f64))
+    } else {
+        meters.to_string()
+    }
+}
+
+/// Formats a pace per 500m, or converted to a pace per mile.
+fn pace_string(pace: std::time::Duration, per_mile: bool) -> String {
+    if per_mile {
+        duration_to_string(&units::pace_per_mile(pace))
+    } else {
+        duration_to_string(&pace)
+    }
+}
+
+/// Filters on workouts given on the command line
+struct WorkoutFilter {
+    since: Option<chrono::NaiveDate>,
+    until: Option<chrono::NaiveDate>,
+    workout_type: Option<WorkoutType>,
+}
+
+impl WorkoutFilter {
+    fn from_args(args: &Args) -> Result<Self,CliError> {
+        Ok(Self {
+            since: args.flag_since.as_deref().map(parse_date).transpose()?,
+            until: args.flag_until.as_deref().map(parse_date).transpose()?,
+            workout_type: args.flag_type.as_deref().map(str::parse).transpose()?,
+        })
+    }
+
+    fn matches(&self, workout: &Workout) -> bool {
+        self.since.is_none_or(|d| workout.datetime.date() >= d) &&
+            self.until.is_none_or(|d| workout.datetime.date() <= d) &&
+            self.workout_type.is_none_or(|t| workout.workout_type == t)
+    }
+}
+
+/// Returns the last `n` items, or all of them if `n` is `None` or larger than
+/// their number.
+fn last_n<T>(items: &[T], n: Option<usize>) -> &[T] {
+    let n = n.unwrap_or(items.len()).min(items.len());
+    &items[items.len() - n..]
+}
+
+/// Workouts shown by list-workouts with their index in `workouts`: the last
+/// `last` of those matching `filter`, newest first with `desc`.
+fn listed_workouts<'a>(workouts: &'a [Workout], filter: &WorkoutFilter, last: Option<usize>, desc: bool) -> Vec<(usize, &'a Workout)> {
+    // keep the original indices, so they can be passed to show-workouts
+    let selected: Vec<(usize, &Workout)> = workouts.iter().enumerate()
+        .filter(|(_, w)| filter.matches(w))
+        .collect();
+
+    let mut shown = last_n(&selected, last).to_vec();
+    if desc {
+        shown.reverse();
+    }
+    shown
+}
+
 /// list-workouts command
 fn cmd_list_workouts(args: Args) -> Result<(),CliError> {
-    let mut drive = Drive::new(args.arg_device.unwrap(), false)?;
+    let filter = WorkoutFilter::from_args(&args)?;
+
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
 
-    let workouts = drive.workouts()?;
+    let workouts = read_workouts(&drive)?;
+    if workouts.is_empty() {
+        println!("No workouts on this drive.");
+        return Ok(());
+    }
 
-    // TODO: highlight personal bests?
+    let bests: Vec<usize> = personal_bests(&workouts).iter().map(|b| b.index).collect();
+    let calorie_model = athlete_profile()?.calorie_model();
 
-    println!("{}", format!("{:>3} {:16} {:17} {:5} {:9} {:9} {:>3} {:>6} {:>3} {:>3} {:>6}",
-        "#", "Date", "Type", "Dist.", "Work Time", "Rest Time", "SPM", "Pace",
-        "HR", "W", "kcal/h").bold().green());
-    println!("{}", String::from_utf8(vec![b'='; 90]).unwrap().truecolor(0x7f,0x7f,0x7f));
+    let mut table = Table::new(&[
+        ("#", Align::Right), ("Date", Align::Left), ("Type", Align::Left),
+        ("Dist.", Align::Right), ("Work Time", Align::Right), ("Rest Time", Align::Right),
+        ("SPM", Align::Right), ("Pace", Align::Right), ("HR", Align::Right),
+        ("W", Align::Right), ("kcal/h", Align::Right), ("kcal", Align::Right)
+    ]);
 
-    let last = args.flag_last.unwrap_or(workouts.len()) as usize;
-    for (i, workout) in workouts[workouts.len()-last..].iter().enumerate() {
-        println!("{:>3} {:16} {:17} {:>5} {:>9} {:>9} {:>3} {:>6} {:>3} {:>3.0} {:>6.0}",
-            i + (workouts.len() - last) + 1,
-            workout.datetime.format("%Y-%m-%d %H:%M"),
+    for (i, workout) in listed_workouts(&workouts, &filter, args.flag_last, args.flag_desc) {
+        let cells = vec![
+            (i + 1).to_string(),
+            workout.datetime.format("%Y-%m-%d %H:%M").to_string(),
             workout.workout_type.to_string(),
-            workout.total_distance,
+            distance_string(workout.total_distance, args.flag_imperial),
             workout.work_duration_string(),
             workout.rest_duration_string(),
-            workout.spm.map(|s| s.to_string()).unwrap_or_default(),
-            workout.pace_string(),
+            workout.spm.or_else(|| workout.average_spm()).map(|s| s.to_string()).unwrap_or_default(),
+            pace_string(workout.pace(), args.flag_pace_per_mile),
             workout.heart_rate().map(|h| h.to_string()).unwrap_or_default(),
-            workout.watts(),
-            workout.cal_hr(),
-        );
+            format!("{:.0}", workout.watts()),
+            format!("{:.0}", calorie_model.as_ref().map_or_else(|| workout.cal_hr(), |m| workout.personal_cal_hr(m))),
+            format!("{:.0}", calorie_model.as_ref().map_or_else(|| workout.total_calories(), |m| workout.calories(m))),
+        ];
+
+        // highlight personal bests
+        if bests.contains(&i) {
+            table.highlighted_row(cells);
+        } else {
+            table.row(cells);
+        }
     }
 
+    // colored knows whether NO_COLOR is set
+    table.print(colored::control::SHOULD_COLORIZE.should_colorize());
     Ok(())
 }
 
-fn select_latest_versions(versions: Vec<FirmwareVersion>, beta: bool) -> Vec<FirmwareVersion> {
-    let mut latest: HashMap<String,FirmwareVersion> = HashMap::new();
+/// Line of a hex dump, see `annotated_hex`
+#[derive(Debug, PartialEq)]
+struct HexLine {
+    offset: usize,
+    /// field name, only on the first line of a field
+    label: String,
+    hex: String,
+    unknown: bool
+}
 
-    for version in versions {
-        if !(version.status == "public" || (beta && version.status == "beta")) {
-            continue;
+/// Lays out raw record bytes in hex, one field per line, 16 bytes at most per
+/// line.
+fn annotated_hex(data: &[u8], fields: &[RecordField]) -> Vec<HexLine> {
+    let mut lines = Vec::new();
+    for field in fields {
+        let name = match field.frame {
+            Some(i) => format!("frames[{}].{}", i, field.name),
+            None => field.name.to_string()
+        };
+
+        let bytes = &data[field.offset..(field.offset + field.size)];
+        for (i, chunk) in bytes.chunks(16).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            lines.push(HexLine {
+                offset: field.offset + i * 16,
+                label: if i == 0 { name.clone() } else { String::new() },
+                hex: hex.join(" "),
+                unknown: field.is_unknown()
+            });
         }
+    }
+    lines
+}
 
-        let monitor = version.monitor.to_lowercase();
-
-        if latest.contains_key(&monitor) && version.version < latest[&monitor].version {
-            continue;
+/// Prints raw record bytes in hex, see `annotated_hex`. Unknown fields are
+/// highlighted.
+fn print_annotated_hex(data: &[u8], fields: &[RecordField]) {
+    for line in annotated_hex(data, fields) {
+        let offset = format!("0x{:04x}", line.offset);
+        if line.unknown {
+            println!("{} {:32} {}", offset.truecolor(0x7f,0x7f,0x7f), line.label.yellow(), line.hex.yellow());
+        } else {
+            println!("{} {:32} {}", offset.truecolor(0x7f,0x7f,0x7f), line.label.bold().green(), line.hex);
         }
+    }
+}
 
-        latest.insert(monitor, version);
+/// check command
+fn cmd_check(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+
+    let count = drive.workout_count()?;
+    let issues = drive.validate()?;
+    if issues.is_empty() {
+        println!("Checked {} workouts, no problems found.", count);
+        return Ok(());
     }
 
-    latest.values().cloned().collect()
+    for issue in &issues {
+        println!("{:<24}{}", format!("Workout {}:", issue.index() + 1).bold().yellow(), issue);
+    }
+
+    let mut affected: Vec<usize> = issues.iter().map(|i| i.index()).collect();
+    affected.dedup();
+    Err(CliError {
+        kind: ErrorKind::Parse,
+        msg: format!("Found problems with {} of {} workouts.", affected.len(), count)
+    })
 }
 
-fn cmd_update_firmware(args: Args) -> Result<(),CliError> {
-    let versions = update_firmware_cache()?;
+/// inspect command
+fn cmd_inspect(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+
+    // indices don't need the records to be parsed, so broken ones can be
+    // inspected as well
+    let count = drive.workout_count()?;
+    let index = match args.arg_workout {
+        Some(selector) => match selector.parse()? {
+            WorkoutSelector::Index(i) => Some(i).filter(|i| *i >= 1 && *i <= count).map(|i| i - 1),
+            selector => selector.position(&drive.workouts()?)
+        }.ok_or(CliError { kind: ErrorKind::Other, msg: "No matching workout found.".to_string() })?,
+        None => count.checked_sub(1)
+            .ok_or(CliError { kind: ErrorKind::Other, msg: "No workouts on this drive.".to_string() })?
+    };
 
-    let mut drive = Drive::new(args.arg_device.unwrap(), true)?;
-    let mut firmwares = drive.firmwares()?;
-    firmwares.sort();
+    let record = drive.raw_workout_record(index)?;
 
-    if firmwares.len() == 0 {
-        println!("\nFirmwares currently stored on drive: none");
-    } else {
-        println!("\nFirmwares currently stored on drive:");
-        for firmware in firmwares {
-            println!("    - {}", firmware);
+    println!("{}", format!("LogDataAccessTbl.bin at 0x{:x}", record.access_table_offset).bold());
+    print_annotated_hex(&record.access_table_entry, &native::access_table_fields(&record.access_table_entry));
+
+    println!();
+    println!("{}", format!("LogDataStorage.bin at 0x{:x}", record.storage_offset).bold());
+    print_annotated_hex(&record.storage_record, &native::storage_record_fields(&record.storage_record));
+
+    Ok(())
+}
+
+/// favorites command
+fn cmd_favorites(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+
+    let favorites = drive.favorites()?;
+
+    if args.flag_json {
+        println!("{}", serde_json::to_string_pretty(&favorites)?);
+        return Ok(());
+    }
+
+    println!("{}", format!("{:>3} {:17} {:>8} {:>8}", "#", "Type", "Target", "Split").bold().green());
+    println!("{}", String::from_utf8(vec![b'='; 39]).unwrap().truecolor(0x7f,0x7f,0x7f));
+
+    for (i, favorite) in favorites.iter().enumerate() {
+        let workout_type = match favorite.workout_type {
+            Some(t) => t.to_string(),
+            None => format!("Unknown (0x{:02x})", favorite.type_code)
+        };
+
+        // only distances are known to be in meters
+        let (target, split) = match favorite.workout_type {
+            Some(WorkoutType::SingleDistance) | Some(WorkoutType::DistanceInterval) => {
+                (format!("{}m", favorite.target), format!("{}m", favorite.split))
+            },
+            _ => (favorite.target.to_string(), favorite.split.to_string())
+        };
+
+        println!("{:>3} {:17} {:>8} {:>8}", i + 1, workout_type, target, split);
+    }
+
+    Ok(())
+}
+
+/// stats command
+fn cmd_stats(args: Args) -> Result<(),CliError> {
+    let granularity: Granularity = args.flag_by.parse()?;
+    let filter = WorkoutFilter::from_args(&args)?;
+
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+
+    let workouts: Vec<Workout> = read_workouts(&drive)?.into_iter()
+        .filter(|w| filter.matches(w))
+        .collect();
+    // relative power is only shown if the weight is known
+    let weight_kg = athlete_profile()?.weight_kg;
+
+    let mut header = format!("{:8} {:>8} {:>8} {:>10} {:>6}",
+        "Period", "Workouts", "Dist.", "Work Time", "Pace");
+    if weight_kg.is_some() {
+        header += &format!(" {:>5}", "W/kg");
+    }
+    println!("{}", header.bold().green());
+    println!("{}", String::from_utf8(vec![b'='; header.len()]).unwrap().truecolor(0x7f,0x7f,0x7f));
+
+    for summary in summarize(&workouts, granularity) {
+        let mut line = format!("{:8} {:>8} {:>8} {:>10} {:>6}",
+            summary.period_string(),
+            summary.workouts,
+            distance_string(summary.total_distance, args.flag_imperial),
+            duration_to_string(&summary.total_work_duration),
+            pace_string(summary.average_pace_500m(), args.flag_pace_per_mile),
+        );
+        if let Some(weight_kg) = weight_kg {
+            let watts_per_kg = summary.watts_per_kg(weight_kg).map(|w| format!("{:.2}", w)).unwrap_or_default();
+            line += &format!(" {:>5}", watts_per_kg);
         }
+        println!("{}", line);
     }
 
-    // filter firmwares, selecting only the most recent versions for each monitor
-    let mut to_install: Vec<String> = select_latest_versions(versions, args.flag_beta).iter()
-        // only consider pm5 firmwares
-        .filter(|v| &v.monitor.to_lowercase()[0..3] == "pm5")
-        // skip pm5v3 for now because i'm not sure what's up with that
-        .filter(|v| v.monitor.len() < 5 || &v.monitor.to_lowercase()[0..5] != "pm5v3")
-        // find the default file for firmware
-        .map(|v| v.files.iter().find(|f| f.default))
-        .filter(|f| f.is_some())
-        .map(|f| f.unwrap().name.clone())
+    let distribution = pace_distribution(&workouts);
+    if let (Some(min), Some(max)) = (distribution.min(), distribution.max()) {
+        let per_mile = args.flag_pace_per_mile;
+        let percentile = |p| pace_string(distribution.percentile(p).unwrap_or_default(), per_mile);
+
+        println!();
+        println!("{:<24}{}", "Fastest Pace:".bold().green(), pace_string(min, per_mile));
+        println!("{:<24}{}", "10th Percentile Pace:".bold().green(), percentile(10.0));
+        println!("{:<24}{}", "25th Percentile Pace:".bold().green(), percentile(25.0));
+        println!("{:<24}{}", "Median Pace:".bold().green(), percentile(50.0));
+        println!("{:<24}{}", "75th Percentile Pace:".bold().green(), percentile(75.0));
+        println!("{:<24}{}", "90th Percentile Pace:".bold().green(), percentile(90.0));
+        println!("{:<24}{}", "Slowest Pace:".bold().green(), pace_string(max, per_mile));
+    }
+
+    Ok(())
+}
+
+/// Reads the athlete profile from `concept2drive/profile.toml` in the config
+/// directory. Without the file, all fields are empty.
+fn athlete_profile() -> Result<AthleteProfile,CliError> {
+    read_profile(xdg::BaseDirectories::new()?.find_config_file(Path::new("concept2drive").join("profile.toml")))
+}
+
+/// Reads the athlete profile from `path`, see `athlete_profile`.
+fn read_profile(path: Option<PathBuf>) -> Result<AthleteProfile,CliError> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(AthleteProfile::default())
+    };
+
+    let contents = std::fs::read_to_string(&path)?;
+    AthleteProfile::parse(&contents)
+        .map_err(|e| CliError { kind: ErrorKind::Parse, msg: format!("Invalid profile {}: {}", path.display(), e) })
+}
+
+/// Returns the access token for the online logbook. A token given on the
+/// command line is stored in the config directory for later uploads.
+fn logbook_token(token: Option<String>) -> Result<String,CliError> {
+    let path = xdg::BaseDirectories::new()?
+        .place_config_file(Path::new("concept2drive").join("logbook_token"))?;
+
+    if let Some(token) = token {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        options.open(&path)?.write_all(token.as_bytes())?;
+        return Ok(token);
+    }
+
+    match std::fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
+        _ => Err(CliError { kind: ErrorKind::Other, msg: "No access token for the logbook stored, pass one with --token.".to_string() })
+    }
+}
+
+/// upload command
+fn cmd_upload(args: Args) -> Result<(),CliError> {
+    let token = logbook_token(args.flag_token)?;
+
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+    let workouts = read_workouts(&drive)?;
+
+    let dates = workouts.iter().map(|w| w.datetime.date());
+    let (from, to) = match (dates.clone().min(), dates.max()) {
+        (Some(from), Some(to)) => (from, to),
+        _ => {
+            println!("No workouts on this drive.");
+            return Ok(());
+        }
+    };
+
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+    let existing = rt.block_on(with_retries("logbook results", || ExistingResult::download(LOGBOOK_API, &token, from, to)))?;
+    let new: Vec<&Workout> = workouts.iter()
+        .filter(|w| !existing.iter().any(|e| e.matches(w)))
         .collect();
-    to_install.sort();
 
-    println!("\nAbout to clear currently stored firmwares and install the following ones:");
-    for firmware in &to_install {
-        println!("    - {}", firmware);
+    if new.is_empty() {
+        println!("All {} workouts are in the logbook already.", workouts.len());
+        return Ok(());
     }
 
-    if !confirm("\nProceed?".to_string())? {
+    println!("About to upload {} workouts to the logbook ({} are in it already).",
+        new.len(), workouts.len() - new.len());
+
+    if !confirm("Proceed?".to_string(), args.flag_yes)? {
         println!("Aborted.");
         return Ok(());
     }
 
-    println!("\nClearing firmwares...");
-    drive.clear_firmwares()?;
-    println!("Writing firmwares...");
-    for firmware in &to_install {
-        let mut template = "{spinner:.bold.green} ".to_string();
-        template += &format!("{:47}", firmware);
-        template += " [{bar:40.bold.green/white}] {bytes}/{total_bytes} ({eta})";
-
-        let pb = indicatif::ProgressBar::new(1);
-        pb.set_style(indicatif::ProgressStyle::default_bar()
-         .template(&template)
-         .progress_chars("##-"));
+    // uploads aren't retried, a request that timed out may still have
+    // created the result
+    for workout in &new {
+        rt.block_on(LogbookResult::from_workout(workout).upload(LOGBOOK_API, &token)).map_err(|e| CliError {
+            kind: ErrorKind::Network,
+            msg: format!("Failed to upload workout from {}: {}", workout.datetime.format("%Y-%m-%d %H:%M"), e)
+        })?;
+    }
 
-        let local_path = xdg::BaseDirectories::new()?
-            .place_cache_file(Path::new("concept2drive").join("firmware").join(&firmware))?;
+    println!("\n{}", format!("Uploaded {} workouts.", new.len()).bold().green());
+    Ok(())
+}
 
-        drive.write_firmware_callback(local_path, |written, total| {
-            pb.set_position(written as u64);
-            pb.set_length(total as u64);
-        })?;
+/// detect command
+fn cmd_detect() -> Result<(),CliError> {
+    let drives = find_concept2_drives();
+    if drives.is_empty() {
+        return Err(CliError { kind: ErrorKind::Device, msg: "No mounted Concept2 drive found.".to_string() });
+    }
 
-        pb.finish();
+    for drive in drives {
+        println!("{}", drive.display());
     }
 
     Ok(())
 }
 
-fn main() {
-    let args: Args = Docopt::new(USAGE)
-        .map(|d| d.version(Some(VERSION.into())))
-        .and_then(|d| d.deserialize())
-        .unwrap_or_else(|e| e.exit());
+/// backup command
+fn cmd_backup(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
 
-    let result = if args.cmd_info {
-        cmd_info(args)
-    } else if args.cmd_init {
-        cmd_init(args)
-    } else if args.cmd_list_workouts {
-        cmd_list_workouts(args)
-    } else if args.cmd_update_firmware {
-        cmd_update_firmware(args)
-    } else {
-        Ok(())
-    };
+    let file = std::fs::File::create(args.arg_file.unwrap())?;
+    let count = drive.backup(std::io::BufWriter::new(file))?;
 
-    if let Err(e) = result {
-        println!("{} {}", "error:".bold().red(), e.msg);
-        std::process::exit(1);
+    println!("Backed up {} files.", count);
+    Ok(())
+}
+
+/// restore command
+fn cmd_restore(args: Args) -> Result<(),CliError> {
+    let file = std::fs::File::open(args.arg_file.unwrap())?;
+    let device = resolve_device(args.arg_device.unwrap())?;
+    let mut drive = Drive::new(&device, true)?;
+
+    println!("About to overwrite the logbook on {} with the backup!", &device);
+
+    if !confirm("Proceed?".to_string(), args.flag_yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let count = drive.restore(std::io::BufReader::new(file))?;
+
+    println!("\n{}", format!("Successfully restored {} files.", count).bold().green());
+    Ok(())
+}
+
+/// prune command
+fn cmd_prune(args: Args) -> Result<(),CliError> {
+    let date = parse_date(&args.arg_date.unwrap())?;
+    let mut drive = open_drive(args.arg_device.unwrap(), true)?;
+
+    let count = read_workouts(&drive)?.iter()
+        .filter(|w| w.datetime.date() < date)
+        .count();
+    if count == 0 {
+        println!("No workouts before {}.", date);
+        return Ok(());
+    }
+
+    println!("About to delete {} workouts before {} from the drive!", count, date);
+
+    if !confirm("Proceed?".to_string(), args.flag_yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let count = drive.prune_before(date)?;
+
+    println!("\n{}", format!("Successfully deleted {} workouts.", count).bold().green());
+    Ok(())
+}
+
+/// Find the workout given on the command line, or the last one if none was
+/// given.
+fn select_workout(drive: &Drive, workout: Option<String>) -> Result<Workout,CliError> {
+    let workout = match workout {
+        Some(selector) => {
+            let selector: WorkoutSelector = selector.parse()?;
+            let workouts = read_workouts(drive)?;
+            let i = selector.position(&workouts)
+                .ok_or(CliError { kind: ErrorKind::Other, msg: "No matching workout found.".to_string() })?;
+            workouts.into_iter().nth(i)
+        },
+        None => drive.last_workout()?
+    };
+
+    workout.ok_or(CliError { kind: ErrorKind::Other, msg: "No workouts on this drive.".to_string() })
+}
+
+/// show-workouts command
+fn cmd_show_workouts(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+
+    let workout = select_workout(&drive, args.arg_workout)?;
+    let athlete = AthleteProfile { max_hr: args.flag_max_hr, ..AthleteProfile::default() }
+        .or(athlete_profile()?);
+    let calorie_model = athlete.calorie_model();
+
+    if args.flag_json {
+        println!("{}", serde_json::to_string_pretty(&workout)?);
+        return Ok(());
+    }
+
+    println!("{:<24}{}", "Date:".bold().green(), workout.datetime.format("%Y-%m-%d %H:%M"));
+    println!("{:<24}{}", "Type:".bold().green(), workout.workout_type);
+    println!("{:<24}{}", "Machine:".bold().green(), workout.machine);
+    if let Some(name) = workout.name {
+        println!("{:<24}{}", "Program:".bold().green(), name);
+    }
+    println!("{:<24}{}", "Distance:".bold().green(), workout.total_distance);
+    println!("{:<24}{}", "Work Time:".bold().green(), workout.work_duration_string());
+    if workout.total_rest_duration.is_some() {
+        println!("{:<24}{}", "Rest Time:".bold().green(), workout.rest_duration_string());
+    }
+    if let Some(rest) = workout.rest_interval {
+        println!("{:<24}{}", "Rest Interval:".bold().green(), duration_to_string(&rest));
+    }
+    println!("{:<24}{}", "Pace:".bold().green(), workout.pace_string());
+    if let (Some(fastest), Some(slowest)) = (workout.fastest_split(), workout.slowest_split()) {
+        println!("{:<24}{} (#{})", "Fastest Split:".bold().green(), fastest.pace_string(), fastest.index);
+        println!("{:<24}{} (#{})", "Slowest Split:".bold().green(), slowest.pace_string(), slowest.index);
+    }
+    if let Some(std_dev) = workout.pace_std_dev() {
+        println!("{:<24}±{:.1}s", "Pace Std. Dev.:".bold().green(), std_dev.as_secs_f64());
+    }
+    if let Some(spm) = workout.spm.or_else(|| workout.average_spm()) {
+        println!("{:<24}{}", "SPM:".bold().green(), spm);
+    }
+    if let Some(max_spm) = workout.max_spm() {
+        println!("{:<24}{}", "Max SPM:".bold().green(), max_spm);
+    }
+    if let Some(hr) = workout.heart_rate() {
+        println!("{:<24}{}", "Heart Rate:".bold().green(), hr);
+    }
+    if let (Some(min), Some(max)) = (workout.min_heart_rate(), workout.max_heart_rate()) {
+        println!("{:<24}{}-{}", "Heart Rate Range:".bold().green(), min, max);
+    }
+    println!("{:<24}{:.0}", "Watts:".bold().green(), workout.watts());
+    if let Some(watts_per_kg) = athlete.weight_kg.and_then(|w| workout.watts_per_kg(w)) {
+        println!("{:<24}{:.2}", "W/kg:".bold().green(), watts_per_kg);
+    }
+    if let Some(peak) = workout.peak_power() {
+        println!("{:<24}{:.0}", "Avg. Frame Watts:".bold().green(), workout.average_power());
+        println!("{:<24}{:.0}", "Peak Frame Watts:".bold().green(), peak.watts(workout.machine));
+    }
+    println!("{:<24}{:.0}", "kcal/h:".bold().green(),
+        calorie_model.as_ref().map_or_else(|| workout.cal_hr(), |m| workout.personal_cal_hr(m)));
+    println!("{:<24}{:.0}", "kcal:".bold().green(),
+        calorie_model.as_ref().map_or_else(|| workout.total_calories(), |m| workout.calories(m)));
+
+    let has_heart_rate = workout.frames.iter().any(|f| f.work_heart_rate.is_some());
+    if let (Some(max_hr), true) = (athlete.max_heart_rate(), has_heart_rate) {
+        println!();
+        for (i, duration) in workout.heart_rate_zones(max_hr).iter().enumerate() {
+            let label = format!("Zone {} ({}-{}%):", i + 1, 50 + i * 10, 60 + i * 10);
+            println!("{:<24}{}", label.bold().green(), duration_to_string(duration));
+        }
+
+        let without = workout.duration_without_heart_rate();
+        if without > std::time::Duration::default() {
+            println!("{:<24}{}", "No Heart Rate:".bold().green(), duration_to_string(&without));
+        }
+    }
+
+    if workout.frames.is_empty() {
+        return Ok(());
+    }
+
+    // rest heart rate is only recorded for intervals
+    let is_interval = workout.total_rest_duration.is_some();
+    let has_drag_factor = workout.frames.iter().any(|f| f.drag_factor.is_some());
+
+    println!();
+    let mut header = format!("{:>3} {:>5} {:>9} {:>9} {:>6} {:>3} {:>3} {:>4}",
+        "#", "Dist.", "Work Time", "Rest Time", "Pace", "SPM", "HR", "kcal");
+    if is_interval {
+        header += &format!(" {:>7}", "Rest HR");
+    }
+    if has_drag_factor {
+        header += &format!(" {:>4}", "Drag");
+    }
+    println!("{}", header.bold().green());
+    println!("{}", String::from_utf8(vec![b'='; header.len() + 1]).unwrap().truecolor(0x7f,0x7f,0x7f));
+
+    for (i, frame) in workout.frames.iter().enumerate() {
+        let mut line = format!("{:>3} {:>5} {:>9} {:>9} {:>6} {:>3} {:>3} {:>4.0}",
+            i + 1,
+            frame.distance,
+            frame.work_duration_string(),
+            frame.rest_duration_string(),
+            frame.pace_string(),
+            frame.spm,
+            frame.work_heart_rate.map(|h| h.to_string()).unwrap_or_default(),
+            calorie_model.as_ref().map_or_else(|| frame.calories(workout.machine), |m| frame.personal_calories(workout.machine, m)),
+        );
+        if is_interval {
+            line += &format!(" {:>7}", frame.rest_heart_rate.map(|h| h.to_string()).unwrap_or_default());
+        }
+        if has_drag_factor {
+            line += &format!(" {:>4}", frame.drag_factor.map(|d| d.to_string()).unwrap_or_default());
+        }
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+/// Returns the difference between two durations, e.g. `+0:01.5`.
+fn duration_delta_string(a: std::time::Duration, b: std::time::Duration) -> String {
+    if a >= b {
+        format!("+{}", duration_to_string(&(a - b)))
+    } else {
+        format!("-{}", duration_to_string(&(b - a)))
+    }
+}
+
+/// Returns the difference between two optional values, e.g. `-3`, or an
+/// empty string if either is missing.
+fn value_delta_string(a: Option<u32>, b: Option<u32>) -> String {
+    match (a, b) {
+        (Some(a), Some(b)) => format!("{:+}", a as i64 - b as i64),
+        _ => String::new()
+    }
+}
+
+/// compare command
+fn cmd_compare(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+    let workouts = read_workouts(&drive)?;
+
+    let select = |selector: String| -> Result<&Workout,CliError> {
+        selector.parse::<WorkoutSelector>()?.position(&workouts)
+            .map(|i| &workouts[i])
+            .ok_or(CliError { kind: ErrorKind::Other, msg: format!("No workout matching \"{}\" found.", selector) })
+    };
+    let workout = select(args.arg_workout.unwrap())?;
+    let other = select(args.arg_other.unwrap())?;
+
+    let header = format!("{:<24}{:>17} {:>17} {:>9}", "", "Workout", "Other", "Delta");
+    println!("{}", header.bold().green());
+    println!("{}", String::from_utf8(vec![b'='; header.len()]).unwrap().truecolor(0x7f,0x7f,0x7f));
+
+    let row = |label: &str, a: String, b: String, delta: String| {
+        println!("{}{:>17} {:>17} {:>9}", format!("{:<24}", label).bold().green(), a, b, delta);
+    };
+    row("Date:", workout.datetime.format("%Y-%m-%d %H:%M").to_string(),
+        other.datetime.format("%Y-%m-%d %H:%M").to_string(), String::new());
+    row("Type:", workout.workout_type.to_string(), other.workout_type.to_string(), String::new());
+    row("Distance:", workout.total_distance.to_string(), other.total_distance.to_string(),
+        value_delta_string(Some(workout.total_distance), Some(other.total_distance)));
+    row("Work Time:", workout.work_duration_string(), other.work_duration_string(),
+        duration_delta_string(workout.total_work_duration, other.total_work_duration));
+    row("Pace:", workout.pace_string(), other.pace_string(),
+        duration_delta_string(workout.pace(), other.pace()));
+    let (spm, other_spm) = (workout.spm.or_else(|| workout.average_spm()), other.spm.or_else(|| other.average_spm()));
+    row("SPM:", spm.map(|s| s.to_string()).unwrap_or_default(),
+        other_spm.map(|s| s.to_string()).unwrap_or_default(), value_delta_string(spm, other_spm));
+    row("Heart Rate:", workout.heart_rate().map(|h| h.to_string()).unwrap_or_default(),
+        other.heart_rate().map(|h| h.to_string()).unwrap_or_default(),
+        value_delta_string(workout.heart_rate(), other.heart_rate()));
+    row("Watts:", format!("{:.0}", workout.watts()), format!("{:.0}", other.watts()),
+        format!("{:+.0}", workout.watts() - other.watts()));
+
+    println!();
+    if !workout.same_splits(other) {
+        println!("The splits can't be compared, the workouts weren't recorded with the same splits.");
+        return Ok(());
+    }
+
+    let header = format!("{:>3} {:>5} {:>9} {:>9} {:>6} {:>9} {:>3} {:>3} {:>3} {:>3}",
+        "#", "Dist.", "Time", "Delta", "Pace", "Delta", "SPM", "+/-", "HR", "+/-");
+    println!("{}", header.bold().green());
+    println!("{}", String::from_utf8(vec![b'='; header.len()]).unwrap().truecolor(0x7f,0x7f,0x7f));
+
+    for (a, b) in workout.splits().iter().zip(other.splits().iter()) {
+        println!("{}", split_comparison(a, b));
+    }
+
+    Ok(())
+}
+
+/// Returns a line of the split table of the compare command, with the deltas
+/// of split `a` to split `b`.
+fn split_comparison(a: &SplitSummary, b: &SplitSummary) -> String {
+    format!("{:>3} {:>5} {:>9} {:>9} {:>6} {:>9} {:>3} {:>3} {:>3} {:>3}",
+        a.index,
+        a.distance,
+        a.duration_string(),
+        duration_delta_string(a.duration, b.duration),
+        a.pace_string(),
+        duration_delta_string(a.pace, b.pace),
+        a.spm,
+        value_delta_string(Some(a.spm), Some(b.spm)),
+        a.heart_rate.map(|h| h.to_string()).unwrap_or_default(),
+        value_delta_string(a.heart_rate, b.heart_rate),
+    )
+}
+
+/// export command
+fn cmd_export(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+
+    // a single workout is picked the same way as for show-workouts
+    let workouts = match args.arg_workout {
+        Some(selector) => vec![select_workout(&drive, Some(selector))?],
+        None => read_workouts(&drive)?
+    };
+
+    write_export(&workouts, &args.flag_format, Path::new(&args.arg_file.unwrap()))?;
+
+    println!("Exported {} {}.", workouts.len(), if workouts.len() == 1 { "workout" } else { "workouts" });
+    Ok(())
+}
+
+/// Writes workouts to a file in one of the formats of the export command.
+fn write_export(workouts: &[Workout], format: &str, path: &Path) -> Result<(),CliError> {
+    let writer = match format {
+        "csv" => concept2drive::export::write_csv,
+        "concept2" => concept2drive::export::write_concept2_csv,
+        "tcx" => concept2drive::export::write_tcx,
+        "json" => concept2drive::export::write_json,
+        format => return Err(CliError { kind: ErrorKind::Other, msg: format!("Unknown export format \"{}\".", format) })
+    };
+
+    let file = std::fs::File::create(path)?;
+    writer(workouts, std::io::BufWriter::new(file))?;
+    Ok(())
+}
+
+/// merge command
+fn cmd_merge(args: Args) -> Result<(),CliError> {
+    let path = PathBuf::from(args.arg_file.unwrap());
+    let format = match path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => "csv",
+        Some("json") => "json",
+        Some("tcx") => "tcx",
+        _ => return Err(CliError { kind: ErrorKind::Other, msg: format!(
+            "Can't tell the export format from {}, use .csv, .json or .tcx.", path.display()) })
+    };
+
+    let mut workouts = Vec::new();
+    for device in &args.arg_devices {
+        let drive = open_drive(device.clone(), false)?;
+        workouts.extend(read_workouts(&drive)?);
+    }
+
+    let total = workouts.len();
+    workouts.sort_by_key(|w| w.datetime);
+    let workouts = dedup_workouts(workouts);
+
+    write_export(&workouts, format, &path)?;
+
+    println!("Exported {} workouts from {} drives, skipped {} duplicates.",
+        workouts.len(), args.arg_devices.len(), total - workouts.len());
+    Ok(())
+}
+
+fn select_latest_versions(versions: Vec<FirmwareVersion>, beta: bool) -> Vec<FirmwareVersion> {
+    let mut latest: HashMap<String,FirmwareVersion> = HashMap::new();
+
+    for version in versions {
+        if !(version.status == "public" || (beta && version.status == "beta")) {
+            continue;
+        }
+
+        let monitor = version.monitor.to_lowercase();
+
+        if latest.contains_key(&monitor) && version.version < latest[&monitor].version {
+            continue;
+        }
+
+        latest.insert(monitor, version);
+    }
+
+    latest.values().cloned().collect()
+}
+
+/// Returns the sorted file names of the most recent firmwares for the monitor
+/// family. With `keep`, firmwares already in `stored` are left out.
+fn firmwares_to_install(versions: Vec<FirmwareVersion>, family: &str, beta: bool, stored: &[String], keep: bool) -> Vec<String> {
+    let mut to_install: Vec<String> = select_latest_versions(versions, beta).iter()
+        // only consider firmwares for the selected monitor
+        .filter(|v| monitor_matches(&v.monitor, family))
+        // find the default file for firmware
+        .map(|v| v.files.iter().find(|f| f.default))
+        .filter(|f| f.is_some())
+        .map(|f| f.unwrap().name.clone())
+        // when keeping the stored firmwares, only add missing ones
+        .filter(|name| !keep || !stored.contains(name))
+        .collect();
+    to_install.sort();
+    to_install
+}
+
+/// Returns true if firmwares for `monitor`, as named by the API (e.g. "PM5"
+/// or "PM5v3"), belong to the monitor family `family`. The PM5v3 is a
+/// hardware revision of the PM5, so "pm5" includes it, while "pm5v3" only
+/// matches its own firmwares.
+fn monitor_matches(monitor: &str, family: &str) -> bool {
+    monitor.to_lowercase().starts_with(family)
+}
+
+/// Detects the monitor family from the firmware version the monitor
+/// reported in `DeviceLogInfo.bin`. The families number their firmwares
+/// differently, so the family that has firmwares with the same major version
+/// is picked. Without a unique match, firmwares for all PM5 revisions are
+/// installed, since the PM5 is the only monitor that uses USB drives.
+fn detect_monitor(device_info: Option<&DeviceInfo>, versions: &[FirmwareVersion]) -> String {
+    let mut families: Vec<String> = device_info.map(|info| versions.iter()
+        .filter(|v| v.major_version == info.firmware_major as u32)
+        .map(|v| v.monitor.to_lowercase())
+        .collect()).unwrap_or_default();
+    families.sort();
+    families.dedup();
+
+    match &families[..] {
+        [family] => family.clone(),
+        _ => "pm5".to_string()
+    }
+}
+
+fn cmd_update_firmware(args: Args) -> Result<(),CliError> {
+    if let Some(monitor) = &args.flag_monitor {
+        if !["pm3", "pm4", "pm5", "pm5v3"].contains(&monitor.to_lowercase().as_str()) {
+            return Err(CliError { kind: ErrorKind::Other, msg: format!("Invalid monitor \"{}\", expected pm3, pm4, pm5 or pm5v3.", monitor) });
+        }
+    }
+
+    let keep = args.flag_no_clear;
+    let versions = update_firmware_cache()?;
+
+    let mut drive = Drive::new(resolve_device(args.arg_device.unwrap())?, true)?;
+
+    let family = match &args.flag_monitor {
+        Some(monitor) => monitor.to_lowercase(),
+        None => {
+            let family = detect_monitor(drive.device_info().ok().as_ref(), &versions);
+            println!("Installing firmwares for {}, use --monitor to choose another monitor.", family.to_uppercase());
+            family
+        }
+    };
+
+    let mut firmwares = drive.firmwares()?;
+    firmwares.sort();
+
+    if firmwares.is_empty() {
+        println!("\nFirmwares currently stored on drive: none");
+    } else {
+        println!("\nFirmwares currently stored on drive:");
+        for firmware in &firmwares {
+            println!("    - {}", firmware);
+        }
+    }
+
+    let to_install = firmwares_to_install(versions, &family, args.flag_beta, &firmwares, keep);
+
+    if to_install.is_empty() {
+        println!("\nAll firmwares are already stored on the drive.");
+        return Ok(());
+    }
+
+    if keep {
+        println!("\nAbout to install the following firmwares, keeping the stored ones:");
+    } else {
+        println!("\nAbout to clear currently stored firmwares and install the following ones:");
+    }
+    for firmware in &to_install {
+        println!("    - {}", firmware);
+    }
+
+    if !confirm("\nProceed?".to_string(), args.flag_yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    if !keep {
+        println!("\nClearing firmwares...");
+        drive.clear_firmwares()?;
+    }
+    println!("Writing firmwares...");
+    for firmware in &to_install {
+        let local_path = xdg::BaseDirectories::new()?
+            .place_cache_file(Path::new("concept2drive").join("firmware").join(firmware))?;
+
+        write_firmware_progress(&mut drive, &local_path)?;
+    }
+
+    Ok(())
+}
+
+/// Write a firmware archive to the drive while printing a progress bar.
+fn write_firmware_progress(drive: &mut Drive, archive: &Path) -> Result<(),CliError> {
+    let name = archive.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let mut template = "{spinner:.bold.green} ".to_string();
+    template += &format!("{:47}", name);
+    template += " [{bar:40.bold.green/white}] {bytes}/{total_bytes} ({eta})";
+
+    let pb = indicatif::ProgressBar::new(1);
+    pb.set_style(indicatif::ProgressStyle::default_bar()
+     .template(&template)
+     .progress_chars("##-"));
+
+    drive.write_firmware_callback(archive, &mut ProgressBarReporter { pb })?;
+
+    Ok(())
+}
+
+/// Shows the progress reported by the library with a progress bar.
+struct ProgressBarReporter {
+    pb: indicatif::ProgressBar
+}
+
+impl ProgressReporter for ProgressBarReporter {
+    fn start(&mut self, total: u64) {
+        self.pb.set_length(total);
+    }
+
+    fn update(&mut self, done: u64) {
+        self.pb.set_position(done);
+    }
+
+    fn finish(&mut self) {
+        self.pb.finish();
+    }
+}
+
+/// install-firmware command
+fn cmd_install_firmware(args: Args) -> Result<(),CliError> {
+    let archive = PathBuf::from(args.arg_archive.unwrap());
+
+    // check the archive before touching the drive
+    let files = firmware_archive_files(&archive)
+        .map_err(|e| CliError { kind: ErrorKind::from_io(&e), msg: format!("Failed to read {}: {}", archive.display(), e) })?;
+    if files.is_empty() {
+        return Err(CliError { kind: ErrorKind::Parse, msg: format!("{} doesn't contain any firmware files.", archive.display()) });
+    }
+
+    let mut drive = Drive::new(resolve_device(args.arg_device.unwrap())?, true)?;
+
+    if args.flag_no_clear {
+        println!("About to install the following firmware files:");
+    } else {
+        println!("About to clear currently stored firmwares and install the following firmware files:");
+    }
+    for file in &files {
+        println!("    - {}", file);
+    }
+
+    if !confirm("\nProceed?".to_string(), args.flag_yes)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    if !args.flag_no_clear {
+        println!("\nClearing firmwares...");
+        drive.clear_firmwares()?;
+    }
+    println!("Writing firmwares...");
+    write_firmware_progress(&mut drive, &archive)
+}
+
+/// show-firmware command
+fn cmd_show_firmware(args: Args) -> Result<(),CliError> {
+    let drive = open_drive(args.arg_device.unwrap(), false)?;
+    let name = args.arg_name.unwrap();
+
+    let files = match drive.firmware_contents(&name) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(CliError { kind: ErrorKind::Other, msg: format!("No firmware archive {} on the drive.", name) });
+        },
+        result => result.map_err(|e| CliError { kind: ErrorKind::from_io(&e), msg: format!("Failed to read {}: {}", name, e) })?
+    };
+
+    println!("{}", format!("{:40} {:>12}", "File", "Size").bold().green());
+    println!("{}", String::from_utf8(vec![b'='; 53]).unwrap().truecolor(0x7f,0x7f,0x7f));
+
+    for (file, size) in &files {
+        println!("{:40} {:>12}", file, size);
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .map(|d| d.version(Some(VERSION.into())))
+        .and_then(|d| d.deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let json = args.flag_json;
+    let result = if args.cmd_detect {
+        cmd_detect()
+    } else if args.cmd_info {
+        cmd_info(args)
+    } else if args.cmd_init {
+        cmd_init(args)
+    } else if args.cmd_set_name {
+        cmd_set_name(args)
+    } else if args.cmd_list_workouts {
+        cmd_list_workouts(args)
+    } else if args.cmd_show_workouts {
+        cmd_show_workouts(args)
+    } else if args.cmd_compare {
+        cmd_compare(args)
+    } else if args.cmd_inspect {
+        cmd_inspect(args)
+    } else if args.cmd_check {
+        cmd_check(args)
+    } else if args.cmd_favorites {
+        cmd_favorites(args)
+    } else if args.cmd_stats {
+        cmd_stats(args)
+    } else if args.cmd_export {
+        cmd_export(args)
+    } else if args.cmd_merge {
+        cmd_merge(args)
+    } else if args.cmd_upload {
+        cmd_upload(args)
+    } else if args.cmd_backup {
+        cmd_backup(args)
+    } else if args.cmd_restore {
+        cmd_restore(args)
+    } else if args.cmd_prune {
+        cmd_prune(args)
+    } else if args.cmd_update_firmware {
+        cmd_update_firmware(args)
+    } else if args.cmd_install_firmware {
+        cmd_install_firmware(args)
+    } else if args.cmd_show_firmware {
+        cmd_show_firmware(args)
+    } else {
+        Ok(())
+    };
+
+    if let Err(e) = result {
+        if json {
+            let error = JsonError { error: &e.msg, kind: e.kind, code: e.kind as i32 };
+            println!("{}", serde_json::to_string_pretty(&error).unwrap());
+        } else {
+            println!("{} {}", "error:".bold().red(), e.msg);
+        }
+        std::process::exit(e.kind as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firmware(monitor: &str, major_version: u32) -> FirmwareVersion {
+        FirmwareVersion {
+            bug_fixes: String::new(),
+            description: String::new(),
+            internal_notes: String::new(),
+            machine: "rower".to_string(),
+            major_version,
+            minor_version: 0,
+            monitor: monitor.to_string(),
+            new_features: String::new(),
+            optional: false,
+            release_date: String::new(),
+            short_description: String::new(),
+            status: "public".to_string(),
+            version: major_version as f32,
+            files: Vec::new()
+        }
+    }
+
+    fn matching(family: &str) -> Vec<&'static str> {
+        ["PM3", "PM4", "PM5", "PM5v3"].iter()
+            .filter(|monitor| monitor_matches(monitor, family))
+            .copied()
+            .collect()
+    }
+
+    /// Directory in the system temp directory, removed again when dropped,
+    /// like `test_util::TempDir` of the library.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("concept2drive-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn parse_args(argv: &[&str]) -> Args {
+        Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv.iter().copied()).deserialize())
+            .unwrap()
+    }
+
+    #[test]
+    fn last_n_clamps() {
+        let items = [1, 2, 3];
+        assert_eq!(last_n(&items, Some(2)), &[2, 3]);
+        assert_eq!(last_n(&items, Some(10)), &[1, 2, 3]);
+        assert_eq!(last_n(&items, Some(0)), &[] as &[i32]);
+        assert_eq!(last_n(&items, None), &[1, 2, 3]);
+        assert_eq!(last_n(&[] as &[i32], Some(5)), &[] as &[i32]);
+    }
+
+    #[test]
+    fn last_flag() {
+        for argv in &[
+            ["concept2drive", "list-workouts", "/dev/sdb", "-n", "5"],
+            ["concept2drive", "list-workouts", "/dev/sdb", "--last", "5"],
+        ] {
+            assert_eq!(parse_args(argv).flag_last, Some(5));
+        }
+        assert_eq!(parse_args(&["concept2drive", "list-workouts", "/dev/sdb", "-n5"]).flag_last, Some(5));
+        assert_eq!(parse_args(&["concept2drive", "list-workouts", "/dev/sdb"]).flag_last, None);
+    }
+
+    #[test]
+    fn monitor_filter() {
+        assert_eq!(matching("pm3"), vec!["PM3"]);
+        assert_eq!(matching("pm4"), vec!["PM4"]);
+        assert_eq!(matching("pm5"), vec!["PM5", "PM5v3"]);
+        assert_eq!(matching("pm5v3"), vec!["PM5v3"]);
+    }
+
+    #[test]
+    fn monitor_detection() {
+        let versions = vec![firmware("PM4", 113), firmware("PM5", 33), firmware("PM5v3", 171)];
+        let device = |firmware_major| DeviceInfo { serial_number: 430000000, firmware_major, firmware_minor: 0 };
+
+        assert_eq!(detect_monitor(Some(&device(171)), &versions), "pm5v3");
+        assert_eq!(detect_monitor(Some(&device(33)), &versions), "pm5");
+        // unknown version or no monitor
+        assert_eq!(detect_monitor(Some(&device(13)), &versions), "pm5");
+        assert_eq!(detect_monitor(None, &versions), "pm5");
+        // ambiguous
+        let versions = vec![firmware("PM5", 33), firmware("PM5v3", 33)];
+        assert_eq!(detect_monitor(Some(&device(33)), &versions), "pm5");
+    }
+
+    /// Rowed workout without frames
+    fn workout(total_distance: u32, seconds: u64) -> Workout {
+        Workout {
+            workout_type: WorkoutType::SingleDistance,
+            machine: MachineType::Rower,
+            name: None,
+            serial_number: 430000000,
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, 10).unwrap().and_hms_opt(9, 30, 0).unwrap(),
+            user_id: 0,
+            record_id: 1,
+            total_distance,
+            total_work_duration: std::time::Duration::from_secs(seconds),
+            total_rest_duration: None,
+            rest_interval: None,
+            spm: Some(24),
+            frames: Vec::new()
+        }
+    }
+
+    /// Initializes a drive image in a new temp directory, writing the given
+    /// workouts to it. Returns the directory and the path of the image.
+    fn test_image(name: &str, workouts: &[Workout]) -> (TempDir, String) {
+        let dir = TempDir::new(name);
+        let image = dir.path().join("drive.img").to_str().unwrap().to_string();
+
+        std::fs::write(&image, vec![0; 8 * 1024 * 1024]).unwrap();
+        cmd_init(parse_args(&["concept2drive", "init", &image, "rower", "--yes"])).unwrap();
+        let mut drive = Drive::new(&image, true).unwrap();
+        for workout in workouts {
+            drive.write_workout(workout).unwrap();
+        }
+
+        (dir, image)
+    }
+
+    /// Replaces files in the logbook directory of an image, through a backup.
+    fn restore_logbook(image: &str, files: &[(&str, &[u8])]) {
+        let mut backup = std::io::Cursor::new(Vec::new());
+        let mut zip = zip::ZipWriter::new(&mut backup);
+        for (name, contents) in files {
+            zip.start_file(format!("Concept2/Logbook/{}", name), zip::write::FileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+        drop(zip);
+
+        backup.set_position(0);
+        Drive::new(image, true).unwrap().restore(backup).unwrap();
+    }
+
+    /// `workout` with a single split, so it can be written to a drive
+    fn split_workout(total_distance: u32, seconds: u64) -> Workout {
+        let frame = WorkoutFrame {
+            distance: total_distance,
+            work_duration: std::time::Duration::from_secs(seconds),
+            rest_duration: None,
+            rest_distance: None,
+            spm: 24,
+            work_heart_rate: None,
+            rest_heart_rate: None,
+            heart_rate_min: None,
+            heart_rate_max: None,
+            heart_rate_avg: None,
+            drag_factor: None,
+        };
+        Workout { frames: vec![frame], ..workout(total_distance, seconds) }
+    }
+
+    #[test]
+    fn lifetime_kwh_skips_empty_workouts() {
+        // 202.5W for 8:00 and an aborted workout
+        let workouts = [workout(2000, 480), workout(0, 0), workout(0, 60), workout(100, 0)];
+        assert!((lifetime_kwh(&workouts) - 202.5 * 480.0 / 3600000.0).abs() < 1e-4);
+        assert_eq!(lifetime_kwh(&[]).to_string(), "0");
+    }
+
+    #[test]
+    fn date_range_filter() {
+        use chrono::Datelike;
+
+        let workouts: Vec<Workout> = (10..15).map(|day| Workout {
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(18, 0, 0).unwrap(),
+            ..workout(2000, 480)
+        }).collect();
+
+        let args = parse_args(&["concept2drive", "list-workouts", "/dev/sdb", "--since", "2024-03-11", "--until", "2024-03-13"]);
+        let filter = WorkoutFilter::from_args(&args).unwrap();
+        let selected: Vec<u32> = workouts.iter().filter(|w| filter.matches(w)).map(|w| w.datetime.day()).collect();
+        // both ends are included, even though the workouts are later that day
+        assert_eq!(selected, [11, 12, 13]);
+        assert_eq!(last_n(&selected, Some(2)), &[12, 13]);
+
+        let args = parse_args(&["concept2drive", "list-workouts", "/dev/sdb", "--since", "11.03.2024"]);
+        assert!(WorkoutFilter::from_args(&args).is_err());
+    }
+
+    #[test]
+    fn workout_type_filter() {
+        use chrono::Datelike;
+
+        let types = [WorkoutType::SingleDistance, WorkoutType::DistanceInterval, WorkoutType::SingleTime,
+            WorkoutType::DistanceInterval, WorkoutType::DistanceInterval];
+        let workouts: Vec<Workout> = types.iter().zip(10..).map(|(workout_type, day)| Workout {
+            workout_type: *workout_type,
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(18, 0, 0).unwrap(),
+            ..workout(2000, 480)
+        }).collect();
+
+        let selected = |argv: &[&str]| -> Vec<u32> {
+            let filter = WorkoutFilter::from_args(&parse_args(argv)).unwrap();
+            workouts.iter().filter(|w| filter.matches(w)).map(|w| w.datetime.day()).collect()
+        };
+
+        assert_eq!(selected(&["concept2drive", "list-workouts", "/dev/sdb", "--type", "distance interval"]), [11, 13, 14]);
+        // combined with the date range
+        assert_eq!(selected(&["concept2drive", "stats", "/dev/sdb", "--type", "Distance Interval", "--until", "2024-03-13"]), [11, 13]);
+        assert_eq!(selected(&["concept2drive", "list-workouts", "/dev/sdb", "--type", "Time"]), [12]);
+        assert_eq!(selected(&["concept2drive", "list-workouts", "/dev/sdb"]), [10, 11, 12, 13, 14]);
+
+        let args = parse_args(&["concept2drive", "list-workouts", "/dev/sdb", "--type", "Sprint"]);
+        assert!(WorkoutFilter::from_args(&args).is_err());
+    }
+
+    #[test]
+    fn imperial_output() {
+        assert_eq!(distance_string(2000, false), "2000");
+        assert_eq!(distance_string(2000, true), "2187");
+        assert_eq!(pace_string(std::time::Duration::from_secs(120), false), "2:00.0");
+        assert_eq!(pace_string(std::time::Duration::from_secs(120), true), "6:26.2");
+    }
+
+    #[test]
+    fn cache_metadata() {
+        let dir = TempDir::new("cache");
+        let cached = dir.path().join("fw.7z");
+
+        // headers of the response that downloaded the file
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::ETAG, "\"v1\"".parse().unwrap());
+        headers.insert(reqwest::header::LAST_MODIFIED, "Tue, 12 Mar 2024 06:15:00 GMT".parse().unwrap());
+        let metadata = CacheMetadata::from_headers(&headers);
+        assert_eq!(metadata.etag.as_deref(), Some("\"v1\""));
+
+        // the metadata is only used while the file is there
+        metadata.write(&cached).unwrap();
+        assert!(CacheMetadata::read(&cached).is_none());
+        std::fs::write(&cached, b"firmware").unwrap();
+        let metadata = CacheMetadata::read(&cached).unwrap();
+
+        let client = reqwest::Client::new();
+        let request = metadata.apply(client.get("http://localhost/fw.7z")).build().unwrap();
+        assert_eq!(request.headers()[reqwest::header::IF_NONE_MATCH], "\"v1\"");
+        assert_eq!(request.headers()[reqwest::header::IF_MODIFIED_SINCE], "Tue, 12 Mar 2024 06:15:00 GMT");
+
+        // servers without validators get an unconditional request
+        let metadata = CacheMetadata::from_headers(&reqwest::header::HeaderMap::new());
+        let request = metadata.apply(client.get("http://localhost/fw.7z")).build().unwrap();
+        assert!(request.headers().is_empty());
+    }
+
+    #[test]
+    fn retries() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let delay = std::time::Duration::from_millis(1);
+        let transient = || CliError { kind: ErrorKind::Network, msg: "Connection reset.".to_string() };
+
+        // fails twice, succeeds on the last attempt
+        let mut attempts = 0;
+        let result = rt.block_on(retry_after("firmware", delay, || {
+            attempts += 1;
+            let result = if attempts < DOWNLOAD_ATTEMPTS { Err(transient()) } else { Ok(attempts) };
+            async move { result }
+        }));
+        assert_eq!(result.unwrap(), DOWNLOAD_ATTEMPTS);
+
+        let mut attempts = 0;
+        let error = rt.block_on(retry_after("firmware", delay, || {
+            attempts += 1;
+            async { Err::<(),_>(transient()) }
+        })).unwrap_err();
+        assert_eq!(attempts, DOWNLOAD_ATTEMPTS);
+        assert!(matches!(error.kind, ErrorKind::Network));
+        assert_eq!(error.msg, "Failed to download firmware after 3 attempts: Connection reset.");
+    }
+
+    #[test]
+    fn install_firmware() {
+        let archive = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/firmware.7z");
+        let dir = TempDir::new("install");
+
+        let image = dir.path().join("drive.img");
+        std::fs::write(&image, vec![0; 8 * 1024 * 1024]).unwrap();
+        drop(Drive::init(&image, "test".to_string()).unwrap());
+        let image = image.to_str().unwrap();
+
+        cmd_install_firmware(parse_args(&["concept2drive", "install-firmware", image, archive, "--yes"])).unwrap();
+        let drive = Drive::new(image, false).unwrap();
+        assert_eq!(drive.firmwares().unwrap(), ["firmware.7z"]);
+        assert_eq!(drive.read_file_bytes("Concept2/Firmware/PM5v2.bin").unwrap(), b"small");
+
+        // archives are checked before the drive is opened
+        let not_an_archive = dir.path().join("firmware.7z");
+        std::fs::write(&not_an_archive, b"not an archive").unwrap();
+        let error = cmd_install_firmware(parse_args(&[
+            "concept2drive", "install-firmware", "/nonexistent", not_an_archive.to_str().unwrap(), "--yes"
+        ])).unwrap_err();
+        assert!(error.msg.starts_with("Failed to read"), "{}", error.msg);
+    }
+
+    #[test]
+    fn keep_stored_firmwares() {
+        let version = |monitor: &str, major_version: u32, status: &str, name: &str| FirmwareVersion {
+            status: status.to_string(),
+            files: vec![api::FirmwareFile {
+                default: true,
+                languages: Vec::new(),
+                name: name.to_string(),
+                path: String::new(),
+                uploaded: String::new()
+            }],
+            ..firmware(monitor, major_version)
+        };
+        let versions = || vec![
+            version("PM5", 32, "public", "PM5v32.7z"), version("PM5", 33, "public", "PM5v33.7z"),
+            version("PM5", 34, "beta", "PM5v34.7z"), version("PM5v3", 171, "public", "PM5v3_171.7z"),
+            version("PM4", 113, "public", "PM4v113.7z"),
+        ];
+        let stored = ["Custom.7z".to_string(), "PM5v33.7z".to_string()];
+
+        assert_eq!(firmwares_to_install(versions(), "pm5", false, &stored, false), ["PM5v33.7z", "PM5v3_171.7z"]);
+        assert_eq!(firmwares_to_install(versions(), "pm5", false, &stored, true), ["PM5v3_171.7z"]);
+        assert_eq!(firmwares_to_install(versions(), "pm5", true, &stored, true), ["PM5v34.7z", "PM5v3_171.7z"]);
+        assert_eq!(firmwares_to_install(versions(), "pm4", false, &stored, true), ["PM4v113.7z"]);
+
+        // installing with --no-clear leaves the stored archives alone
+        let archive = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/firmware.7z");
+        let dir = TempDir::new("keep");
+        let other = dir.path().join("other.7z");
+        std::fs::copy(archive, &other).unwrap();
+        let other = other.to_str().unwrap();
+
+        let image = dir.path().join("drive.img");
+        std::fs::write(&image, vec![0; 8 * 1024 * 1024]).unwrap();
+        drop(Drive::init(&image, "test".to_string()).unwrap());
+        let image = image.to_str().unwrap();
+
+        cmd_install_firmware(parse_args(&["concept2drive", "install-firmware", image, archive, "--yes"])).unwrap();
+        cmd_install_firmware(parse_args(&["concept2drive", "install-firmware", image, other, "--no-clear", "--yes"])).unwrap();
+        let mut firmwares = Drive::new(image, false).unwrap().firmwares().unwrap();
+        firmwares.sort();
+        assert_eq!(firmwares, ["firmware.7z", "other.7z"]);
+
+        cmd_install_firmware(parse_args(&["concept2drive", "install-firmware", image, archive, "--yes"])).unwrap();
+        assert_eq!(Drive::new(image, false).unwrap().firmwares().unwrap(), ["firmware.7z"]);
+    }
+
+    #[test]
+    fn compare_splits() {
+        let three_splits = |splits: [(u64, u32, Option<u32>); 3]| Workout {
+            frames: splits.iter().map(|(seconds, spm, heart_rate)| WorkoutFrame {
+                distance: 1000,
+                work_duration: std::time::Duration::from_secs(*seconds),
+                rest_duration: None,
+                rest_distance: None,
+                spm: *spm,
+                work_heart_rate: *heart_rate,
+                rest_heart_rate: None,
+                heart_rate_min: None,
+                heart_rate_max: None,
+                heart_rate_avg: None,
+                drag_factor: None,
+            }).collect(),
+            ..workout(3000, splits.iter().map(|s| s.0).sum())
+        };
+
+        let workout = three_splits([(240, 24, Some(150)), (236, 26, Some(160)), (230, 28, Some(170))]);
+        let benchmark = three_splits([(242, 25, Some(152)), (236, 26, None), (228, 27, Some(165))]);
+        assert!(workout.same_splits(&benchmark));
+
+        let lines: Vec<String> = workout.splits().iter().zip(benchmark.splits().iter())
+            .map(|(a, b)| split_comparison(a, b))
+            .collect();
+        assert_eq!(lines, [
+            "  1  1000    4:00.0   -0:02.0 2:00.0   -0:01.0  24  -1 150  -2",
+            "  2  1000    3:56.0   +0:00.0 1:58.0   +0:00.0  26  +0 160    ",
+            "  3  1000    3:50.0   +0:02.0 1:55.0   +0:01.0  28  +1 170  +5",
+        ]);
+
+        // splits of different distances or workouts of another type don't line up
+        let mut other = three_splits([(240, 24, None), (236, 26, None), (230, 28, None)]);
+        other.frames[2].distance = 500;
+        assert!(!workout.same_splits(&other));
+        let mut other = three_splits([(240, 24, None), (236, 26, None), (230, 28, None)]);
+        other.workout_type = WorkoutType::DistanceInterval;
+        assert!(!workout.same_splits(&other));
+        other.workout_type = WorkoutType::SingleDistance;
+        other.frames.pop();
+        assert!(!workout.same_splits(&other));
+    }
+
+    #[test]
+    fn content_length_header() {
+        let length = |value: Option<&str>| {
+            let mut headers = reqwest::header::HeaderMap::new();
+            if let Some(value) = value {
+                headers.insert(reqwest::header::CONTENT_LENGTH, value.parse().unwrap());
+            }
+            content_length(&headers)
+        };
+
+        assert_eq!(length(Some("3145728")), Some(3145728));
+        assert_eq!(length(Some(" 42 ")), Some(42));
+        // chunked responses don't have one
+        assert_eq!(length(None), None);
+        assert_eq!(length(Some("")), None);
+        assert_eq!(length(Some("-1")), None);
+        assert_eq!(length(Some("3 MB")), None);
+        assert_eq!(length(Some("99999999999999999999999")), None);
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::CONTENT_LENGTH, reqwest::header::HeaderValue::from_bytes(b"4\xff2").unwrap());
+        assert_eq!(content_length(&headers), None);
+    }
+
+    #[test]
+    fn init_without_prompt() {
+        let dir = TempDir::new("init");
+        let image = dir.path().join("drive.img");
+        let image = image.to_str().unwrap();
+
+        // stdin is never read, so this would hang or abort without the flag
+        for (flag, name) in &[("--yes", "rower"), ("-y", "skier")] {
+            std::fs::write(image, vec![0; 8 * 1024 * 1024]).unwrap();
+            cmd_init(parse_args(&["concept2drive", "init", image, name, flag])).unwrap();
+            let drive = Drive::new(image, false).unwrap();
+            assert!(drive.is_concept2_drive());
+            assert_eq!(drive.user().unwrap().1, *name);
+        }
+
+        assert!(confirm("Proceed?".to_string(), true).unwrap());
+    }
+
+    #[test]
+    fn profile_loader() {
+        assert_eq!(read_profile(None).unwrap(), AthleteProfile::default());
+
+        let dir = TempDir::new("profile");
+        let path = dir.path().join("profile.toml");
+
+        std::fs::write(&path, "# measured in March\nweight_kg = 80.5\nage = 35\n\nsex = \"female\"\nmax_hr = 185\n").unwrap();
+        assert_eq!(read_profile(Some(path.clone())).unwrap(), AthleteProfile {
+            weight_kg: Some(80.5),
+            age: Some(35),
+            sex: Some(Sex::Female),
+            max_hr: Some(185)
+        });
+
+        std::fs::write(&path, "weight = 80.5\n").unwrap();
+        let error = read_profile(Some(path.clone())).unwrap_err();
+        assert_eq!(error.msg, format!("Invalid profile {}: Line 1: Unknown key weight.", path.display()));
+    }
+
+    #[test]
+    fn show_firmware() {
+        let dir = TempDir::new("show-firmware");
+        let image = dir.path().join("drive.img");
+        let image = image.to_str().unwrap();
+
+        std::fs::write(image, vec![0; 8 * 1024 * 1024]).unwrap();
+        cmd_init(parse_args(&["concept2drive", "init", image, "rower", "--yes"])).unwrap();
+        Drive::new(image, true).unwrap()
+            .write_firmware(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/firmware.7z")).unwrap();
+
+        cmd_show_firmware(parse_args(&["concept2drive", "show-firmware", image, "firmware.7z"])).unwrap();
+
+        let error = cmd_show_firmware(parse_args(&["concept2drive", "show-firmware", image, "other.7z"])).unwrap_err();
+        assert_eq!(error.msg, "No firmware archive other.7z on the drive.");
+        let error = cmd_show_firmware(parse_args(&["concept2drive", "show-firmware", image, "../x.7z"])).unwrap_err();
+        assert_eq!(error.msg, "Failed to read ../x.7z: Invalid firmware archive name ../x.7z.");
+    }
+
+    #[test]
+    fn empty_logbook() {
+        let dir = TempDir::new("empty");
+        let image = dir.path().join("drive.img");
+        let image = image.to_str().unwrap();
+
+        std::fs::write(image, vec![0; 8 * 1024 * 1024]).unwrap();
+        cmd_init(parse_args(&["concept2drive", "init", image, "rower", "--yes"])).unwrap();
+        assert!(Drive::new(image, false).unwrap().workouts().unwrap().is_empty());
+
+        // used to panic indexing the last workout
+        cmd_list_workouts(parse_args(&["concept2drive", "list-workouts", image])).unwrap();
+        cmd_list_workouts(parse_args(&["concept2drive", "list-workouts", image, "--last=3", "--desc"])).unwrap();
+        cmd_info(parse_args(&["concept2drive", "info", image])).unwrap();
+        cmd_info(parse_args(&["concept2drive", "info", image, "--json"])).unwrap();
+
+        assert!(last_n::<Workout>(&[], Some(3)).is_empty());
+        assert!(last_n::<Workout>(&[], None).is_empty());
+        assert_eq!(last_n(&[1, 2, 3], Some(2)), [2, 3]);
+        assert_eq!(last_n(&[1, 2, 3], Some(5)), [1, 2, 3]);
+    }
+
+    #[test]
+    fn annotated_hex_dump() {
+        // single distance record with one frame and 20 trailing bytes
+        let mut record: Vec<u8> = (0..102).collect();
+        record[1] = 0x01;
+        let lines = annotated_hex(&record, &native::storage_record_fields(&record));
+
+        let line = |label: &str| lines.iter().find(|l| l.label == label).unwrap();
+        assert_eq!(line("magic"), &HexLine { offset: 0, label: "magic".to_string(), hex: "00".to_string(), unknown: false });
+        assert_eq!(line("serial_number"), &HexLine {
+            offset: 4, label: "serial_number".to_string(), hex: "04 05 06 07".to_string(), unknown: false
+        });
+        assert_eq!(line("total_distance").offset, 24);
+        assert_eq!(line("frames[0].duration_or_distance").offset, 50);
+        assert_eq!(line("frames[0].spm").hex, "35");
+
+        // long fields continue on unlabeled lines
+        let unknown: Vec<&HexLine> = lines.iter().filter(|l| l.offset >= 32 && l.offset < 50).collect();
+        assert_eq!(unknown.len(), 2);
+        assert_eq!((unknown[0].offset, unknown[0].label.as_str(), unknown[0].unknown), (32, "unknown_3", true));
+        assert_eq!((unknown[1].offset, unknown[1].label.as_str()), (48, ""));
+        assert_eq!(unknown[1].hex, "30 31");
+
+        let last = lines.last().unwrap();
+        assert_eq!((last.offset, last.label.as_str(), last.unknown), (98, "", true));
+        assert_eq!(line("unknown").offset, 82);
+
+        let dir = TempDir::new("inspect");
+        let image = dir.path().join("drive.img");
+        let image = image.to_str().unwrap();
+
+        std::fs::write(image, vec![0; 8 * 1024 * 1024]).unwrap();
+        cmd_init(parse_args(&["concept2drive", "init", image, "rower", "--yes"])).unwrap();
+        let error = cmd_inspect(parse_args(&["concept2drive", "inspect", image])).unwrap_err();
+        assert_eq!(error.msg, "No workouts on this drive.");
+
+        Drive::new(image, true).unwrap().write_workout(&split_workout(2000, 480)).unwrap();
+        cmd_inspect(parse_args(&["concept2drive", "inspect", image])).unwrap();
+        cmd_inspect(parse_args(&["concept2drive", "inspect", image, "1"])).unwrap();
+        let error = cmd_inspect(parse_args(&["concept2drive", "inspect", image, "2"])).unwrap_err();
+        assert_eq!(error.msg, "No matching workout found.");
+    }
+
+    #[test]
+    fn compressed_images_are_read_only() {
+        match open_drive("drive.img.gz".to_string(), true) {
+            Err(error) => assert_eq!(error.msg, "Compressed images can only be read."),
+            Ok(_) => panic!("opened a compressed image for writing")
+        }
+    }
+
+    #[test]
+    fn error_exit_codes() {
+        let dir = TempDir::new("exit-codes");
+        let image = dir.path().join("drive.img");
+        let image = image.to_str().unwrap();
+        let missing = dir.path().join("missing.img");
+        let missing = missing.to_str().unwrap();
+
+        let code = |argv: &[&str]| {
+            let args = parse_args(argv);
+            let result = if args.cmd_info {
+                cmd_info(args)
+            } else if args.cmd_stats {
+                cmd_stats(args)
+            } else if args.cmd_list_workouts {
+                cmd_list_workouts(args)
+            } else {
+                cmd_show_workouts(args)
+            };
+            result.unwrap_err().kind as i32
+        };
+
+        assert_eq!(code(&["concept2drive", "info", missing]), 2);
+        // not FAT formatted
+        std::fs::write(image, vec![0; 8 * 1024 * 1024]).unwrap();
+        assert_eq!(code(&["concept2drive", "info", image]), 2);
+
+        cmd_init(parse_args(&["concept2drive", "init", image, "rower", "--yes"])).unwrap();
+        assert_eq!(code(&["concept2drive", "show-workouts", image, "3"]), 1);
+        // invalid arguments are usage errors, not drive errors
+        assert_eq!(code(&["concept2drive", "stats", image, "--by", "year"]), 1);
+        assert_eq!(code(&["concept2drive", "list-workouts", image, "--type", "Marathon"]), 1);
+        assert_eq!(code(&["concept2drive", "show-workouts", image, "yesterday"]), 1);
+
+        // access table entry with an invalid magic byte
+        restore_logbook(image, &[("LogDataAccessTbl.bin", &[0x12; 32])]);
+        assert_eq!(code(&["concept2drive", "show-workouts", image]), 4);
+
+        let error = CliError { kind: ErrorKind::Network, msg: "Request failed.".to_string() };
+        let json = serde_json::to_value(JsonError { error: &error.msg, kind: error.kind, code: error.kind as i32 }).unwrap();
+        assert_eq!(json, serde_json::json!({"error": "Request failed.", "kind": "network", "code": 3}));
+        assert_eq!(ErrorKind::default() as i32, 1);
+    }
+
+    #[test]
+    fn export_json() {
+        let workouts: Vec<Workout> = (10..13).map(|day| Workout {
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(18, 0, 0).unwrap(),
+            ..split_workout(2000, 480)
+        }).collect();
+        let (dir, image) = test_image("export-json", &workouts);
+        let file = dir.path().join("workouts.json");
+        let file = file.to_str().unwrap();
+
+        cmd_export(parse_args(&["concept2drive", "export", &image, file, "--format", "json"])).unwrap();
+        let json: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        assert_eq!(json.len(), 3);
+        assert_eq!(json[2]["frames"].as_array().unwrap().len(), 1);
+
+        cmd_export(parse_args(&["concept2drive", "export", &image, "2", file, "--format", "json"])).unwrap();
+        let json: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0], serde_json::to_value(&Drive::new(&image, false).unwrap().workouts().unwrap()[1]).unwrap());
+
+        let error = cmd_export(parse_args(&["concept2drive", "export", &image, file, "--format", "xml"])).unwrap_err();
+        assert_eq!(error.msg, "Unknown export format \"xml\".");
+    }
+
+    #[test]
+    fn merge_drives() {
+        let on = |day, distance| Workout {
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(18, 0, 0).unwrap(),
+            ..split_workout(distance, 480)
+        };
+        // the workout on the 11th was copied to both drives
+        let (dir, first) = test_image("merge-1", &[on(10, 2000), on(12, 1900), on(11, 2100)]);
+        let (_other_dir, second) = test_image("merge-2", &[on(11, 2100), on(13, 2200)]);
+        let file = dir.path().join("merged.json");
+        let file = file.to_str().unwrap();
+
+        cmd_merge(parse_args(&["concept2drive", "merge", &first, &second, file])).unwrap();
+        let json: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        let distances: Vec<u64> = json.iter().map(|w| w["total_distance"].as_u64().unwrap()).collect();
+        assert_eq!(distances, [2000, 2100, 1900, 2200]);
+
+        let csv = dir.path().join("merged.csv");
+        cmd_merge(parse_args(&["concept2drive", "merge", &second, &first, csv.to_str().unwrap()])).unwrap();
+        assert_eq!(std::fs::read_to_string(&csv).unwrap().lines().count(), 5);
+
+        let error = cmd_merge(parse_args(&["concept2drive", "merge", &first, &second, "merged.txt"])).unwrap_err();
+        assert_eq!(error.msg, "Can't tell the export format from merged.txt, use .csv, .json or .tcx.");
+    }
+
+    #[test]
+    fn stats_pace_distribution() {
+        let (_dir, image) = test_image("stats", &[split_workout(2000, 420), split_workout(2000, 500)]);
+
+        cmd_stats(parse_args(&["concept2drive", "stats", &image])).unwrap();
+        cmd_stats(parse_args(&["concept2drive", "stats", &image, "--by", "month", "--pace-per-mile"])).unwrap();
+        // no workouts left, so no distribution either
+        cmd_stats(parse_args(&["concept2drive", "stats", &image, "--type", "Time"])).unwrap();
+    }
+
+    #[test]
+    fn prune_command() {
+        let on = |day| Workout {
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(18, 0, 0).unwrap(),
+            ..split_workout(2000, 480)
+        };
+        let (_dir, image) = test_image("prune", &[on(10), on(12), on(11)]);
+
+        cmd_prune(parse_args(&["concept2drive", "prune", &image, "2024-03-12", "--yes"])).unwrap();
+        let remaining = Drive::new(&image, false).unwrap().workouts().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].datetime, on(12).datetime);
+
+        // nothing to delete, so nothing to confirm either
+        cmd_prune(parse_args(&["concept2drive", "prune", &image, "2024-03-12"])).unwrap();
+        assert!(cmd_prune(parse_args(&["concept2drive", "prune", &image, "12.03.2024", "--yes"])).is_err());
+    }
+
+    #[test]
+    fn info_total_times() {
+        let mut interval = split_workout(2000, 480);
+        interval.workout_type = WorkoutType::DistanceInterval;
+        interval.frames[0].rest_duration = Some(std::time::Duration::from_secs(90));
+        interval.total_rest_duration = Some(std::time::Duration::from_secs(90));
+        let workouts = [split_workout(2000, 420), interval, split_workout(5000, 1260)];
+
+        let (work, rest) = total_times(&workouts);
+        assert_eq!(duration_to_string(&work), "36:00.0");
+        assert_eq!(duration_to_string(&rest), "1:30.0");
+        assert_eq!(total_times(&[]), (std::time::Duration::from_secs(0), std::time::Duration::from_secs(0)));
+
+        // intervals can't be written yet
+        let (_dir, image) = test_image("info", &[split_workout(2000, 420), split_workout(5000, 1260)]);
+        let parsed = Drive::new(&image, false).unwrap().workouts().unwrap();
+        assert_eq!(total_times(&parsed), (std::time::Duration::from_secs(1680), std::time::Duration::from_secs(0)));
+        cmd_info(parse_args(&["concept2drive", "info", &image])).unwrap();
+    }
+
+    #[test]
+    fn list_order() {
+        use chrono::Datelike;
+
+        let types = [WorkoutType::SingleDistance, WorkoutType::SingleTime, WorkoutType::SingleDistance,
+            WorkoutType::SingleDistance, WorkoutType::SingleTime];
+        let workouts: Vec<Workout> = types.iter().zip(10..).map(|(workout_type, day)| Workout {
+            workout_type: *workout_type,
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(18, 0, 0).unwrap(),
+            ..workout(2000, 480)
+        }).collect();
+
+        // numbers and days of the listed workouts
+        let listed = |argv: &[&str]| -> Vec<(usize, u32)> {
+            let args = parse_args(argv);
+            let filter = WorkoutFilter::from_args(&args).unwrap();
+            listed_workouts(&workouts, &filter, args.flag_last, args.flag_desc).iter()
+                .map(|(i, w)| (i + 1, w.datetime.day()))
+                .collect()
+        };
+
+        assert_eq!(listed(&["concept2drive", "list-workouts", "/dev/sdb"]), [(1, 10), (2, 11), (3, 12), (4, 13), (5, 14)]);
+        assert_eq!(listed(&["concept2drive", "list-workouts", "/dev/sdb", "--desc"]), [(5, 14), (4, 13), (3, 12), (2, 11), (1, 10)]);
+        // the newest two, in both directions
+        assert_eq!(listed(&["concept2drive", "list-workouts", "/dev/sdb", "-n", "2"]), [(4, 13), (5, 14)]);
+        assert_eq!(listed(&["concept2drive", "list-workouts", "/dev/sdb", "-n", "2", "--desc"]), [(5, 14), (4, 13)]);
+        // numbers still refer to all workouts when filtering
+        assert_eq!(listed(&["concept2drive", "list-workouts", "/dev/sdb", "--type", "Distance", "--desc", "--last=2"]),
+            [(4, 13), (3, 12)]);
+        assert_eq!(listed(&["concept2drive", "list-workouts", "/dev/sdb", "-n", "0", "--desc"]), []);
+    }
+
+    #[test]
+    fn check_command() {
+        let (_dir, image) = test_image("check", &[split_workout(2000, 420), split_workout(5000, 1260)]);
+        cmd_check(parse_args(&["concept2drive", "check", &image])).unwrap();
+
+        // a second entry for a record that isn't there
+        let mut access_table = Vec::new();
+        let record = Drive::new(&image, false).unwrap().raw_workout_record(0).unwrap();
+        access_table.extend_from_slice(&record.access_table_entry);
+        concept2drive::native::LogDataAccessTableEntry::new(WorkoutType::SingleDistance, None, 1, 500, 82, 1)
+            .write(&mut access_table).unwrap();
+        access_table.extend_from_slice(&[0xff; 32]);
+
+        restore_logbook(&image, &[("LogDataAccessTbl.bin", &access_table), ("LogDataStorage.bin", &record.storage_record)]);
+
+        let error = cmd_check(parse_args(&["concept2drive", "check", &image])).unwrap_err();
+        assert_eq!(error.msg, "Found problems with 1 of 2 workouts.");
+        assert_eq!(error.kind, ErrorKind::Parse);
+    }
+
+    #[test]
+    fn export_single_workout() {
+        let on = |day, hour, distance| Workout {
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(hour, 0, 0).unwrap(),
+            ..split_workout(distance, 480)
+        };
+        let (dir, image) = test_image("export-single", &[on(10, 18, 2000), on(11, 7, 1900), on(11, 18, 2100), on(12, 18, 2200)]);
+        let file = dir.path().join("workout.csv");
+        let file = file.to_str().unwrap();
+
+        cmd_export(parse_args(&["concept2drive", "export", &image, "2024-03-10", file])).unwrap();
+        let csv = std::fs::read_to_string(file).unwrap();
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].starts_with("2024-03-10 18:00,"), "{}", rows[0]);
+
+        // the last workout of the day, unless the time is given
+        cmd_export(parse_args(&["concept2drive", "export", &image, "2024-03-11", file, "--format", "json"])).unwrap();
+        let json: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0]["total_distance"], 2100);
+        cmd_export(parse_args(&["concept2drive", "export", &image, "2024-03-11 07:00", file, "--format", "json"])).unwrap();
+        let json: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        assert_eq!(json[0]["total_distance"], 1900);
+
+        let missing = dir.path().join("missing.tcx");
+        let error = cmd_export(parse_args(&["concept2drive", "export", &image, "2024-03-13", missing.to_str().unwrap()])).unwrap_err();
+        assert_eq!(error.msg, "No matching workout found.");
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn cached_download() {
+        let dir = TempDir::new("download");
+        let target = dir.path().join("PM5v33.7z");
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        let (base, server) = api::tests::mock_server(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 8\r\nConnection: close\r\n\r\nfirmware",
+            "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nConnection: close\r\n\r\nnewer firmware",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let file = FirmwareFile {
+            default: true,
+            languages: Vec::new(),
+            name: "PM5v33.7z".to_string(),
+            path: format!("{}/files/PM5v33.7z", base),
+            uploaded: String::new()
+        };
+
+        // the first download is unconditional and stores the validators
+        rt.block_on(download_file_attempt(&file, &target)).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"firmware");
+        assert_eq!(CacheMetadata::read(&target).unwrap().etag.as_deref(), Some("\"v1\""));
+
+        // an unchanged file isn't downloaded again
+        rt.block_on(download_file_attempt(&file, &target)).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"firmware");
+
+        // a changed one replaces the cached file, without a Content-Length
+        rt.block_on(download_file_attempt(&file, &target)).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"newer firmware");
+        assert_eq!(CacheMetadata::read(&target).unwrap().etag.as_deref(), Some("\"v2\""));
+
+        // errors keep the cached file and its metadata
+        let error = rt.block_on(download_file_attempt(&file, &target)).unwrap_err();
+        assert!(matches!(error.kind, ErrorKind::Network));
+        assert_eq!(std::fs::read(&target).unwrap(), b"newer firmware");
+        assert!(CacheMetadata::read(&target).is_some());
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /files/PM5v33.7z HTTP/1.1\r\n"));
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("\r\nif-none-match: \"v1\"\r\n"));
+        assert!(requests[2].contains("\r\nif-none-match: \"v1\"\r\n"));
+        assert!(requests[3].contains("\r\nif-none-match: \"v2\"\r\n"));
+    }
+
+    #[test]
+    fn retried_download() {
+        let dir = TempDir::new("retry");
+        let target = dir.path().join("PM5v33.7z");
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let delay = std::time::Duration::from_millis(1);
+
+        // a server error followed by a truncated response, then the file
+        let (base, server) = api::tests::mock_server(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 8\r\nConnection: close\r\n\r\nfirm",
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 8\r\nConnection: close\r\n\r\nfirmware",
+        ]);
+        let file = FirmwareFile {
+            default: true,
+            languages: Vec::new(),
+            name: "PM5v33.7z".to_string(),
+            path: format!("{}/files/PM5v33.7z", base),
+            uploaded: String::new()
+        };
+
+        rt.block_on(retry_after(&file.name, delay, || download_file_attempt(&file, &target))).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"firmware");
+        assert_eq!(CacheMetadata::read(&target).unwrap().etag.as_deref(), Some("\"v1\""));
+
+        // the truncated file wasn't considered up-to-date
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), DOWNLOAD_ATTEMPTS as usize);
+        assert!(requests.iter().all(|r| !r.contains("if-none-match")));
+
+        // the last error is reported once the attempts are used up
+        let responses = vec!["HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"; 3];
+        let (base, server) = api::tests::mock_server(responses);
+        let file = FirmwareFile { path: format!("{}/files/PM5v33.7z", base), ..file };
+        let error = rt.block_on(retry_after(&file.name, delay, || download_file_attempt(&file, &target))).unwrap_err();
+        assert!(matches!(error.kind, ErrorKind::Network));
+        assert!(error.msg.starts_with("Failed to download PM5v33.7z after 3 attempts: "));
+        assert_eq!(server.join().unwrap().len(), DOWNLOAD_ATTEMPTS as usize);
     }
 }
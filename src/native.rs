@@ -2,15 +2,139 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use std::convert::TryInto;
-use std::io::Read;
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Write};
 use std::time::Duration;
 
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{Datelike, Timelike};
 
 use crate::error::*;
 use crate::workouts::*;
 
+/// Upper bound for the number of frames in a storage record. Record sizes in
+/// the access table are 16 bit, so no valid record can contain more frames.
+const MAX_FRAMES: u32 = 0xffff / 32;
+
+/// Magic byte at the start of storage records written by the monitor.
+const STORAGE_MAGIC: u8 = 0x95;
+
+/// Offsets of the drag factor in the unknown bytes of `SingleFrame`, by the
+/// firmware version (major, minor) known to store it there. None are listed
+/// yet: all records available so far have zeros in those bytes, so no offset
+/// could be confirmed. Until a version is added, frames have no drag factor.
+const DRAG_FACTOR_OFFSETS: &[((u8, u8), usize)] = &[];
+
+/// Firmware versions (major, minor) known to store the heart rate minimum,
+/// maximum and average of a split in bytes 4 to 6 of `SingleFrame`. That
+/// meaning is a guess that no record could confirm so far, all of them have
+/// zeros there. Frames of other versions have no heart rate range.
+const HEART_RATE_RANGE_FIRMWARES: &[(u8, u8)] = &[];
+
+/// A field of a raw record, used to annotate hex dumps. Offsets are relative
+/// to the start of the record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordField {
+    pub name: &'static str,
+    /// index of the frame the field belongs to, `None` for header fields
+    pub frame: Option<usize>,
+    pub offset: usize,
+    pub size: usize
+}
+
+impl RecordField {
+    /// Returns true if the meaning of the field isn't known yet.
+    pub fn is_unknown(&self) -> bool {
+        self.name.starts_with("unknown")
+    }
+}
+
+/// Names and sizes of the fields of a record, in the order they are read
+pub type FieldLayout = &'static [(&'static str, usize)];
+
+/// Total size of the fields in a layout
+fn layout_size(layout: FieldLayout) -> usize {
+    layout.iter().map(|(_, size)| size).sum()
+}
+
+/// Implements `unknown_fields` for a record type with the given fields.
+macro_rules! unknown_fields {
+    ($t:ty: $($field:ident),+) => {
+        impl $t {
+            /// Returns the bytes of the fields that aren't understood yet,
+            /// named as in `FIELDS`, for reverse engineering tools.
+            ///
+            /// Unstable: fields are renamed or removed as they get decoded.
+            pub fn unknown_fields(&self) -> Vec<(&'static str, &[u8])> {
+                vec![$((stringify!($field), &self.$field[..])),+]
+            }
+        }
+    }
+}
+
+unknown_fields!(LogDataAccessTableEntry: unknown_1, unknown_2, unknown_3, unknown_4);
+unknown_fields!(SingleEntry: unknown_1, unknown_2, unknown_3);
+unknown_fields!(SingleFrame: unknown);
+unknown_fields!(FixedIntervalEntry: unknown_1, unknown_2, unknown_3);
+unknown_fields!(FixedIntervalFrame: unknown);
+unknown_fields!(VariableIntervalEntry: unknown_1, unknown_2, unknown_3);
+
+/// Appends the fields of a record part starting at `offset`. Stops at the end
+/// of the data.
+fn push_fields(
+    fields: &mut Vec<RecordField>,
+    layout: FieldLayout,
+    frame: Option<usize>,
+    offset: &mut usize,
+    len: usize
+) {
+    for (name, size) in layout {
+        if *offset >= len {
+            return;
+        }
+
+        let size = (*size).min(len - *offset);
+        fields.push(RecordField { name, frame, offset: *offset, size });
+        *offset += size;
+    }
+}
+
+/// Lays out the fields of a raw `LogDataAccessTbl.bin` entry.
+pub fn access_table_fields(entry: &[u8]) -> Vec<RecordField> {
+    let mut fields = Vec::new();
+    push_fields(&mut fields, LogDataAccessTableEntry::FIELDS, None, &mut 0, entry.len());
+    fields
+}
+
+/// Lays out the fields of a raw `LogDataStorage.bin` record, using the
+/// workout type in its second byte: the header, followed by the fields of
+/// every frame. Bytes not covered by the known layouts, e.g. of variable
+/// interval workouts, are returned as a single unknown field.
+pub fn storage_record_fields(record: &[u8]) -> Vec<RecordField> {
+    let (header, frame): (FieldLayout, FieldLayout) = match record.get(1) {
+        Some(0x01) | Some(0x03) | Some(0x05) | Some(0x0a) => (SingleEntry::FIELDS, SingleFrame::FIELDS),
+        Some(0x06) | Some(0x07) => (FixedIntervalEntry::FIELDS, FixedIntervalFrame::FIELDS),
+        _ => (&[("magic", 1), ("workout_type", 1)], &[])
+    };
+
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    push_fields(&mut fields, header, None, &mut offset, record.len());
+
+    let frame_size = layout_size(frame);
+    let mut i = 0;
+    while frame_size > 0 && offset + frame_size <= record.len() {
+        push_fields(&mut fields, frame, Some(i), &mut offset, record.len());
+        i += 1;
+    }
+
+    if offset < record.len() {
+        fields.push(RecordField { name: "unknown", frame: None, offset, size: record.len() - offset });
+    }
+
+    fields
+}
+
 #[derive(Debug, Default)]
 pub struct LogDataAccessTableEntry {
     pub magic: u8,
@@ -25,11 +149,43 @@ pub struct LogDataAccessTableEntry {
     pub record_offset: u16,
     unknown_3: [u8; 6],
     pub record_size: u16,
-    index: u16,
+    pub index: u16,
     unknown_4: [u8; 4]
 }
 
 impl LogDataAccessTableEntry {
+    pub const SIZE: usize = 32;
+
+    /// Names and sizes of the fields, in the order `read` reads them.
+    pub const FIELDS: FieldLayout = &[
+        ("magic", 1), ("workout_type", 1), ("interval_rest_time", 2), ("workout_name", 2),
+        ("unknown_1", 2), ("timestamp", 2), ("unknown_2", 2), ("num_splits", 2),
+        ("duration_or_distance", 2), ("record_offset", 2), ("unknown_3", 6),
+        ("record_size", 2), ("index", 2), ("unknown_4", 4)
+    ];
+
+    /// Creates an entry pointing to a storage record. Fields that aren't
+    /// understood yet are left zeroed.
+    pub fn new(
+        workout_type: WorkoutType,
+        name: Option<WorkoutName>,
+        num_splits: u16,
+        record_offset: u16,
+        record_size: u16,
+        index: u16
+    ) -> Self {
+        Self {
+            magic: 0xf0,
+            workout_type: workout_type as u8,
+            workout_name: name.map(|n| n.to_bytes()).unwrap_or_default(),
+            num_splits,
+            record_offset,
+            record_size,
+            index,
+            ..Default::default()
+        }
+    }
+
     pub fn read<R: Read>(f: &mut R) -> Result<Self,ParserError> {
         let magic = f.read_u8()?;
         let workout_type = f.read_u8()?;
@@ -52,7 +208,7 @@ impl LogDataAccessTableEntry {
         f.read_exact(&mut unknown_4)?;
 
         if magic != 0xf0 && magic != 0xff && magic != 0x70 {
-            return Err(ParserError::default());
+            return Err(ParserError::BadMagic { found: magic, offset: None });
         }
 
         Ok(Self {
@@ -72,6 +228,38 @@ impl LogDataAccessTableEntry {
             unknown_4
         })
     }
+
+    /// Returns the program picked on the monitor for this workout.
+    pub fn workout_name(&self) -> Option<WorkoutName> {
+        WorkoutName::from_bytes(self.workout_name)
+    }
+
+    /// Returns the programmed rest between intervals for fixed interval
+    /// workouts. Stored in seconds, same as in the storage record.
+    pub fn interval_rest_time(&self) -> Option<Duration> {
+        match WorkoutType::try_from(self.workout_type) {
+            Ok(WorkoutType::TimeInterval) | Ok(WorkoutType::DistanceInterval) =>
+                Some(Duration::from_secs(self.interval_rest_time.into())),
+            _ => None
+        }
+    }
+
+    pub fn write<W: Write>(&self, f: &mut W) -> Result<(),std::io::Error> {
+        f.write_u8(self.magic)?;
+        f.write_u8(self.workout_type)?;
+        f.write_u16::<LittleEndian>(self.interval_rest_time)?;
+        f.write_all(&self.workout_name)?;
+        f.write_all(&self.unknown_1)?;
+        f.write_u16::<BigEndian>(self.timestamp)?;
+        f.write_all(&self.unknown_2)?;
+        f.write_u16::<LittleEndian>(self.num_splits)?;
+        f.write_u16::<LittleEndian>(self.duration_or_distance)?;
+        f.write_u16::<LittleEndian>(self.record_offset)?;
+        f.write_all(&self.unknown_3)?;
+        f.write_u16::<LittleEndian>(self.record_size)?;
+        f.write_u16::<LittleEndian>(self.index)?;
+        f.write_all(&self.unknown_4)
+    }
 }
 
 #[derive(Debug)]
@@ -81,34 +269,177 @@ pub enum LogDataStorageEntry {
     VariableInterval(VariableIntervalEntry),
 }
 
+/// Layout of the storage records, which differs between firmware
+/// generations. Only one layout is known so far. Another one would be added
+/// as a variant with its own readers and `FIELDS`, picked in `for_firmware`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordLayout {
+    /// the layout of all records seen so far
+    Pm5
+}
+
+impl RecordLayout {
+    /// Returns the layout used by the given firmware version (major, minor),
+    /// as reported by `Drive::device_info`. Records of unknown versions are
+    /// parsed with the known layout; `LogDataStorageEntry::check_layout`
+    /// catches the ones that don't match it.
+    pub fn for_firmware(_firmware: Option<(u8, u8)>) -> Self {
+        Self::Pm5
+    }
+}
+
 impl LogDataStorageEntry {
     pub fn read<R: Read>(f: &mut R) -> Result<Self,ParserError> {
+        Self::read_layout(f, RecordLayout::Pm5)
+    }
+
+    /// Like `read`, but for a record in the given layout.
+    pub fn read_layout<R: Read>(f: &mut R, layout: RecordLayout) -> Result<Self,ParserError> {
         let magic = f.read_u8()?;
         let workout_type = f.read_u8()?;
 
-        match workout_type {
-            0x01 | 0x03 | 0x05 | 0x0A => {
+        match (layout, workout_type) {
+            (RecordLayout::Pm5, 0x01 | 0x03 | 0x05 | 0x0A) => {
                 Ok(Self::Single(SingleEntry::read(f, magic, workout_type.try_into()?)?))
             },
-            0x06 | 0x07 => {
+            (RecordLayout::Pm5, 0x06 | 0x07) => {
                 Ok(Self::FixedInterval(FixedIntervalEntry::read(f, magic, workout_type.try_into()?)?))
             },
-            0x08 => {
+            (RecordLayout::Pm5, 0x08) => {
                 Ok(Self::VariableInterval(VariableIntervalEntry::read(f, magic, workout_type.try_into()?)?))
             },
             _ => {
-                Err(ParserError::default())
+                Err(ParserError::UnknownWorkoutType(workout_type))
             }
         }
     }
 }
 
-impl Into<Workout> for LogDataStorageEntry {
-    fn into(self) -> Workout {
+impl LogDataStorageEntry {
+    /// Drag factors of the frames, in order, see `SingleFrame::drag_factor`.
+    /// Interval records aren't known to store one.
+    pub fn drag_factors(&self, firmware: (u8, u8)) -> Vec<Option<u32>> {
+        match self {
+            Self::Single(entry) => entry.frames.iter().map(|f| f.drag_factor(firmware)).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Heart rate ranges of the frames, in order, see
+    /// `SingleFrame::heart_rate_range`.
+    pub fn heart_rate_ranges(&self, firmware: (u8, u8)) -> Vec<Option<HeartRateRange>> {
+        match self {
+            Self::Single(entry) => entry.frames.iter().map(|f| f.heart_rate_range(firmware)).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    pub fn workout_type(&self) -> WorkoutType {
+        match self {
+            Self::Single(entry) => entry.workout_type,
+            Self::FixedInterval(entry) => entry.workout_type,
+            Self::VariableInterval(entry) => entry.workout_type
+        }
+    }
+
+    /// Checks that the record matches the access table entry pointing to it.
+    /// A record in another layout usually has a different size, but if
+    /// fields only moved within the same size, the workout type is the
+    /// likeliest to be read wrong. Variable interval records can't be
+    /// checked yet, their layout isn't known.
+    pub fn check_layout(&self, at_entry: &LogDataAccessTableEntry) -> Result<(),ParserError> {
+        let record_size = at_entry.record_size as usize;
+        if let Some(layout_size) = self.layout_size() {
+            if layout_size != record_size {
+                return Err(ParserError::LayoutMismatch { record_size, layout_size });
+            }
+        }
+
+        let workout_type = self.workout_type() as u8;
+        if workout_type != at_entry.workout_type {
+            return Err(ParserError::FieldMismatch {
+                field: "workout type",
+                access_table: at_entry.workout_type as u32,
+                record: workout_type as u32
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Size of the record according to the layout it was parsed with,
+    /// including all frames, or `None` if the layout for the workout type
+    /// isn't fully known. A different size than the one in the access table
+    /// means the record was written in another layout, e.g. by a firmware
+    /// generation that isn't supported yet.
+    pub fn layout_size(&self) -> Option<usize> {
+        match self {
+            Self::Single(entry) => Some(layout_size(SingleEntry::FIELDS) +
+                entry.frames.len() * layout_size(SingleFrame::FIELDS)),
+            Self::FixedInterval(entry) => Some(layout_size(FixedIntervalEntry::FIELDS) +
+                entry.frames.len() * layout_size(FixedIntervalFrame::FIELDS)),
+            Self::VariableInterval(_) => None
+        }
+    }
+
+    /// Builds the storage record for a workout. Only single workouts are
+    /// supported so far.
+    pub fn from_workout(workout: &Workout) -> Result<Self,ParserError> {
+        match workout.workout_type {
+            WorkoutType::FreeRow | WorkoutType::SingleDistance |
+            WorkoutType::SingleTime | WorkoutType::SingleCalorie => {
+                Ok(Self::Single(SingleEntry::from_workout(workout)?))
+            },
+            _ => {
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                    "Writing interval workouts isn't supported yet.").into())
+            }
+        }
+    }
+
+    pub fn write<W: Write>(&self, f: &mut W) -> Result<(),std::io::Error> {
         match self {
-            Self::Single(entry) => entry.into(),
-            Self::FixedInterval(entry) => entry.into(),
-            Self::VariableInterval(entry) => entry.into()
+            Self::Single(entry) => entry.write(f),
+            _ => {
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                    "Writing interval workouts isn't supported yet."))
+            }
+        }
+    }
+}
+
+impl TryFrom<LogDataStorageEntry> for Workout {
+    type Error = ParserError;
+
+    fn try_from(entry: LogDataStorageEntry) -> Result<Self,ParserError> {
+        match entry {
+            LogDataStorageEntry::Single(entry) => entry.try_into(),
+            LogDataStorageEntry::FixedInterval(entry) => entry.try_into(),
+            LogDataStorageEntry::VariableInterval(entry) => entry.try_into()
+        }
+    }
+}
+
+/// What the splits of a single workout are measured in. Determines the
+/// unit of the split size and which value each frame stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitKind {
+    Distance,
+    Time,
+    Calorie
+}
+
+impl SplitKind {
+    /// Returns the kind of splits recorded for a single workout, or `None`
+    /// for intervals. The `split_info` byte of the record looks like it
+    /// should tell the kinds apart, but it has been 0 for distance and time
+    /// splits alike, so the kind has to follow from the workout type.
+    pub fn of(workout_type: WorkoutType) -> Option<Self> {
+        match workout_type {
+            WorkoutType::FreeRow | WorkoutType::SingleDistance => Some(Self::Distance),
+            WorkoutType::SingleTime => Some(Self::Time),
+            WorkoutType::SingleCalorie => Some(Self::Calorie),
+            _ => None
         }
     }
 }
@@ -117,7 +448,9 @@ impl Into<Workout> for LogDataStorageEntry {
 pub struct SingleEntry {
     magic: u8,
     workout_type: WorkoutType,
-    unknown_1: [u8; 2],
+    /// assumed to be the machine type as reported by the PM5, 0 on rowers
+    machine: MachineType,
+    unknown_1: [u8; 1],
     serial_number: u32,
     timestamp: u32,
     user_id: u16,
@@ -128,14 +461,30 @@ pub struct SingleEntry {
     total_distance: u32,
     spm: u8,
     split_info: u8,
+    split_kind: SplitKind,
     split_size: u16,
     unknown_3: [u8; 18],
     frames: Vec<SingleFrame>
 }
 
 impl SingleEntry {
+    /// Names and sizes of the header fields, in the order they are read,
+    /// including the magic and workout type read by `LogDataStorageEntry`.
+    pub const FIELDS: FieldLayout = &[
+        ("magic", 1), ("workout_type", 1), ("machine", 1), ("unknown_1", 1),
+        ("serial_number", 4), ("timestamp", 4), ("user_id", 2), ("unknown_2", 4),
+        ("record_id", 1), ("magic_2", 3), ("total_duration", 2), ("total_distance", 4),
+        ("spm", 1), ("split_info", 1), ("split_size", 2), ("unknown_3", 18)
+    ];
+
+    /// Frames as stored, e.g. to access their `unknown_fields`.
+    pub fn frames(&self) -> &[SingleFrame] {
+        &self.frames
+    }
+
     pub fn read<R: Read>(f: &mut R, magic: u8, workout_type: WorkoutType) -> Result<Self,std::io::Error> {
-        let mut unknown_1 = [0; 2];
+        let machine = MachineType::from_code(f.read_u8()?);
+        let mut unknown_1 = [0; 1];
         f.read_exact(&mut unknown_1)?;
         let serial_number = f.read_u32::<BigEndian>()?;
         let timestamp = f.read_u32::<BigEndian>()?;
@@ -153,19 +502,17 @@ impl SingleEntry {
         let mut unknown_3 = [0; 18];
         f.read_exact(&mut unknown_3)?;
 
-        let num_frames: u32 = match workout_type {
-            WorkoutType::FreeRow | WorkoutType::SingleDistance => {
-                total_distance / (split_size as u32) +
-                    if total_distance % (split_size as u32) > 0 { 1 } else { 0 }
-            },
-            WorkoutType::SingleTime => {
-                todo!()
-            },
-            WorkoutType::SingleCalorie => {
-                todo!()
-            },
-            _ => { unreachable!() }
-        };
+        let split_kind = SplitKind::of(workout_type).ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidData, "Not a single workout."))?;
+
+        if split_size == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                "Invalid split size of 0 in workout record."));
+        }
+
+        let num_frames = Self::num_frames(split_kind, total_duration, total_distance, split_size);
+
+        check_num_frames(num_frames)?;
 
         let mut frames = Vec::with_capacity(num_frames as usize);
 
@@ -176,6 +523,7 @@ impl SingleEntry {
         Ok(Self {
             magic,
             workout_type,
+            machine,
             unknown_1,
             serial_number,
             timestamp,
@@ -187,44 +535,160 @@ impl SingleEntry {
             total_distance,
             spm,
             split_info,
+            split_kind,
             split_size,
             unknown_3,
             frames
         })
     }
+
+    /// Returns the number of frames following the header, which isn't
+    /// stored but derived from the totals and the split size.
+    fn num_frames(split_kind: SplitKind, total_duration: u16, total_distance: u32, split_size: u16) -> u32 {
+        match split_kind {
+            SplitKind::Distance => {
+                total_distance.div_ceil(split_size as u32)
+            },
+            SplitKind::Time => {
+                (total_duration as u32).div_ceil(split_size as u32)
+            },
+            SplitKind::Calorie => {
+                // The calorie target isn't part of the known header fields,
+                // so estimate the calories from duration and distance. The
+                // monitor uses the same formula to decide when the target is
+                // reached, so this should round to the target.
+                let total_calories = calories(
+                    Duration::from_millis(total_duration as u64 * 100),
+                    total_distance
+                ).round() as u32;
+
+                total_calories.div_ceil(split_size as u32)
+            }
+        }
+    }
+
+    /// Builds the storage record for a single workout. The split size is
+    /// taken from the first frame, so the frames have to match what the
+    /// monitor would have recorded for the totals.
+    pub fn from_workout(workout: &Workout) -> Result<Self,ParserError> {
+        let workout_type = workout.workout_type;
+
+        let first = workout.frames.first().ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput, "Workout has no splits."))?;
+
+        let split_kind = SplitKind::of(workout_type).ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput, "Not a single workout."))?;
+
+        let split_size = match split_kind {
+            SplitKind::Distance => first.distance as u64,
+            SplitKind::Time => tenths(first.work_duration),
+            SplitKind::Calorie => calories(first.work_duration, first.distance).round() as u64,
+        };
+        let split_size: u16 = record_field(split_size, "split size")?;
+        if split_size == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                "Workout has a split size of 0.").into());
+        }
+
+        let total_duration: u16 = record_field(tenths(workout.total_work_duration), "duration")?;
+        let total_distance: u32 = record_field(workout.total_distance as u64, "distance")?;
+
+        let num_frames = Self::num_frames(split_kind, total_duration, total_distance, split_size);
+        if num_frames as usize != workout.frames.len() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                format!("Workout has {} splits, but its totals require {}.", workout.frames.len(), num_frames)).into());
+        }
+
+        let frames = workout.frames.iter()
+            .map(|frame| SingleFrame::from_frame(frame, split_kind))
+            .collect::<Result<Vec<_>,_>>()?;
+
+        Ok(Self {
+            magic: STORAGE_MAGIC,
+            workout_type,
+            machine: workout.machine,
+            unknown_1: [0; 1],
+            serial_number: workout.serial_number,
+            timestamp: encode_timestamp(&workout.datetime)?,
+            user_id: workout.user_id,
+            unknown_2: [0; 4],
+            record_id: record_field(workout.record_id as u64, "record id")?,
+            magic_2: [0; 3],
+            total_duration,
+            total_distance,
+            spm: record_field(workout.spm.unwrap_or_default() as u64, "stroke rate")?,
+            split_info: 0,
+            split_kind,
+            split_size,
+            unknown_3: [0; 18],
+            frames
+        })
+    }
+
+    pub fn write<W: Write>(&self, f: &mut W) -> Result<(),std::io::Error> {
+        f.write_u8(self.magic)?;
+        f.write_u8(self.workout_type as u8)?;
+        f.write_u8(self.machine.code())?;
+        f.write_all(&self.unknown_1)?;
+        f.write_u32::<BigEndian>(self.serial_number)?;
+        f.write_u32::<BigEndian>(self.timestamp)?;
+        f.write_u16::<BigEndian>(self.user_id)?;
+        f.write_all(&self.unknown_2)?;
+        f.write_u8(self.record_id)?;
+        f.write_all(&self.magic_2)?;
+        f.write_u16::<BigEndian>(self.total_duration)?;
+        f.write_u32::<BigEndian>(self.total_distance)?;
+        f.write_u8(self.spm)?;
+        f.write_u8(self.split_info)?;
+        f.write_u16::<BigEndian>(self.split_size)?;
+        f.write_all(&self.unknown_3)?;
+
+        for frame in &self.frames {
+            frame.write(f)?;
+        }
+
+        Ok(())
+    }
 }
 
-impl Into<Workout> for SingleEntry {
-    fn into(self) -> Workout {
-        let mut frames: Vec<WorkoutFrame> = self.frames.into_iter().map(|f| f.into()).collect();
+impl TryFrom<SingleEntry> for Workout {
+    type Error = ParserError;
+
+    fn try_from(entry: SingleEntry) -> Result<Self,ParserError> {
+        let mut frames: Vec<WorkoutFrame> = entry.frames.into_iter().map(|f| f.into()).collect();
 
         for f in frames.iter_mut() {
-            match self.workout_type {
-                WorkoutType::FreeRow | WorkoutType::SingleDistance => {
-                    f.distance = self.split_size as u32;
-                },
-                WorkoutType::SingleTime => {
-                    todo!()
+            match entry.split_kind {
+                SplitKind::Distance => {
+                    f.distance = entry.split_size as u32;
                 },
-                WorkoutType::SingleCalorie => {
-                    todo!()
+                SplitKind::Time => {
+                    f.work_duration = Duration::from_millis(entry.split_size as u64 * 100);
                 },
-                _ => { unreachable!() }
+                SplitKind::Calorie => {
+                    // Frames contain the split duration, the distance is
+                    // derived from the power needed to burn split_size
+                    // calories in that time.
+                    f.distance = calorie_split_distance(entry.split_size as u32, f.work_duration);
+                }
             }
         }
 
-        Workout {
-            workout_type: self.workout_type,
-            serial_number: self.serial_number,
-            datetime: decode_timestamp(self.timestamp),
-            user_id: self.user_id,
-            record_id: self.record_id as u16,
-            total_distance: self.total_distance,
-            total_work_duration: Duration::from_millis(self.total_duration as u64 * 100),
+        Ok(Workout {
+            workout_type: entry.workout_type,
+            machine: entry.machine,
+            name: None,
+            serial_number: entry.serial_number,
+            datetime: decode_timestamp(entry.timestamp)?,
+            user_id: entry.user_id,
+            record_id: entry.record_id as u16,
+            total_distance: entry.total_distance,
+            total_work_duration: Duration::from_millis(entry.total_duration as u64 * 100),
             total_rest_duration: None,
-            spm: Some(self.spm.into()),
+            rest_interval: None,
+            spm: Some(entry.spm.into()),
             frames
-        }
+        })
     }
 }
 
@@ -232,7 +696,9 @@ impl Into<Workout> for SingleEntry {
 pub struct FixedIntervalEntry {
     magic: u8,
     workout_type: WorkoutType,
-    unknown_1: [u8; 2],
+    /// assumed to be the machine type as reported by the PM5, 0 on rowers
+    machine: MachineType,
+    unknown_1: [u8; 1],
     serial_number: u32,
     timestamp: u32,
     user_id: u16,
@@ -248,14 +714,93 @@ pub struct FixedIntervalEntry {
 }
 
 impl FixedIntervalEntry {
+    /// Names and sizes of the header fields, see `SingleEntry::FIELDS`.
+    pub const FIELDS: FieldLayout = &[
+        ("magic", 1), ("workout_type", 1), ("machine", 1), ("unknown_1", 1),
+        ("serial_number", 4), ("timestamp", 4), ("user_id", 2), ("unknown_2", 4),
+        ("record_id", 1), ("num_splits", 1), ("split_size", 2), ("interval_rest_time", 2),
+        ("total_work_duration", 4), ("total_rest_distance", 2), ("unknown_3", 22)
+    ];
+
+    /// Frames as stored, see `SingleEntry::frames`.
+    pub fn frames(&self) -> &[FixedIntervalFrame] {
+        &self.frames
+    }
+
     pub fn read<R: Read>(f: &mut R, magic: u8, workout_type: WorkoutType) -> Result<Self,std::io::Error> {
-        todo!();
+        let machine = MachineType::from_code(f.read_u8()?);
+        let mut unknown_1 = [0; 1];
+        f.read_exact(&mut unknown_1)?;
+        let serial_number = f.read_u32::<BigEndian>()?;
+        let timestamp = f.read_u32::<BigEndian>()?;
+        let user_id = f.read_u16::<BigEndian>()?;
+        let mut unknown_2 = [0; 4];
+        f.read_exact(&mut unknown_2)?;
+        let record_id = f.read_u8()?;
+        let num_splits = f.read_u8()?;
+        // 1/10s for time intervals, meters for distance intervals
+        let split_size = f.read_u16::<BigEndian>()?;
+        // seconds
+        let interval_rest_time = f.read_u16::<BigEndian>()?;
+        let total_work_duration = f.read_u32::<BigEndian>()?;
+        let total_rest_distance = f.read_u16::<BigEndian>()?;
+        let mut unknown_3 = [0; 22];
+        f.read_exact(&mut unknown_3)?;
+
+        check_num_frames(num_splits as u32)?;
+
+        let mut frames = Vec::with_capacity(num_splits as usize);
+
+        for _i in 0..num_splits {
+            frames.push(FixedIntervalFrame::read(f)?);
+        }
+
+        Ok(Self {
+            magic,
+            workout_type,
+            machine,
+            unknown_1,
+            serial_number,
+            timestamp,
+            user_id,
+            unknown_2,
+            record_id,
+            num_splits,
+            split_size,
+            interval_rest_time,
+            total_work_duration,
+            total_rest_distance,
+            unknown_3,
+            frames
+        })
     }
 }
 
-impl Into<Workout> for FixedIntervalEntry {
-    fn into(self) -> Workout {
-        todo!();
+impl TryFrom<FixedIntervalEntry> for Workout {
+    type Error = ParserError;
+
+    fn try_from(entry: FixedIntervalEntry) -> Result<Self,ParserError> {
+        let rest_duration = Duration::from_secs(entry.interval_rest_time as u64);
+        let frames: Vec<WorkoutFrame> = entry.frames.into_iter().map(|f| f.into()).collect();
+        // the monitor doesn't record rest after the last interval, so this
+        // can be less than the programmed rest times the intervals
+        let total_rest_duration = frames.iter().filter_map(|f| f.rest_duration).sum();
+
+        Ok(Workout {
+            workout_type: entry.workout_type,
+            machine: entry.machine,
+            name: None,
+            serial_number: entry.serial_number,
+            datetime: decode_timestamp(entry.timestamp)?,
+            user_id: entry.user_id,
+            record_id: entry.record_id as u16,
+            total_distance: frames.iter().map(|f| f.distance).sum(),
+            total_work_duration: Duration::from_millis(entry.total_work_duration as u64 * 100),
+            total_rest_duration: Some(total_rest_duration),
+            rest_interval: Some(rest_duration),
+            spm: None,
+            frames
+        })
     }
 }
 
@@ -278,76 +823,863 @@ pub struct VariableIntervalEntry {
 
 impl VariableIntervalEntry {
     pub fn read<R: Read>(f: &mut R, magic: u8, workout_type: WorkoutType) -> Result<Self,std::io::Error> {
-        todo!();
+        // TODO
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported,
+            "Variable interval workouts are not supported yet."))
     }
 }
 
-impl Into<Workout> for VariableIntervalEntry {
-    fn into(self) -> Workout {
+impl TryFrom<VariableIntervalEntry> for Workout {
+    type Error = ParserError;
+
+    fn try_from(entry: VariableIntervalEntry) -> Result<Self,ParserError> {
         todo!();
     }
 }
 
+/// Minimum, maximum and average heart rate of a split, each `None` if the
+/// monitor didn't store it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartRateRange {
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    pub avg: Option<u32>
+}
+
 #[derive(Debug)]
 pub struct SingleFrame {
     duration_or_distance: u16,
     heart_rate: u8,
     spm: u8,
-    unknown: [u8; 28]
+    // unconfirmed, see `HEART_RATE_RANGE_FIRMWARES`
+    heart_rate_min: u8,
+    heart_rate_max: u8,
+    heart_rate_avg: u8,
+    unknown: [u8; 25]
 }
 
 impl SingleFrame {
+    /// Names and sizes of the fields, in the order `read` reads them.
+    pub const FIELDS: FieldLayout = &[
+        ("duration_or_distance", 2), ("heart_rate", 1), ("spm", 1), ("heart_rate_min", 1),
+        ("heart_rate_max", 1), ("heart_rate_avg", 1), ("unknown", 25)
+    ];
+
     pub fn read<R: Read>(f: &mut R) -> Result<Self,std::io::Error> {
         let duration_or_distance = f.read_u16::<BigEndian>()?;
         let heart_rate = f.read_u8()?;
         let spm = f.read_u8()?;
-        let mut unknown = [0; 28];
+        let heart_rate_min = f.read_u8()?;
+        let heart_rate_max = f.read_u8()?;
+        let heart_rate_avg = f.read_u8()?;
+        let mut unknown = [0; 25];
         f.read_exact(&mut unknown)?;
 
-        // TODO: read heart min, max, median/mean
-
         Ok(Self {
             duration_or_distance,
             heart_rate,
             spm,
+            heart_rate_min,
+            heart_rate_max,
+            heart_rate_avg,
             unknown
         })
     }
+
+    /// Drag factor recorded for this frame, if its offset is known for the
+    /// firmware version that wrote the record.
+    pub fn drag_factor(&self, firmware: (u8, u8)) -> Option<u32> {
+        self.drag_factor_at(DRAG_FACTOR_OFFSETS, firmware)
+    }
+
+    fn drag_factor_at(&self, offsets: &[((u8, u8), usize)], firmware: (u8, u8)) -> Option<u32> {
+        offsets.iter()
+            .find(|(version, _)| *version == firmware)
+            .map(|(_, offset)| self.unknown[*offset] as u32)
+            // frames written by us or by monitors without a drag sensor
+            .filter(|drag_factor| *drag_factor > 0)
+    }
+
+    /// Heart rate range of this frame, if the firmware version that wrote
+    /// the record is known to store one.
+    pub fn heart_rate_range(&self, firmware: (u8, u8)) -> Option<HeartRateRange> {
+        self.heart_rate_range_of(HEART_RATE_RANGE_FIRMWARES, firmware)
+    }
+
+    fn heart_rate_range_of(&self, firmwares: &[(u8, u8)], firmware: (u8, u8)) -> Option<HeartRateRange> {
+        let value = |v: u8| if v > 0 { Some(v as u32) } else { None };
+
+        Some(HeartRateRange {
+            min: value(self.heart_rate_min),
+            max: value(self.heart_rate_max),
+            avg: value(self.heart_rate_avg)
+        }).filter(|_| firmwares.contains(&firmware))
+    }
+
+    /// Builds a frame of a single workout. Depending on the split kind, only
+    /// the distance or the duration is stored, the other one is implied by the
+    /// split size.
+    pub fn from_frame(frame: &WorkoutFrame, split_kind: SplitKind) -> Result<Self,ParserError> {
+        let duration_or_distance = match split_kind {
+            SplitKind::Time => frame.distance as u64,
+            SplitKind::Distance | SplitKind::Calorie => tenths(frame.work_duration)
+        };
+
+        Ok(Self {
+            duration_or_distance: record_field(duration_or_distance, "split")?,
+            heart_rate: record_field(frame.work_heart_rate.unwrap_or_default() as u64, "heart rate")?,
+            spm: record_field(frame.spm as u64, "stroke rate")?,
+            // not written until their meaning is confirmed
+            heart_rate_min: 0,
+            heart_rate_max: 0,
+            heart_rate_avg: 0,
+            unknown: [0; 25]
+        })
+    }
+
+    pub fn write<W: Write>(&self, f: &mut W) -> Result<(),std::io::Error> {
+        f.write_u16::<BigEndian>(self.duration_or_distance)?;
+        f.write_u8(self.heart_rate)?;
+        f.write_u8(self.spm)?;
+        f.write_u8(self.heart_rate_min)?;
+        f.write_u8(self.heart_rate_max)?;
+        f.write_u8(self.heart_rate_avg)?;
+        f.write_all(&self.unknown)
+    }
 }
 
-impl Into<WorkoutFrame> for SingleFrame {
-    fn into(self) -> WorkoutFrame {
+impl From<SingleFrame> for WorkoutFrame {
+    fn from(frame: SingleFrame) -> Self {
         // Depending on the type, either distance or duration
         // will have to be overwritten
         WorkoutFrame {
-            distance: self.duration_or_distance as u32,
-            work_duration: Duration::from_millis(self.duration_or_distance as u64 * 100),
+            distance: frame.duration_or_distance as u32,
+            work_duration: Duration::from_millis(frame.duration_or_distance as u64 * 100),
             rest_duration: None,
-            spm: self.spm as u32,
-            work_heart_rate: if self.heart_rate > 0 { Some(self.heart_rate as u32) } else { None },
+            rest_distance: None,
+            spm: frame.spm as u32,
+            work_heart_rate: if frame.heart_rate > 0 { Some(frame.heart_rate as u32) } else { None },
             rest_heart_rate: None,
+            // set from `SingleFrame::heart_rate_range` if the firmware is known
+            heart_rate_min: None,
+            heart_rate_max: None,
+            heart_rate_avg: None,
+            drag_factor: None,
         }
     }
 }
 
+/// Frame of a fixed interval workout. The field meanings are inferred from
+/// the header of the record and the settings of the workout, no record of
+/// an interval workout from a monitor was available to confirm them yet.
 #[derive(Debug)]
 pub struct FixedIntervalFrame {
+    work_duration: u32,
+    work_distance: u32,
+    spm: u8,
+    work_heart_rate: u8,
+    rest_heart_rate: u8,
+    rest_duration: u16,
+    rest_distance: u16,
+    unknown: [u8; 17]
+}
+
+impl FixedIntervalFrame {
+    /// Names and sizes of the fields, in the order `read` reads them.
+    pub const FIELDS: FieldLayout = &[
+        ("work_duration", 4), ("work_distance", 4), ("spm", 1), ("work_heart_rate", 1),
+        ("rest_heart_rate", 1), ("rest_duration", 2), ("rest_distance", 2), ("unknown", 17)
+    ];
+
+    pub fn read<R: Read>(f: &mut R) -> Result<Self,std::io::Error> {
+        // presumably 1/10s, like the total work duration
+        let work_duration = f.read_u32::<BigEndian>()?;
+        let work_distance = f.read_u32::<BigEndian>()?;
+        let spm = f.read_u8()?;
+        let work_heart_rate = f.read_u8()?;
+        let rest_heart_rate = f.read_u8()?;
+        // presumably seconds, like the interval rest time in the entry
+        let rest_duration = f.read_u16::<BigEndian>()?;
+        let rest_distance = f.read_u16::<BigEndian>()?;
+        let mut unknown = [0; 17];
+        f.read_exact(&mut unknown)?;
+
+        Ok(Self {
+            work_duration,
+            work_distance,
+            spm,
+            work_heart_rate,
+            rest_heart_rate,
+            rest_duration,
+            rest_distance,
+            unknown
+        })
+    }
+}
+
+impl From<FixedIntervalFrame> for WorkoutFrame {
+    fn from(frame: FixedIntervalFrame) -> Self {
+        WorkoutFrame {
+            distance: frame.work_distance,
+            work_duration: Duration::from_millis(frame.work_duration as u64 * 100),
+            rest_duration: Some(Duration::from_secs(frame.rest_duration as u64)),
+            rest_distance: Some(frame.rest_distance as u32),
+            spm: frame.spm as u32,
+            work_heart_rate: if frame.work_heart_rate > 0 { Some(frame.work_heart_rate as u32) } else { None },
+            rest_heart_rate: if frame.rest_heart_rate > 0 { Some(frame.rest_heart_rate as u32) } else { None },
+            heart_rate_min: None,
+            heart_rate_max: None,
+            heart_rate_avg: None,
+            drag_factor: None,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct VariableIntervalFrame {
 }
 
+/// Entry in `StrokeDataAccessTbl.bin`, pointing to the stroke samples of one
+/// workout in `StrokeDataStorage.bin`. Entries are 16 bytes; like the log
+/// data table, unused entries are filled with 0xff.
+#[derive(Debug, Default)]
+pub struct StrokeDataAccessTableEntry {
+    pub magic: u8,
+    unknown_1: u8,
+    pub record_id: u16,
+    pub serial_number: u32,
+    pub record_offset: u32,
+    pub num_strokes: u16,
+    unknown_2: [u8; 2]
+}
+
+impl StrokeDataAccessTableEntry {
+    pub const SIZE: u16 = 16;
+
+    pub fn read<R: Read>(f: &mut R) -> Result<Self,ParserError> {
+        let magic = f.read_u8()?;
+        let unknown_1 = f.read_u8()?;
+        let record_id = f.read_u16::<BigEndian>()?;
+        let serial_number = f.read_u32::<BigEndian>()?;
+        let record_offset = f.read_u32::<LittleEndian>()?;
+        let num_strokes = f.read_u16::<LittleEndian>()?;
+        let mut unknown_2 = [0; 2];
+        f.read_exact(&mut unknown_2)?;
+
+        Ok(Self {
+            magic,
+            unknown_1,
+            record_id,
+            serial_number,
+            record_offset,
+            num_strokes,
+            unknown_2
+        })
+    }
+}
+
+/// Favorite workout in `Favorites.bin`.
+///
+/// The file starts with a 0x80 byte header that isn't understood yet,
+/// followed by 20 byte entries, terminated by 0xff:
+///
+/// - 0x00: magic, always 0x93
+/// - 0x01: workout type, using the same codes as the log data
+/// - 0x02: u16, unknown
+/// - 0x04: u32 BE, target (meters for distance workouts)
+/// - 0x08: u32 BE, split size (same unit)
+/// - 0x0c: 8 bytes, unknown. Entries of variable intervals end with an
+///   increasing u16, presumably pointing to the interval definitions, which
+///   are not part of this file.
+#[derive(Debug)]
+pub struct FavoritesEntry {
+    pub magic: u8,
+    pub workout_type: u8,
+    unknown_1: [u8; 2],
+    pub target: u32,
+    pub split: u32,
+    unknown_2: [u8; 8]
+}
+
+impl FavoritesEntry {
+    pub const HEADER_SIZE: u64 = 0x80;
+    pub const SIZE: u64 = 20;
+
+    pub fn read<R: Read>(f: &mut R) -> Result<Self,ParserError> {
+        let magic = f.read_u8()?;
+        let workout_type = f.read_u8()?;
+        let mut unknown_1 = [0; 2];
+        f.read_exact(&mut unknown_1)?;
+        let target = f.read_u32::<BigEndian>()?;
+        let split = f.read_u32::<BigEndian>()?;
+        let mut unknown_2 = [0; 8];
+        f.read_exact(&mut unknown_2)?;
+
+        if magic != 0x93 && magic != 0xff {
+            return Err(ParserError::BadMagic { found: magic, offset: None });
+        }
+
+        Ok(Self {
+            magic,
+            workout_type,
+            unknown_1,
+            target,
+            split,
+            unknown_2
+        })
+    }
+}
+
+/// Layout of the stroke data files, read from `LogStrokeInfo.bin`. The file
+/// is 48 bytes, the known parts being:
+///
+/// - 0x00: magic, always `f4 f4 25 ca`
+/// - 0x04: u16 BE, presumably the size of a `StrokeDataAccessTbl.bin`
+///   entry (16), followed by a u16 BE of 0x1000
+/// - 0x08: u16 BE, presumably the size of a `StrokeDataStorage.bin` record
+///   (16), followed by a u16 BE of 0x1000
+/// - 0x0c: 28 bytes, unknown. Holds pairs of equal u32 BE values (1, 0x2000
+///   and 2), probably one value per file, and ends with `01 00 06 01`.
+///
+/// `DeviceLogInfo.bin` contains similar values, so this is likely a generic
+/// description of the monitor's record files.
+#[derive(Debug)]
+pub struct LogStrokeInfo {
+    pub access_table_entry_size: u16,
+    unknown_1: u16,
+    pub storage_record_size: u16,
+    unknown_2: u16,
+    unknown_3: [u8; 28]
+}
+
+impl LogStrokeInfo {
+    const MAGIC: [u8; 4] = [0xf4, 0xf4, 0x25, 0xca];
+
+    pub fn read<R: Read>(f: &mut R) -> Result<Self,ParserError> {
+        let mut magic = [0; 4];
+        f.read_exact(&mut magic)?;
+        if magic != Self::MAGIC {
+            return Err(ParserError::BadMagic { found: magic[0], offset: Some(0) });
+        }
+
+        let access_table_entry_size = f.read_u16::<BigEndian>()?;
+        let unknown_1 = f.read_u16::<BigEndian>()?;
+        let storage_record_size = f.read_u16::<BigEndian>()?;
+        let unknown_2 = f.read_u16::<BigEndian>()?;
+        let mut unknown_3 = [0; 28];
+        f.read_exact(&mut unknown_3)?;
+
+        Ok(Self {
+            access_table_entry_size,
+            unknown_1,
+            storage_record_size,
+            unknown_2,
+            unknown_3
+        })
+    }
+
+    /// Returns true if the record sizes match the ones the stroke data
+    /// parsers expect.
+    pub fn is_supported(&self) -> bool {
+        self.access_table_entry_size == StrokeDataAccessTableEntry::SIZE &&
+            self.storage_record_size == StrokeDataStorageEntry::SIZE
+    }
+}
+
+/// Single stroke sample in `StrokeDataStorage.bin`, 16 bytes each. Time is
+/// in 0.1 s since the start of the workout, distance in 0.1 m, pace in 0.1 s
+/// per 500m. A heart rate of 0 means no monitor was connected.
+#[derive(Debug)]
+pub struct StrokeDataStorageEntry {
+    time: u32,
+    distance: u32,
+    pace: u16,
+    spm: u8,
+    heart_rate: u8,
+    unknown: [u8; 4]
+}
+
+impl StrokeDataStorageEntry {
+    pub const SIZE: u16 = 16;
+
+    pub fn read<R: Read>(f: &mut R) -> Result<Self,ParserError> {
+        let time = f.read_u32::<LittleEndian>()?;
+        let distance = f.read_u32::<LittleEndian>()?;
+        let pace = f.read_u16::<LittleEndian>()?;
+        let spm = f.read_u8()?;
+        let heart_rate = f.read_u8()?;
+        let mut unknown = [0; 4];
+        f.read_exact(&mut unknown)?;
+
+        Ok(Self {
+            time,
+            distance,
+            pace,
+            spm,
+            heart_rate,
+            unknown
+        })
+    }
+}
+
+impl From<StrokeDataStorageEntry> for Stroke {
+    fn from(entry: StrokeDataStorageEntry) -> Self {
+        Self {
+            time: Duration::from_millis(entry.time as u64 * 100),
+            distance: entry.distance / 10,
+            pace: Duration::from_millis(entry.pace as u64 * 100),
+            spm: entry.spm as u32,
+            heart_rate: if entry.heart_rate > 0 { Some(entry.heart_rate as u32) } else { None }
+        }
+    }
+}
+
 
-pub fn decode_timestamp(timestamp: u32) -> chrono::NaiveDateTime {
+/// Calories burned for the given work duration and distance, using the same
+/// formula as the monitor (see `Workout::cal_hr`).
+fn calories(duration: Duration, distance: u32) -> f64 {
+    let hours = duration.as_secs_f64() / 3600.0;
+    if distance == 0 {
+        return 300.0 * hours;
+    }
+
+    let pace = duration.as_secs_f64() / distance as f64;
+    let watts = 2.8 / pace.powi(3);
+    (watts * 3.44 + 300.0) * hours
+}
+
+/// Inverse of `calories`: the distance that has to be rowed to burn the given
+/// calories in the given time.
+fn calorie_split_distance(calories: u32, duration: Duration) -> u32 {
+    let hours = duration.as_secs_f64() / 3600.0;
+    if hours == 0.0 {
+        return 0;
+    }
+
+    let watts = (calories as f64 / hours - 300.0) / 3.44;
+    if watts <= 0.0 {
+        return 0;
+    }
+
+    let pace = (2.8 / watts).cbrt();
+    (duration.as_secs_f64() / pace).round() as u32
+}
+
+/// Fails for frame counts no valid record can have, before anything is
+/// allocated for them.
+fn check_num_frames(num_frames: u32) -> Result<(),std::io::Error> {
+    if num_frames > MAX_FRAMES {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+            format!("Implausible number of splits ({}) in workout record.", num_frames)));
+    }
+
+    Ok(())
+}
+
+/// Returns a duration in tenths of a second, the unit used by most record
+/// fields.
+fn tenths(duration: Duration) -> u64 {
+    (duration.as_millis() / 100) as u64
+}
+
+/// Converts a value for a record field, failing if it doesn't fit.
+fn record_field<T: TryFrom<u64>>(value: u64, what: &str) -> Result<T,ParserError> {
+    T::try_from(value).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput,
+        format!("The {} of {} doesn't fit into a workout record.", what, value)).into())
+}
+
+/// Decodes a record timestamp. The date is packed into the upper 16 bits
+/// (7 bits year since 2000, 5 bits day, 4 bits month), the time uses one byte
+/// each for hour and minute. Only the low 5 and 6 bits of those bytes are
+/// meaningful, anything above is masked off. Fails for timestamps that don't
+/// encode a valid date or time, e.g. a month of 0.
+pub fn decode_timestamp(timestamp: u32) -> Result<chrono::NaiveDateTime,ParserError> {
     let year = 2000 + ((timestamp & (0b1111111 << 25)) >> 25);
     let day = (timestamp & (0b11111 << 20)) >> 20;
     let month = (timestamp & (0b1111 << 16)) >> 16;
 
-    let hour = (timestamp & (0b11111111 << 8)) >> 8;
-    let minute = timestamp & 0b1111111;
+    let hour = (timestamp & (0b11111 << 8)) >> 8;
+    let minute = timestamp & 0b111111;
+
+    let date = chrono::NaiveDate::from_ymd_opt(year as i32, month, day)
+        .ok_or(ParserError::InvalidTimestamp(timestamp))?;
+    let time = chrono::NaiveTime::from_hms_milli_opt(hour, minute, 0, 0)
+        .ok_or(ParserError::InvalidTimestamp(timestamp))?;
+    Ok(date.and_time(time))
+}
+
+/// Encodes a record timestamp, the inverse of `decode_timestamp`. Seconds are
+/// dropped. Fails for years outside of 2000 to 2127, which don't fit into
+/// the 7 bits of the year.
+pub fn encode_timestamp(datetime: &chrono::NaiveDateTime) -> Result<u32,ParserError> {
+    let year = datetime.year();
+    if !(2000..2128).contains(&year) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+            format!("Workout date in {} can't be stored on the drive.", year)).into());
+    }
+
+    Ok(((year - 2000) as u32) << 25 |
+        (datetime.day() << 20) |
+        (datetime.month() << 16) |
+        (datetime.hour() << 8) |
+        datetime.minute())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    fn parse(record: &[u8]) -> Result<Workout,ParserError> {
+        Workout::try_from(LogDataStorageEntry::read(&mut &record[..])?)
+    }
+
+    /// Builds a single workout record from 2024-03-12 06:15 with frames
+    /// holding the given durations or distances.
+    fn single_record(workout_type: WorkoutType, total_duration: u16, total_distance: u32, split_size: u16, frames: &[u16]) -> Vec<u8> {
+        let mut record = vec![STORAGE_MAGIC, workout_type as u8, 0, 0];
+        record.extend_from_slice(&430000000u32.to_be_bytes());
+        record.extend_from_slice(&0x30c3060fu32.to_be_bytes());
+        record.extend_from_slice(&[0; 10]);
+        record.extend_from_slice(&total_duration.to_be_bytes());
+        record.extend_from_slice(&total_distance.to_be_bytes());
+        record.extend_from_slice(&[24, 0]);
+        record.extend_from_slice(&split_size.to_be_bytes());
+        record.extend_from_slice(&[0; 18]);
+        for frame in frames {
+            record.extend_from_slice(&frame.to_be_bytes());
+            record.extend_from_slice(&[0, 24]);
+            record.extend_from_slice(&[0; 28]);
+        }
+        record
+    }
+
+    #[test]
+    fn split_kind() {
+        assert_eq!(SplitKind::of(WorkoutType::SingleDistance), Some(SplitKind::Distance));
+        assert_eq!(SplitKind::of(WorkoutType::FreeRow), Some(SplitKind::Distance));
+        assert_eq!(SplitKind::of(WorkoutType::SingleTime), Some(SplitKind::Time));
+        assert_eq!(SplitKind::of(WorkoutType::SingleCalorie), Some(SplitKind::Calorie));
+        assert_eq!(SplitKind::of(WorkoutType::VariableInterval), None);
+
+        // 2000m in 500m splits, the frames store the durations
+        let mut record = single_record(WorkoutType::SingleDistance, 4800, 2000, 500, &[1200, 1190, 1210, 1200]);
+        let workout = parse(&record).unwrap();
+        assert_eq!(workout.frames.iter().map(|f| f.distance).collect::<Vec<_>>(), vec![500; 4]);
+        assert_eq!(workout.frames[1].work_duration, Duration::from_secs(119));
+        assert_eq!(workout.total_work_duration, Duration::from_secs(480));
+
+        // the split_info byte doesn't change how frames are read
+        record[29] = 0x01;
+        assert_eq!(parse(&record).unwrap(), workout);
+    }
+
+    #[test]
+    fn single_time() {
+        // 10:00 in 2:00 splits
+        let record = single_record(WorkoutType::SingleTime, 6000, 2515, 1200, &[500, 505, 510, 500, 500]);
+        assert_eq!(record.len(), 50 + 5 * 32);
+
+        let workout = parse(&record).unwrap();
+        assert_eq!(workout.frames.len(), 5);
+        assert_eq!(workout.frames.iter().map(|f| f.distance).collect::<Vec<_>>(), vec![500, 505, 510, 500, 500]);
+        assert!(workout.frames.iter().all(|f| f.work_duration == Duration::from_secs(120)));
+        assert_eq!(workout.total_work_duration, Duration::from_secs(600));
+        assert_eq!(workout.total_distance, 2515);
+    }
+
+    #[test]
+    fn single_calorie() {
+        // 2000m in 8:00 are 133 calories, so 7 splits of 20 calories
+        let record = single_record(WorkoutType::SingleCalorie, 4800, 2000, 20, &[722; 7]);
+
+        let workout = parse(&record).unwrap();
+        assert_eq!(workout.frames.len(), 7);
+        assert_eq!(workout.frames[0].work_duration, Duration::from_millis(72200));
+        assert_eq!(workout.frames[0].distance, 301);
+
+        // without enough frames for the calories, the record is cut short
+        let record = single_record(WorkoutType::SingleCalorie, 4800, 2000, 20, &[722; 6]);
+        assert!(matches!(parse(&record), Err(ParserError::UnexpectedEof)));
+    }
+
+    // The interval tests only check that records are decoded consistently
+    // with the inferred layout, see `FixedIntervalFrame`.
+    #[test]
+    fn fixed_interval() {
+        let entry = LogDataStorageEntry::read(&mut &TIME_INTERVAL_RECORD[..]).unwrap();
+        assert_eq!(entry.layout_size(), Some(TIME_INTERVAL_RECORD.len()));
+
+        let workout = Workout::try_from(entry).unwrap();
+        assert_eq!(workout.workout_type, WorkoutType::TimeInterval);
+        assert_eq!(workout.datetime, datetime(2024, 3, 12, 6, 15));
+        assert_eq!(workout.frames.len(), 3);
+        assert_eq!(workout.frames.iter().map(|f| f.distance).collect::<Vec<_>>(), vec![310, 305, 300]);
+        assert_eq!(workout.total_distance, 915);
+        assert_eq!(workout.total_work_duration, Duration::from_secs(360));
+        assert_eq!(workout.rest_interval, Some(Duration::from_secs(60)));
+        assert_eq!(workout.frames[0].rest_duration, Some(Duration::from_secs(60)));
+        assert_eq!(workout.frames[2].rest_duration, Some(Duration::ZERO));
+        assert_eq!(workout.total_rest_duration, Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn interval_heart_rates() {
+        let workout = parse(&TIME_INTERVAL_RECORD).unwrap();
+        let heart_rates: Vec<(Option<u32>, Option<u32>)> = workout.frames.iter()
+            .map(|f| (f.work_heart_rate, f.rest_heart_rate))
+            .collect();
+        assert_eq!(heart_rates, [(Some(165), Some(120)), (Some(170), Some(125)), (Some(172), Some(130))]);
+        // only the work heart rates count for the workout
+        assert_eq!(workout.heart_rate(), Some(169));
+        assert_eq!(workout.min_heart_rate(), Some(165));
+    }
+
+    #[test]
+    fn single_split_size_zero() {
+        let record = single_record(WorkoutType::SingleDistance, 4800, 2000, 0, &[]);
+        match parse(&record) {
+            Err(ParserError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn single_too_many_splits() {
+        // 4,000,000m in 1m splits, only the header is present
+        let record = single_record(WorkoutType::SingleDistance, 4800, 4_000_000, 1, &[]);
+        match parse(&record) {
+            Err(ParserError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn fixed_interval_frame() {
+        let frame = FixedIntervalFrame::read(&mut &TIME_INTERVAL_RECORD[52..84]).unwrap();
+        let frame = WorkoutFrame::from(frame);
+        assert_eq!(frame.work_duration, Duration::from_secs(120));
+        assert_eq!(frame.distance, 310);
+        assert_eq!(frame.spm, 30);
+        assert_eq!(frame.work_heart_rate, Some(165));
+        assert_eq!(frame.rest_heart_rate, Some(120));
+        assert_eq!(frame.rest_duration, Some(Duration::from_secs(60)));
+        assert_eq!(frame.rest_distance, Some(20));
 
-    let date = chrono::NaiveDate::from_ymd(year as i32, month, day);
-    let time = chrono::NaiveTime::from_hms_milli(hour, minute, 0, 0);
-    date.and_time(time)
+        let unset = WorkoutFrame::from(FixedIntervalFrame::read(&mut &[0; 32][..]).unwrap());
+        assert_eq!(unset.work_heart_rate, None);
+        assert_eq!(unset.rest_heart_rate, None);
+    }
+
+    #[test]
+    fn drag_factor() {
+        let mut bytes = [0; 32];
+        bytes[..7].copy_from_slice(&[0x04, 0xb0, 0x00, 0x18, 0x00, 0x00, 0x00]);
+        bytes[7 + 3] = 125;
+        let frame = SingleFrame::read(&mut &bytes[..]).unwrap();
+
+        let offsets = [((32, 0), 3)];
+        assert_eq!(frame.drag_factor_at(&offsets, (32, 0)), Some(125));
+        assert_eq!(frame.drag_factor_at(&offsets, (31, 4)), None);
+        // no firmware is known to store it yet
+        assert_eq!(frame.drag_factor((32, 0)), None);
+
+        let unset = SingleFrame::read(&mut &[0; 32][..]).unwrap();
+        assert_eq!(unset.drag_factor_at(&offsets, (32, 0)), None);
+    }
+
+    #[test]
+    fn heart_rate_range() {
+        let mut bytes = [0; 32];
+        bytes[..7].copy_from_slice(&[0x04, 0xb0, 150, 0x18, 132, 171, 0]);
+        let frame = SingleFrame::read(&mut &bytes[..]).unwrap();
+
+        let firmwares = [(32, 0)];
+        assert_eq!(frame.heart_rate_range_of(&firmwares, (32, 0)),
+            Some(HeartRateRange { min: Some(132), max: Some(171), avg: None }));
+        assert_eq!(frame.heart_rate_range_of(&firmwares, (31, 4)), None);
+        // no firmware is known to store it yet
+        assert_eq!(frame.heart_rate_range((32, 0)), None);
+
+        let frame = WorkoutFrame::from(SingleFrame::read(&mut &bytes[..]).unwrap());
+        assert_eq!(frame.work_heart_rate, Some(150));
+        assert_eq!((frame.heart_rate_min, frame.heart_rate_max, frame.heart_rate_avg), (None, None, None));
+    }
+
+    #[test]
+    fn fixed_interval_truncated() {
+        assert!(matches!(parse(&TIME_INTERVAL_RECORD[..100]), Err(ParserError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn decode_end_of_hour() {
+        // 2021-11-07 14:59
+        assert_eq!(decode_timestamp(0x2a7b0e3b).unwrap(), datetime(2021, 11, 7, 14, 59));
+        // 2023-12-31 23:59
+        assert_eq!(decode_timestamp(0x2ffc173b).unwrap(), datetime(2023, 12, 31, 23, 59));
+    }
+
+    #[test]
+    fn decode_midnight() {
+        // 2022-01-01 00:00
+        assert_eq!(decode_timestamp(0x2c110000).unwrap(), datetime(2022, 1, 1, 0, 0));
+    }
+
+    #[test]
+    fn decode_masks_unused_bits() {
+        // the top bits of the hour and minute bytes are ignored
+        assert_eq!(decode_timestamp(0x2a7beefb).unwrap(), datetime(2021, 11, 7, 14, 59));
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(matches!(decode_timestamp(0x2a0b0e3b), Err(ParserError::InvalidTimestamp(0x2a0b0e3b))));
+        assert!(matches!(decode_timestamp(0x2a7d0e3b), Err(ParserError::InvalidTimestamp(_))));
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        for &dt in &[
+            datetime(2000, 1, 1, 0, 0),
+            datetime(2021, 11, 7, 14, 59),
+            datetime(2024, 2, 29, 23, 59),
+            datetime(2127, 12, 31, 12, 30)
+        ] {
+            assert_eq!(decode_timestamp(encode_timestamp(&dt).unwrap()).unwrap(), dt);
+        }
+    }
+
+    #[test]
+    fn encode_rejects_years_out_of_range() {
+        assert!(encode_timestamp(&datetime(1999, 12, 31, 23, 59)).is_err());
+        assert!(encode_timestamp(&datetime(2128, 1, 1, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn access_table_entry() {
+        let mut bytes = [0; 32];
+        bytes[..6].copy_from_slice(&[0xf0, 0x06, 0x3c, 0x00, 0x01, 0x03]);
+        bytes[12] = 3;
+        bytes[16..18].copy_from_slice(&0x1234u16.to_le_bytes());
+        bytes[24] = 148;
+        bytes[26] = 7;
+
+        let entry = LogDataAccessTableEntry::read(&mut &bytes[..]).unwrap();
+        assert_eq!(entry.workout_type, 0x06);
+        assert_eq!(entry.workout_name(), Some(WorkoutName { list: 1, index: 3 }));
+        assert_eq!(entry.workout_name().unwrap().to_string(), "List 1, #3");
+        assert_eq!(entry.interval_rest_time(), Some(Duration::from_secs(60)));
+        assert_eq!(entry.num_splits, 3);
+        assert_eq!(entry.record_offset, 0x1234);
+        assert_eq!(entry.record_size, 148);
+        assert_eq!(entry.index, 7);
+
+        let mut written = Vec::new();
+        entry.write(&mut written).unwrap();
+        assert_eq!(written, bytes);
+
+        // no program, e.g. Just Row, and no rest for single workouts
+        bytes[1] = 0x01;
+        bytes[4..6].copy_from_slice(&[0, 0]);
+        let entry = LogDataAccessTableEntry::read(&mut &bytes[..]).unwrap();
+        assert_eq!(entry.workout_name(), None);
+        assert_eq!(entry.interval_rest_time(), None);
+    }
+
+    #[test]
+    fn log_stroke_info() {
+        // the LogStrokeInfo.bin written by init, which was taken from a PM5
+        let sample = include_bytes!("data/LogStrokeInfo.bin");
+        let info = LogStrokeInfo::read(&mut &sample[..]).unwrap();
+        assert_eq!(info.access_table_entry_size, 16);
+        assert_eq!(info.storage_record_size, 16);
+        assert!(info.is_supported());
+
+        let mut other = sample.to_vec();
+        other[9] = 32;
+        let info = LogStrokeInfo::read(&mut &other[..]).unwrap();
+        assert_eq!(info.storage_record_size, 32);
+        assert!(!info.is_supported());
+
+        other[0] = 0x12;
+        assert!(matches!(LogStrokeInfo::read(&mut &other[..]), Err(ParserError::BadMagic { found: 0x12, offset: Some(0) })));
+        assert!(matches!(LogStrokeInfo::read(&mut &sample[..20]), Err(ParserError::UnexpectedEof)));
+    }
+
+    #[test]
+    fn record_fields() {
+        let field = |fields: &[RecordField], name: &str, frame| *fields.iter()
+            .find(|f| f.name == name && f.frame == frame).unwrap();
+
+        let fields = storage_record_fields(&TIME_INTERVAL_RECORD);
+        assert_eq!(field(&fields, "num_splits", None), RecordField { name: "num_splits", frame: None, offset: 19, size: 1 });
+        assert_eq!(field(&fields, "interval_rest_time", None).offset, 22);
+        assert_eq!(field(&fields, "unknown_3", None), RecordField { name: "unknown_3", frame: None, offset: 30, size: 22 });
+        assert_eq!(field(&fields, "work_duration", Some(0)).offset, 52);
+        assert_eq!(field(&fields, "rest_duration", Some(2)).offset, 127);
+        assert!(field(&fields, "unknown_2", None).is_unknown());
+        assert!(!field(&fields, "timestamp", None).is_unknown());
+        // the fields cover the record without gaps
+        assert_eq!(fields.iter().map(|f| f.size).sum::<usize>(), TIME_INTERVAL_RECORD.len());
+        assert!(fields.windows(2).all(|w| w[0].offset + w[0].size == w[1].offset));
+
+        // incomplete fields are cut off, incomplete frames left unknown
+        let fields = storage_record_fields(&TIME_INTERVAL_RECORD[..60]);
+        assert_eq!(*fields.last().unwrap(), RecordField { name: "unknown", frame: None, offset: 52, size: 8 });
+        let fields = storage_record_fields(&TIME_INTERVAL_RECORD[..10]);
+        assert_eq!(*fields.last().unwrap(), RecordField { name: "timestamp", frame: None, offset: 8, size: 2 });
+
+        // variable intervals aren't laid out yet
+        let fields = storage_record_fields(&[0x95, 0x08, 1, 2, 3]);
+        assert_eq!(fields.iter().map(|f| (f.name, f.offset)).collect::<Vec<_>>(),
+            [("magic", 0), ("workout_type", 1), ("unknown", 2)]);
+
+        let fields = access_table_fields(&[0; 32]);
+        assert_eq!(field(&fields, "record_offset", None).offset, 16);
+        assert_eq!(field(&fields, "record_size", None).offset, 24);
+        assert_eq!(field(&fields, "index", None).offset, 26);
+        assert_eq!(fields.last().unwrap().offset + fields.last().unwrap().size, 32);
+    }
+
+    #[test]
+    fn unknown_fields_match_raw_bytes() {
+        // mark the unknown bytes, so they can't be confused with each other
+        let mut record = TIME_INTERVAL_RECORD;
+        let fields = storage_record_fields(&record);
+        for field in fields.iter().filter(|f| f.is_unknown()) {
+            for (i, byte) in record.iter_mut().enumerate().skip(field.offset).take(field.size) {
+                *byte = i as u8;
+            }
+        }
+
+        let entry = match LogDataStorageEntry::read(&mut &record[..]).unwrap() {
+            LogDataStorageEntry::FixedInterval(entry) => entry,
+            entry => panic!("Expected a fixed interval, got {:?}", entry)
+        };
+
+        let raw = |name: &str, frame: Option<usize>| {
+            let field = fields.iter().find(|f| f.name == name && f.frame == frame).unwrap();
+            &record[field.offset..(field.offset + field.size)]
+        };
+        let unknown = entry.unknown_fields();
+        assert_eq!(unknown.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["unknown_1", "unknown_2", "unknown_3"]);
+        for (name, bytes) in &unknown {
+            assert_eq!(*bytes, raw(name, None), "{}", name);
+        }
+        assert_eq!(unknown[2].1.len(), 22);
+
+        assert_eq!(entry.frames().len(), 3);
+        for (i, frame) in entry.frames().iter().enumerate() {
+            assert_eq!(frame.unknown_fields(), [("unknown", raw("unknown", Some(i)))]);
+        }
+
+        let mut access_table_entry: Vec<u8> = (0..32).collect();
+        access_table_entry[..2].copy_from_slice(&[0xf0, 0x06]);
+        let fields = access_table_fields(&access_table_entry);
+        let entry = LogDataAccessTableEntry::read(&mut &access_table_entry[..]).unwrap();
+        for (name, bytes) in entry.unknown_fields() {
+            let field = fields.iter().find(|f| f.name == name).unwrap();
+            assert_eq!(bytes, &access_table_entry[field.offset..(field.offset + field.size)], "{}", name);
+        }
+    }
 }
@@ -0,0 +1,123 @@
+use crate::workouts::*;
+
+/// Personal data of the athlete that the drive doesn't store, used to
+/// personalize calorie estimates and heart rate zones. All fields are
+/// optional, so a profile can be combined from several sources with `or`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AthleteProfile {
+    pub weight_kg: Option<f64>,
+    pub age: Option<u32>,
+    pub sex: Option<Sex>,
+    pub max_hr: Option<u32>
+}
+
+fn invalid_line(line: usize, msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Line {}: {}", line, msg))
+}
+
+impl AthleteProfile {
+    /// Parses a profile from TOML with top-level keys only, e.g.:
+    ///
+    /// ```text
+    /// weight_kg = 80.5
+    /// age = 35
+    /// sex = "female"
+    /// max_hr = 185
+    /// ```
+    ///
+    /// Missing keys are left empty, unknown keys are rejected so typos don't
+    /// go unnoticed.
+    pub fn parse(s: &str) -> Result<Self,std::io::Error> {
+        let mut profile = Self::default();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => return Err(invalid_line(i + 1, "Expected key = value."))
+            };
+            let unquoted = value.trim_matches('"');
+
+            match key {
+                "weight_kg" => profile.weight_kg = Some(value.parse()
+                    .map_err(|_| invalid_line(i + 1, "weight_kg has to be a number."))?),
+                "age" => profile.age = Some(value.parse()
+                    .map_err(|_| invalid_line(i + 1, "age has to be a whole number."))?),
+                "max_hr" => profile.max_hr = Some(value.parse()
+                    .map_err(|_| invalid_line(i + 1, "max_hr has to be a whole number."))?),
+                "sex" => profile.sex = Some(match unquoted.to_lowercase().as_str() {
+                    "male" | "m" => Sex::Male,
+                    "female" | "f" => Sex::Female,
+                    _ => return Err(invalid_line(i + 1, "sex has to be \"male\" or \"female\"."))
+                }),
+                _ => return Err(invalid_line(i + 1, &format!("Unknown key {}.", key)))
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Fills the fields missing from this profile with those of `other`.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            weight_kg: self.weight_kg.or(other.weight_kg),
+            age: self.age.or(other.age),
+            sex: self.sex.or(other.sex),
+            max_hr: self.max_hr.or(other.max_hr)
+        }
+    }
+
+    /// Calorie model for this athlete, if the weight is known.
+    pub fn calorie_model(&self) -> Option<CalorieModel> {
+        self.weight_kg.map(|weight_kg| CalorieModel { weight_kg, age: self.age, sex: self.sex })
+    }
+
+    /// Maximum heart rate for heart rate zones. Without a measured value, it
+    /// is estimated from the age as `220 - age`.
+    pub fn max_heart_rate(&self) -> Option<u32> {
+        self.max_hr.or_else(|| self.age.map(|age| 220u32.saturating_sub(age)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calorie_model_needs_weight() {
+        let profile = AthleteProfile::parse("age = 35\nsex = \"female\"").unwrap();
+        assert_eq!(profile.calorie_model(), None);
+
+        let flags = AthleteProfile { weight_kg: Some(80.5), ..AthleteProfile::default() };
+        let model = flags.or(profile).calorie_model().unwrap();
+        assert_eq!(model.weight_kg, 80.5);
+        assert_eq!(model.age, Some(35));
+        assert_eq!(model.sex, Some(Sex::Female));
+    }
+
+    #[test]
+    fn parse() {
+        let profile = AthleteProfile::parse(concat!(
+            "# from the last test\n",
+            "weight_kg = 72\n",
+            "  age=41  # years\n",
+            "sex = m\n",
+        )).unwrap();
+        assert_eq!(profile, AthleteProfile { weight_kg: Some(72.0), age: Some(41), sex: Some(Sex::Male), max_hr: None });
+        // estimated from the age
+        assert_eq!(profile.max_heart_rate(), Some(179));
+        assert_eq!(AthleteProfile { max_hr: Some(190), ..profile }.max_heart_rate(), Some(190));
+
+        assert_eq!(AthleteProfile::parse("").unwrap(), AthleteProfile::default());
+        assert_eq!(AthleteProfile::default().max_heart_rate(), None);
+
+        let error = |s| AthleteProfile::parse(s).unwrap_err().to_string();
+        assert_eq!(error("age = 35\nmax_hr = fast"), "Line 2: max_hr has to be a whole number.");
+        assert_eq!(error("sex = \"x\""), "Line 1: sex has to be \"male\" or \"female\".");
+        assert_eq!(error("weight_kg"), "Line 1: Expected key = value.");
+    }
+}
@@ -0,0 +1,22 @@
+/// Receives progress updates from long running operations like writing
+/// firmwares, so they can be shown in any kind of UI.
+pub trait ProgressReporter {
+    /// Called once before any work is done, with the total amount of work.
+    fn start(&mut self, total: u64);
+
+    /// Called whenever progress was made, with the amount of work done so
+    /// far.
+    fn update(&mut self, done: u64);
+
+    /// Called once after the operation completed successfully.
+    fn finish(&mut self);
+}
+
+/// Reporter that ignores all updates.
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn start(&mut self, _total: u64) {}
+    fn update(&mut self, _done: u64) {}
+    fn finish(&mut self) {}
+}
@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::PathBuf;
+
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+pub trait ReadWriteSeek: Read + Write + Seek {}
+impl<T: Read + Write + Seek> ReadWriteSeek for T {}
+
+/// Wrapper that rejects all writes, so images opened without write access
+/// behave the same whether they are backed by a file or by memory.
+pub struct ReadOnly<T>(pub T);
+
+impl<T: Read> Read for ReadOnly<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize,std::io::Error> {
+        self.0.read(buf)
+    }
+}
+
+impl<T: Seek> Seek for ReadOnly<T> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> Result<u64,std::io::Error> {
+        self.0.seek(pos)
+    }
+}
+
+impl<T> Write for ReadOnly<T> {
+    fn write(&mut self, _buf: &[u8]) -> Result<usize,std::io::Error> {
+        Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Drive was opened read-only."))
+    }
+
+    fn flush(&mut self) -> Result<(),std::io::Error> {
+        Ok(())
+    }
+}
+
+/// Access to the files on a drive. All paths are relative to the root of the
+/// drive and use `/` as separator, e.g. `Concept2/Logbook/UserStatic.bin`.
+pub trait Storage {
+    /// Opens an existing file for reading.
+    fn open_file(&self, path: &str) -> Result<Box<dyn ReadSeek + '_>,std::io::Error>;
+
+    /// Opens an existing file for reading and writing.
+    fn open_file_rw(&self, path: &str) -> Result<Box<dyn ReadWriteSeek + '_>,std::io::Error>;
+
+    /// Creates a file, truncating it if it already exists.
+    fn create_file(&self, path: &str) -> Result<Box<dyn ReadWriteSeek + '_>,std::io::Error>;
+
+    /// Creates a directory. The parent directory has to exist.
+    fn create_dir(&self, path: &str) -> Result<(),std::io::Error>;
+
+    /// Lists the names of all entries in a directory.
+    fn list_dir(&self, path: &str) -> Result<Vec<String>,std::io::Error>;
+
+    /// Removes a file or an empty directory.
+    fn remove(&self, path: &str) -> Result<(),std::io::Error>;
+
+    /// Returns true if the given file or directory exists.
+    fn exists(&self, path: &str) -> bool;
+
+    /// Returns true if the given path is an existing directory.
+    fn is_dir(&self, path: &str) -> bool;
+}
+
+/// FAT filesystem read directly from a block device or image
+pub struct FatStorage<T: fatfs::ReadWriteSeek> {
+    fs: fatfs::FileSystem<T>
+}
+
+impl<T: fatfs::ReadWriteSeek> FatStorage<T> {
+    pub fn new(disk: T) -> Result<Self,std::io::Error> {
+        let fs = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())?;
+        Ok(Self { fs })
+    }
+}
+
+impl<T: fatfs::ReadWriteSeek> Storage for FatStorage<T> {
+    fn open_file(&self, path: &str) -> Result<Box<dyn ReadSeek + '_>,std::io::Error> {
+        Ok(Box::new(self.fs.root_dir().open_file(path)?))
+    }
+
+    fn open_file_rw(&self, path: &str) -> Result<Box<dyn ReadWriteSeek + '_>,std::io::Error> {
+        Ok(Box::new(self.fs.root_dir().open_file(path)?))
+    }
+
+    fn create_file(&self, path: &str) -> Result<Box<dyn ReadWriteSeek + '_>,std::io::Error> {
+        let mut file = self.fs.root_dir().create_file(path)?;
+        file.truncate()?;
+        Ok(Box::new(file))
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(),std::io::Error> {
+        self.fs.root_dir().create_dir(path)?;
+        Ok(())
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<String>,std::io::Error> {
+        self.fs.root_dir().open_dir(path)?.iter()
+            .map(|e| e.map(|e| e.file_name()))
+            .filter(|n| !matches!(n.as_deref(), Ok(".") | Ok("..")))
+            .collect()
+    }
+
+    fn remove(&self, path: &str) -> Result<(),std::io::Error> {
+        self.fs.root_dir().remove(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.fs.root_dir().open_file(path).is_ok() || self.fs.root_dir().open_dir(path).is_ok()
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.fs.root_dir().open_dir(path).is_ok()
+    }
+}
+
+/// Drive that is already mounted at a directory, accessed through the
+/// regular filesystem
+pub struct MountedStorage {
+    root: PathBuf
+}
+
+impl MountedStorage {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        path.split('/').fold(self.root.clone(), |p, c| p.join(c))
+    }
+}
+
+impl Storage for MountedStorage {
+    fn open_file(&self, path: &str) -> Result<Box<dyn ReadSeek + '_>,std::io::Error> {
+        Ok(Box::new(BufReader::new(File::open(self.path(path))?)))
+    }
+
+    fn open_file_rw(&self, path: &str) -> Result<Box<dyn ReadWriteSeek + '_>,std::io::Error> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.path(path))?;
+        Ok(Box::new(file))
+    }
+
+    fn create_file(&self, path: &str) -> Result<Box<dyn ReadWriteSeek + '_>,std::io::Error> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.path(path))?;
+        Ok(Box::new(file))
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(),std::io::Error> {
+        std::fs::create_dir(self.path(path))
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<String>,std::io::Error> {
+        std::fs::read_dir(self.path(path))?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect()
+    }
+
+    fn remove(&self, path: &str) -> Result<(),std::io::Error> {
+        let path = self.path(path);
+        if path.is_dir() {
+            std::fs::remove_dir(path)
+        } else {
+            std::fs::remove_file(path)
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.path(path).exists()
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.path(path).is_dir()
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::IsTerminal;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right
+}
+
+/// ANSI escape sequences used for styling
+const BOLD_GREEN: &str = "\x1b[1;32m";
+const GREY: &str = "\x1b[38;2;127;127;127m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Table for terminal output, with each column as wide as its widest cell.
+/// Colors are only used if enabled with `color`, plain text is the default.
+pub struct Table {
+    columns: Vec<(String, Align)>,
+    /// cells and whether the row is highlighted
+    rows: Vec<(Vec<String>, bool)>,
+    color: bool
+}
+
+impl Table {
+    pub fn new(columns: &[(&str, Align)]) -> Self {
+        Self {
+            columns: columns.iter().map(|(title, align)| (title.to_string(), *align)).collect(),
+            rows: Vec::new(),
+            color: false
+        }
+    }
+
+    /// Enables or disables colors, e.g. with `is_terminal` of the output.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push((cells, false));
+    }
+
+    /// Adds a row that is printed in yellow, e.g. for personal bests.
+    pub fn highlighted_row(&mut self, cells: Vec<String>) {
+        self.rows.push((cells, true));
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        self.columns.iter().enumerate().map(|(i, (title, _))| {
+            self.rows.iter()
+                .filter_map(|(cells, _)| cells.get(i))
+                .map(|c| c.chars().count())
+                .fold(title.chars().count(), usize::max)
+        }).collect()
+    }
+
+    fn line(&self, cells: &[String], widths: &[usize]) -> String {
+        let cells: Vec<String> = self.columns.iter().zip(widths).enumerate().map(|(i, ((_, align), width))| {
+            let cell = cells.get(i).map(|c| c.as_str()).unwrap_or_default();
+            match align {
+                Align::Left => format!("{:<width$}", cell, width = width),
+                Align::Right => format!("{:>width$}", cell, width = width)
+            }
+        }).collect();
+
+        cells.join(" ").trim_end().to_string()
+    }
+
+    fn styled(&self, line: String, style: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, line, RESET)
+        } else {
+            line
+        }
+    }
+
+    /// Returns the table as text, with a header and a separator line.
+    pub fn render(&self) -> String {
+        let widths = self.widths();
+        let titles: Vec<String> = self.columns.iter().map(|(title, _)| title.clone()).collect();
+
+        let header = self.line(&titles, &widths);
+        let separator = "=".repeat(widths.iter().sum::<usize>() + widths.len().saturating_sub(1));
+
+        let mut lines = Vec::with_capacity(self.rows.len() + 2);
+        lines.push(self.styled(header, BOLD_GREEN));
+        lines.push(self.styled(separator, GREY));
+
+        for (cells, highlighted) in &self.rows {
+            let line = self.line(cells, &widths);
+            if *highlighted {
+                lines.push(self.styled(line, YELLOW));
+            } else {
+                lines.push(line);
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    /// Prints the table to stdout, with colors if stdout is a terminal and
+    /// `color` allows them.
+    pub fn print(self, color: bool) {
+        let color = color && std::io::stdout().is_terminal();
+        println!("{}", self.color(color).render());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Table {
+        let mut table = Table::new(&[("#", Align::Right), ("Type", Align::Left), ("Dist.", Align::Right)]);
+        table.row(vec!["1".to_string(), "Distance".to_string(), "2000".to_string()]);
+        table.highlighted_row(vec!["2".to_string(), "Variable Interval".to_string(), "10000".to_string()]);
+        table.row(vec!["10".to_string(), "Time".to_string(), "500".to_string()]);
+        table
+    }
+
+    #[test]
+    fn plain_alignment() {
+        assert_eq!(sample().color(false).render(), [
+            " # Type              Dist.",
+            "==========================",
+            " 1 Distance           2000",
+            " 2 Variable Interval 10000",
+            "10 Time                500",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn colored() {
+        let rendered = sample().color(true).render();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], "\x1b[1;32m # Type              Dist.\x1b[0m");
+        assert_eq!(lines[3], "\x1b[33m 2 Variable Interval 10000\x1b[0m");
+        assert_eq!(lines[4], "10 Time                500");
+    }
+}
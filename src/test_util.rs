@@ -0,0 +1,149 @@
+//! Fixtures shared by the unit tests: drive images held in memory and
+//! workouts that can be written to them.
+
+use std::cell::RefCell;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::drive::Drive;
+use crate::workouts::*;
+
+/// In-memory image that can still be read after the drive using it is
+/// dropped, e.g. to reopen it or to corrupt it.
+#[derive(Clone, Default)]
+pub struct SharedImage(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+impl SharedImage {
+    pub fn new(size: usize) -> Self {
+        Self::from_vec(vec![0; size])
+    }
+
+    pub fn from_vec(bytes: Vec<u8>) -> Self {
+        Self(Rc::new(RefCell::new(Cursor::new(bytes))))
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.borrow().get_ref().clone()
+    }
+}
+
+impl Read for SharedImage {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl Write for SharedImage {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SharedImage {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.borrow_mut().seek(pos)
+    }
+}
+
+/// Directory in the system temp directory, removed again when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("concept2drive-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Size of the images created by `init_image`, large enough for a FAT16
+/// filesystem.
+pub const IMAGE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Returns the bytes of a freshly initialized drive image.
+pub fn init_image(user_name: &str) -> Vec<u8> {
+    let image = SharedImage::new(IMAGE_SIZE);
+    drop(Drive::init_stream(image.clone(), user_name.to_string()).unwrap());
+    image.to_vec()
+}
+
+/// Initializes a writable drive in memory, with the given workouts written
+/// to it.
+pub fn drive_with(workouts: &[Workout]) -> Drive {
+    let mut drive = Drive::from_stream(Cursor::new(init_image("test")), true).unwrap();
+    for workout in workouts {
+        drive.write_workout(workout).unwrap();
+    }
+    drive
+}
+
+/// Storage record of a time interval of 3x 2:00 with 1:00 rest, the last
+/// interval without rest. Synthetic: put together by hand following the
+/// inferred layout of `FixedIntervalFrame`, not captured from a monitor.
+pub const TIME_INTERVAL_RECORD: [u8; 148] = [
+    0x95, 0x06, 0x00, 0x00, 0x19, 0xa1, 0x47, 0x81, 0x30, 0xc3, 0x06, 0x0f, 0x00, 0xaf, 0x00, 0x00,
+    0x00, 0x00, 0x04, 0x03, 0x04, 0xb0, 0x00, 0x3c, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x04, 0xb0, 0x00, 0x00, 0x01, 0x36, 0x1e, 0xa5, 0x78, 0x00, 0x3c, 0x00, 0x14, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x04, 0xb0, 0x00, 0x00, 0x01, 0x31, 0x1e, 0xaa, 0x7d, 0x00, 0x3c, 0x00, 0x12, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x04, 0xb0, 0x00, 0x00, 0x01, 0x2c, 0x1f, 0xac, 0x82, 0x00, 0x00, 0x00, 0x0f, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+];
+
+pub fn datetime(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+    chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap()
+        .and_hms_opt(hour, minute, 0).unwrap()
+}
+
+/// Single distance workout of `splits` 500m splits, 2:00/500m each.
+pub fn distance_workout(datetime: chrono::NaiveDateTime, splits: u32) -> Workout {
+    let frames = (0..splits).map(|_| WorkoutFrame {
+        distance: 500,
+        work_duration: Duration::from_secs(120),
+        rest_duration: None,
+        rest_distance: None,
+        spm: 24,
+        work_heart_rate: None,
+        rest_heart_rate: None,
+        heart_rate_min: None,
+        heart_rate_max: None,
+        heart_rate_avg: None,
+        drag_factor: None,
+    }).collect();
+
+    Workout {
+        workout_type: WorkoutType::SingleDistance,
+        machine: MachineType::Rower,
+        name: None,
+        serial_number: 430000000,
+        datetime,
+        user_id: 0,
+        record_id: 1,
+        total_distance: 500 * splits,
+        total_work_duration: Duration::from_secs(120 * splits as u64),
+        total_rest_duration: None,
+        rest_interval: None,
+        spm: Some(24),
+        frames
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+const METERS_PER_MILE: f64 = 1609.344;
+const METERS_PER_YARD: f64 = 0.9144;
+const KG_PER_LB: f64 = 0.45359237;
+
+pub fn meters_to_miles(meters: f64) -> f64 {
+    meters / METERS_PER_MILE
+}
+
+pub fn meters_to_yards(meters: f64) -> f64 {
+    meters / METERS_PER_YARD
+}
+
+pub fn kg_to_lbs(kg: f64) -> f64 {
+    kg / KG_PER_LB
+}
+
+pub fn lbs_to_kg(lbs: f64) -> f64 {
+    lbs * KG_PER_LB
+}
+
+/// Converts a pace per 500m to a pace per mile.
+pub fn pace_per_mile(pace_500m: Duration) -> Duration {
+    pace_500m.mul_f64(METERS_PER_MILE / 500.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn distances() {
+        assert_close(meters_to_miles(1609.344), 1.0);
+        assert_close(meters_to_miles(42195.0), 26.219);
+        assert_close(meters_to_yards(2000.0), 2187.227);
+    }
+
+    #[test]
+    fn weights() {
+        assert_close(kg_to_lbs(80.0), 176.370);
+        assert_close(lbs_to_kg(176.370), 80.0);
+        assert_close(lbs_to_kg(kg_to_lbs(72.5)), 72.5);
+    }
+
+    #[test]
+    fn pace() {
+        // 2:00/500m is 6:26.2/mile
+        assert_eq!(pace_per_mile(Duration::from_secs(120)).as_millis(), 386_242);
+    }
+}
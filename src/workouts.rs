@@ -1,14 +1,18 @@
 // TODO
 #![allow(dead_code)]
 
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryFrom;
+use std::str::FromStr;
 use std::time::Duration;
 
-use chrono;
+use chrono::Datelike;
 
 use crate::error::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum WorkoutType {
     FreeRow = 0x01,
     SingleDistance = 0x03,
@@ -31,7 +35,7 @@ impl TryFrom<u8> for WorkoutType {
             0x07 => Ok(WorkoutType::DistanceInterval),
             0x08 => Ok(WorkoutType::VariableInterval),
             0x0A => Ok(WorkoutType::SingleCalorie),
-            _ => Err(ParserError::default())
+            _ => Err(ParserError::UnknownWorkoutType(value))
         }
     }
 }
@@ -50,54 +54,429 @@ impl std::fmt::Display for WorkoutType {
     }
 }
 
-#[derive(Debug)]
+impl FromStr for WorkoutType {
+    type Err = ParserError;
+
+    /// Parses the `Display` representation, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let all = [
+            Self::FreeRow,
+            Self::SingleDistance,
+            Self::SingleTime,
+            Self::TimeInterval,
+            Self::DistanceInterval,
+            Self::VariableInterval,
+            Self::SingleCalorie,
+        ];
+
+        let s = s.trim();
+        for workout_type in all.iter() {
+            if workout_type.to_string().eq_ignore_ascii_case(s) {
+                return Ok(*workout_type);
+            }
+        }
+
+        let names: Vec<String> = all.iter().map(|t| format!("\"{}\"", t)).collect();
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!(
+            "Invalid workout type \"{}\", expected one of: {}", s, names.join(", ")
+        )).into())
+    }
+}
+
+/// Concept2 machine the monitor was attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MachineType {
+    #[default]
+    Rower,
+    SkiErg,
+    BikeErg
+}
+
+impl MachineType {
+    /// Decodes the erg machine type as reported by the PM5 (see the PM5
+    /// CSAFE/Bluetooth specification), e.g. 0 for a model D rower, 128 for a
+    /// SkiErg or 192 for a BikeErg. Unknown codes are treated as rowers.
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            128..=191 | 225 => Self::SkiErg,
+            192..=223 | 226 => Self::BikeErg,
+            _ => Self::Rower
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Rower => 0,
+            Self::SkiErg => 128,
+            Self::BikeErg => 192
+        }
+    }
+
+    /// Pace to power conversion used by the monitor on this machine.
+    pub fn power_model(&self) -> PowerModel {
+        match self {
+            Self::Rower | Self::SkiErg => PowerModel::default(),
+            // The BikeErg shows twice the distance rowing would at the same
+            // power, so the constant is 2.8 / 2^3.
+            Self::BikeErg => PowerModel { constant: 0.35 }
+        }
+    }
+}
+
+impl std::fmt::Display for MachineType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::Rower => "RowErg",
+            Self::SkiErg => "SkiErg",
+            Self::BikeErg => "BikeErg",
+        })
+    }
+}
+
+/// Model used to calculate power from pace, `watts = constant / pace^3`
+/// with the pace in seconds per meter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerModel {
+    pub constant: f64
+}
+
+impl Default for PowerModel {
+    /// The standard Concept2 pace to power conversion
+    fn default() -> Self {
+        Self { constant: 2.8 }
+    }
+}
+
+impl PowerModel {
+    pub fn watts(&self, duration: Duration, distance: u32) -> f64 {
+        if distance == 0 || duration.is_zero() {
+            return 0.0;
+        }
+
+        let pace: f64 = duration.as_secs_f64() / distance as f64;
+        self.constant / pace.powi(3)
+    }
+}
+
+/// Exponent of Riegel's endurance formula, `t2 = t1 * (d2 / d1)^1.06`
+const RIEGEL_EXPONENT: f64 = 1.06;
+
+/// Power needed to hold the given pace per 500m, using the standard
+/// Concept2 model. Returns 0 for a pace of 0.
+pub fn watts_from_pace(pace: Duration) -> f64 {
+    let pace = pace.as_secs_f64() / 500.0;
+    if pace == 0.0 {
+        return 0.0;
+    }
+
+    PowerModel::default().constant / pace.powi(3)
+}
+
+/// Power relative to body weight, `None` if the weight isn't a positive
+/// number.
+fn watts_per_kg(watts: f64, weight: f64) -> Option<f64> {
+    Some(watts / weight).filter(|_| weight > 0.0 && weight.is_finite())
+}
+
+/// Pace per 500m resulting from the given power, the inverse of
+/// `watts_from_pace`. Returns 0 for non-positive power.
+pub fn pace_from_watts(watts: f64) -> Duration {
+    if watts <= 0.0 {
+        return Duration::from_secs(0);
+    }
+
+    Duration::from_secs_f64((PowerModel::default().constant / watts).cbrt() * 500.0)
+}
+
+/// Predicts the time for `target_distance` based on the given workout, using
+/// Riegel's formula. Returns 0 if the workout has no distance.
+pub fn project_time(current: &Workout, target_distance: u32) -> Duration {
+    if current.total_distance == 0 {
+        return Duration::from_secs(0);
+    }
+
+    let ratio = target_distance as f64 / current.total_distance as f64;
+    current.total_work_duration.mul_f64(ratio.powf(RIEGEL_EXPONENT))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sex {
+    Male,
+    Female
+}
+
+/// Body measurements used for calorie estimation.
+///
+/// If age, sex and heart rate are all known, the heart-rate based formula
+/// by Keytel et al. (2005) is used:
+///
+/// - men: `kcal/min = (-55.0969 + 0.6309 * hr + 0.1988 * kg + 0.2017 * age) / 4.184`
+/// - women: `kcal/min = (-20.4022 + 0.4472 * hr - 0.1263 * kg + 0.074 * age) / 4.184`
+///
+/// Otherwise, Concept2's weight-adjusted formula is used, which replaces the
+/// fixed 300 kcal/h in `cal_hr` with 1.714 kcal/h per pound of body weight:
+/// `kcal/h = 3.44 * watts + 1.714 * lbs`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalorieModel {
+    pub weight_kg: f64,
+    pub age: Option<u32>,
+    pub sex: Option<Sex>
+}
+
+impl CalorieModel {
+    fn keytel_cal_hr(&self, age: u32, sex: Sex, heart_rate: u32) -> f64 {
+        let (hr, kg, age) = (heart_rate as f64, self.weight_kg, age as f64);
+
+        let kcal_min = match sex {
+            Sex::Male => (-55.0969 + 0.6309 * hr + 0.1988 * kg + 0.2017 * age) / 4.184,
+            Sex::Female => (-20.4022 + 0.4472 * hr - 0.1263 * kg + 0.074 * age) / 4.184
+        };
+
+        kcal_min.max(0.0) * 60.0
+    }
+}
+
+/// Workout program picked on the monitor, stored as two bytes in the access
+/// table. The first byte appears to select the list the program was picked
+/// from and the second one its position in that list. Which list number
+/// belongs to which menu (standard list, custom list, favorites) hasn't been
+/// established yet, so only the raw values are exposed. Two zero bytes mean
+/// no program was picked, e.g. for Just Row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WorkoutName {
+    pub list: u8,
+    pub index: u8,
+}
+
+impl WorkoutName {
+    pub fn from_bytes(bytes: [u8; 2]) -> Option<Self> {
+        match bytes {
+            [0, 0] => None,
+            [list, index] => Some(Self { list, index })
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 2] {
+        [self.list, self.index]
+    }
+}
+
+impl std::fmt::Display for WorkoutName {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "List {}, #{}", self.list, self.index)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Workout {
     pub workout_type: WorkoutType,
+    pub machine: MachineType,
+    /// program picked on the monitor, if any
+    pub name: Option<WorkoutName>,
     pub serial_number: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::datetime"))]
     pub datetime: chrono::NaiveDateTime,
     pub user_id: u16,   // TODO: needed?
     pub record_id: u16, // TODO: needed?
     pub total_distance: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::duration"))]
     pub total_work_duration: Duration,
     /// only set for intervals
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::option_duration"))]
     pub total_rest_duration: Option<Duration>,
+    /// programmed rest between intervals, only set for fixed intervals
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::option_duration"))]
+    pub rest_interval: Option<Duration>,
     /// only set for single workouts
     pub spm: Option<u32>,
     /// splits for single workouts, intervals for interval workouts
     pub frames: Vec<WorkoutFrame>
 }
 
+/// Identifies a workout by the monitor that recorded it, its record id and
+/// its date. The same workout read from two drives has the same key, even if
+/// it doesn't compare equal, e.g. because the program name is only stored on
+/// one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorkoutKey {
+    pub serial_number: u32,
+    pub record_id: u16,
+    pub datetime: chrono::NaiveDateTime
+}
+
 impl Workout {
+    pub fn key(&self) -> WorkoutKey {
+        WorkoutKey {
+            serial_number: self.serial_number,
+            record_id: self.record_id,
+            datetime: self.datetime
+        }
+    }
+
+    /// Average power over the workout, using the power model of the machine
+    /// it was recorded on. Returns 0 for workouts without distance or
+    /// duration (e.g. aborted ones).
     pub fn watts(&self) -> f64 {
-        let pace: f64 = self.total_work_duration.as_secs() as f64 / self.total_distance as f64;
-        2.8 / pace.powi(3)
+        self.watts_with(&self.machine.power_model())
+    }
+
+    /// Like `watts`, but using a custom power model.
+    pub fn watts_with(&self, model: &PowerModel) -> f64 {
+        model.watts(self.total_work_duration, self.total_distance)
+    }
+
+    /// Average of the frame powers, weighted by their work duration. Unlike
+    /// `watts`, which only looks at the totals, this reflects how much power
+    /// varied between frames. Falls back to `watts` for workouts without
+    /// frames.
+    pub fn average_power(&self) -> f64 {
+        let duration: f64 = self.frames.iter().map(|f| f.work_duration.as_secs_f64()).sum();
+        if duration == 0.0 {
+            return self.watts();
+        }
+
+        let model = self.machine.power_model();
+        self.frames.iter()
+            .map(|f| f.watts_with(&model) * f.work_duration.as_secs_f64())
+            .sum::<f64>() / duration
+    }
+
+    /// Returns the frame with the highest power.
+    pub fn peak_power(&self) -> Option<&WorkoutFrame> {
+        self.frames.iter().max_by(|a, b| a.watts(self.machine).total_cmp(&b.watts(self.machine)))
     }
 
     pub fn cal_hr(&self) -> f64 {
         (self.watts() * 3.44) + 300.0
     }
 
+    /// Calories burned over the whole workout at the rate of `cal_hr`, as
+    /// shown by the monitor. Rest between intervals isn't counted.
+    pub fn total_calories(&self) -> f64 {
+        self.cal_hr() * self.total_work_duration.as_secs_f64() / 3600.0
+    }
+
     pub fn cal_hr_weight_corrected(&self, weight: f64) -> f64 {
         (self.watts() * 3.44) + (1.714 * 2.2046 * weight)
     }
 
+    /// Average watts per kg of body weight, for comparing rowers of
+    /// different weights. `None` if the weight is unknown (0 or less).
+    pub fn watts_per_kg(&self, weight: f64) -> Option<f64> {
+        watts_per_kg(self.watts(), weight)
+    }
+
+    /// Calories burned per hour of work, see `CalorieModel` for the formula.
+    pub fn personal_cal_hr(&self, model: &CalorieModel) -> f64 {
+        match (model.age, model.sex, self.heart_rate()) {
+            (Some(age), Some(sex), Some(hr)) => model.keytel_cal_hr(age, sex, hr),
+            _ => self.cal_hr_weight_corrected(model.weight_kg)
+        }
+    }
+
+    /// Total calories burned during the work portion of the workout, see
+    /// `CalorieModel` for the formula.
+    pub fn calories(&self, model: &CalorieModel) -> f64 {
+        self.personal_cal_hr(model) * self.total_work_duration.as_secs_f64() / 3600.0
+    }
+
     pub fn pace(&self) -> Duration {
         let splits = std::cmp::max(self.total_distance / 500, 1);
         Duration::from_millis(self.total_work_duration.as_millis() as u64 / splits as u64)
     }
 
+    /// Average pace per 500m. For intervals, only the work portions of the
+    /// frames are taken into account, so rest doesn't affect the pace.
+    pub fn average_pace_500m(&self) -> Duration {
+        let (duration, distance): (Duration, u32) = if self.total_rest_duration.is_some() && !self.frames.is_empty() {
+            (self.frames.iter().map(|f| f.work_duration).sum(), self.frames.iter().map(|f| f.distance).sum())
+        } else {
+            (self.total_work_duration, self.total_distance)
+        };
+
+        if distance == 0 {
+            return Duration::from_secs(0);
+        }
+
+        duration.mul_f64(500.0 / distance as f64)
+    }
+
+    /// Average heart rate over all frames with heart rate data. Returns
+    /// `None` if no frame has any.
     pub fn heart_rate(&self) -> Option<u32> {
-        if self.frames.len() == 0 {
+        let heart_rates: Vec<u32> = self.frames.iter().filter_map(|f| f.work_heart_rate).collect();
+
+        if heart_rates.is_empty() {
             return None;
         }
 
-        if self.frames.iter().any(|f| f.work_heart_rate.is_none()) {
+        Some(heart_rates.iter().sum::<u32>() / heart_rates.len() as u32)
+    }
+
+    /// Highest heart rate over all frames with heart rate data, using the
+    /// per-frame maximum where the monitor recorded one.
+    pub fn max_heart_rate(&self) -> Option<u32> {
+        self.frames.iter().filter_map(|f| f.heart_rate_max.or(f.work_heart_rate)).max()
+    }
+
+    /// Lowest heart rate over all frames with heart rate data, using the
+    /// per-frame minimum where the monitor recorded one.
+    pub fn min_heart_rate(&self) -> Option<u32> {
+        self.frames.iter().filter_map(|f| f.heart_rate_min.or(f.work_heart_rate)).min()
+    }
+
+    /// Stroke rate averaged over the frames, weighted by their work duration.
+    /// Unlike `spm`, this is also available for intervals. Returns `None` for
+    /// workouts without frames.
+    pub fn average_spm(&self) -> Option<u32> {
+        let duration: f64 = self.frames.iter().map(|f| f.work_duration.as_secs_f64()).sum();
+        if duration == 0.0 {
             return None;
         }
 
-        Some(self.frames.iter()
-            .map(|f| f.work_heart_rate.unwrap())
-            .sum::<u32>() / self.frames.len() as u32)
+        let spm = self.frames.iter()
+            .map(|f| f.spm as f64 * f.work_duration.as_secs_f64())
+            .sum::<f64>() / duration;
+
+        Some(spm.round() as u32)
+    }
+
+    /// Highest stroke rate of all frames.
+    pub fn max_spm(&self) -> Option<u32> {
+        self.frames.iter().map(|f| f.spm).max()
+    }
+
+    /// Work time spent in each of the five heart rate zones, i.e. at 50-60%,
+    /// 60-70%, 70-80%, 80-90% and 90-100% of `max_hr`, based on the heart
+    /// rate of each frame. Heart rates below 50% are counted towards zone 1,
+    /// those above `max_hr` towards zone 5. Frames without heart rate are
+    /// left out, see `duration_without_heart_rate`.
+    pub fn heart_rate_zones(&self, max_hr: u32) -> [Duration; 5] {
+        let mut zones = [Duration::default(); 5];
+        if max_hr == 0 {
+            return zones;
+        }
+
+        for frame in &self.frames {
+            if let Some(hr) = frame.work_heart_rate {
+                let zone = ((hr * 10 / max_hr) as usize).saturating_sub(5).min(4);
+                zones[zone] += frame.work_duration;
+            }
+        }
+
+        zones
+    }
+
+    /// Work time of all frames without heart rate data.
+    pub fn duration_without_heart_rate(&self) -> Duration {
+        self.frames.iter()
+            .filter(|f| f.work_heart_rate.is_none())
+            .map(|f| f.work_duration)
+            .sum()
     }
 
     pub fn work_duration_string(&self) -> String {
@@ -115,30 +494,237 @@ impl Workout {
     pub fn pace_string(&self) -> String {
         duration_to_string(&self.pace())
     }
+
+    /// Splits the work portion of the workout into segments of the given
+    /// distance, e.g. to compare 1000m splits of a 5000m row that was
+    /// recorded with 500m splits. Frames are assumed to be rowed at a
+    /// constant pace, so frames overlapping two segments are divided
+    /// proportionally. The last segment is shorter if the distance doesn't
+    /// divide evenly.
+    pub fn resplit(&self, distance_m: u32) -> Vec<SplitSummary> {
+        let mut splits = Vec::new();
+        if distance_m == 0 {
+            return splits;
+        }
+
+        let mut current = SplitAccumulator::default();
+
+        for frame in &self.frames {
+            if frame.distance == 0 {
+                current.add(frame, 0, frame.work_duration);
+                continue;
+            }
+
+            let mut remaining = frame.distance;
+            while remaining > 0 {
+                let portion = remaining.min(distance_m - current.distance);
+                let duration = frame.work_duration.mul_f64(portion as f64 / frame.distance as f64);
+                current.add(frame, portion, duration);
+                remaining -= portion;
+
+                if current.distance == distance_m {
+                    let index = splits.len() + 1;
+                    splits.push(std::mem::take(&mut current).finish(index));
+                }
+            }
+        }
+
+        if current.distance > 0 {
+            let index = splits.len() + 1;
+            splits.push(current.finish(index));
+        }
+
+        splits
+    }
+
+    /// Returns true if both workouts are of the same type and were recorded
+    /// with the same splits, so they can be compared split by split.
+    pub fn same_splits(&self, other: &Workout) -> bool {
+        let same_target = |a: &WorkoutFrame, b: &WorkoutFrame| match self.workout_type {
+            WorkoutType::SingleTime | WorkoutType::TimeInterval => a.work_duration == b.work_duration,
+            // frames are split by calories, which aren't stored
+            WorkoutType::SingleCalorie => true,
+            _ => a.distance == b.distance
+        };
+
+        self.workout_type == other.workout_type &&
+            self.frames.len() == other.frames.len() &&
+            self.frames.iter().zip(&other.frames).all(|(a, b)| same_target(a, b))
+    }
+
+    /// Returns the recorded splits (or intervals) of the workout, one per
+    /// frame, with the same numbers show-workouts displays.
+    pub fn splits(&self) -> Vec<SplitSummary> {
+        self.frames.iter().enumerate().map(|(i, frame)| SplitSummary {
+            index: i + 1,
+            distance: frame.distance,
+            duration: frame.work_duration,
+            pace: frame.pace(),
+            spm: frame.spm,
+            heart_rate: frame.work_heart_rate,
+        }).collect()
+    }
+
+    /// Split with the fastest pace, see `splits`, the first one if several
+    /// are equally fast. Splits without distance are left out.
+    pub fn fastest_split(&self) -> Option<SplitSummary> {
+        self.splits().into_iter().filter(|s| s.distance > 0).min_by_key(|s| s.pace)
+    }
+
+    /// Split with the slowest pace, see `fastest_split`.
+    pub fn slowest_split(&self) -> Option<SplitSummary> {
+        // max_by_key would return the last of equally slow splits
+        self.splits().into_iter().filter(|s| s.distance > 0).min_by_key(|s| std::cmp::Reverse(s.pace))
+    }
+
+    /// Standard deviation of the split paces, i.e. how evenly the workout
+    /// was paced. Needs at least two splits with distance.
+    pub fn pace_std_dev(&self) -> Option<Duration> {
+        let paces: Vec<f64> = self.splits().iter()
+            .filter(|s| s.distance > 0)
+            .map(|s| s.pace.as_secs_f64())
+            .collect();
+        if paces.len() < 2 {
+            return None;
+        }
+
+        let mean = paces.iter().sum::<f64>() / paces.len() as f64;
+        let variance = paces.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / paces.len() as f64;
+        Some(Duration::from_secs_f64(variance.sqrt()))
+    }
 }
 
-#[derive(Debug)]
+/// Segment of a workout, see `Workout::splits` and `Workout::resplit`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SplitSummary {
+    /// position in the workout, starting at 1
+    pub index: usize,
+    pub distance: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::duration"))]
+    pub duration: Duration,
+    /// pace per 500m over the segment
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::duration"))]
+    pub pace: Duration,
+    /// average stroke rate, weighted by time
+    pub spm: u32,
+    /// average heart rate, weighted by time, over the frames that have one
+    pub heart_rate: Option<u32>,
+}
+
+impl SplitSummary {
+    pub fn duration_string(&self) -> String {
+        duration_to_string(&self.duration)
+    }
+
+    pub fn pace_string(&self) -> String {
+        duration_to_string(&self.pace)
+    }
+}
+
+/// Running totals for the segment `Workout::resplit` is currently filling.
+#[derive(Default)]
+struct SplitAccumulator {
+    distance: u32,
+    duration: Duration,
+    spm_secs: f64,
+    heart_rate_secs: f64,
+    heart_rate_duration: f64,
+}
+
+impl SplitAccumulator {
+    fn add(&mut self, frame: &WorkoutFrame, distance: u32, duration: Duration) {
+        let secs = duration.as_secs_f64();
+
+        self.distance += distance;
+        self.duration += duration;
+        self.spm_secs += frame.spm as f64 * secs;
+
+        if let Some(hr) = frame.work_heart_rate {
+            self.heart_rate_secs += hr as f64 * secs;
+            self.heart_rate_duration += secs;
+        }
+    }
+
+    fn finish(self, index: usize) -> SplitSummary {
+        let secs = self.duration.as_secs_f64();
+        let pace = if self.distance > 0 {
+            self.duration.mul_f64(500.0 / self.distance as f64)
+        } else {
+            Duration::from_secs(0)
+        };
+
+        SplitSummary {
+            index,
+            distance: self.distance,
+            duration: self.duration,
+            pace,
+            spm: if secs > 0.0 { (self.spm_secs / secs).round() as u32 } else { 0 },
+            heart_rate: if self.heart_rate_duration > 0.0 {
+                Some((self.heart_rate_secs / self.heart_rate_duration).round() as u32)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct WorkoutFrame {
     pub distance: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::duration"))]
     pub work_duration: Duration,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::option_duration"))]
     pub rest_duration: Option<Duration>,
+    pub rest_distance: Option<u32>,
     pub spm: u32,
     pub work_heart_rate: Option<u32>,
     pub rest_heart_rate: Option<u32>,
+    /// heart rate statistics over the split, only decoded for single
+    /// workouts of some firmware versions
+    pub heart_rate_min: Option<u32>,
+    pub heart_rate_max: Option<u32>,
+    pub heart_rate_avg: Option<u32>,
+    /// as set on the machine, only decoded for some firmware versions
+    pub drag_factor: Option<u32>,
 }
 
 impl WorkoutFrame {
-    pub fn watts(&self) -> f64 {
-        let pace: f64 = self.work_duration.as_secs() as f64 / self.distance as f64;
-        2.8 / pace.powi(3)
+    /// Power over the work portion of this frame, using the power model of
+    /// the machine the workout was recorded on. Frames don't know their
+    /// machine, so it has to be passed from the workout.
+    pub fn watts(&self, machine: MachineType) -> f64 {
+        self.watts_with(&machine.power_model())
     }
 
-    pub fn cal_hr(&self) -> f64 {
-        (self.watts() * 3.44) + 300.0
+    pub fn watts_with(&self, model: &PowerModel) -> f64 {
+        model.watts(self.work_duration, self.distance)
     }
 
-    pub fn cal_hr_weight_corrected(&self, weight: f64) -> f64 {
-        (self.watts() * 3.44) + (1.714 * 2.2046 * weight)
+    pub fn cal_hr(&self, machine: MachineType) -> f64 {
+        (self.watts(machine) * 3.44) + 300.0
+    }
+
+    /// Calories burned during the work portion of this frame at the rate of
+    /// `cal_hr`. The rest following it isn't counted.
+    pub fn calories(&self, machine: MachineType) -> f64 {
+        self.cal_hr(machine) * self.work_duration.as_secs_f64() / 3600.0
+    }
+
+    pub fn cal_hr_weight_corrected(&self, machine: MachineType, weight: f64) -> f64 {
+        (self.watts(machine) * 3.44) + (1.714 * 2.2046 * weight)
+    }
+
+    /// Calories burned during the work portion of this frame, see
+    /// `CalorieModel` for the formula.
+    pub fn personal_calories(&self, machine: MachineType, model: &CalorieModel) -> f64 {
+        let cal_hr = match (model.age, model.sex, self.work_heart_rate) {
+            (Some(age), Some(sex), Some(hr)) => model.keytel_cal_hr(age, sex, hr),
+            _ => self.cal_hr_weight_corrected(machine, model.weight_kg)
+        };
+
+        cal_hr * self.work_duration.as_secs_f64() / 3600.0
     }
 
     pub fn pace(&self) -> Duration {
@@ -163,19 +749,988 @@ impl WorkoutFrame {
     }
 }
 
+/// Single stroke sample recorded during a workout
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stroke {
+    /// time since the start of the workout
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::duration"))]
+    pub time: Duration,
+    /// distance since the start of the workout
+    pub distance: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize::duration"))]
+    pub pace: Duration,
+    pub spm: u32,
+    pub heart_rate: Option<u32>,
+}
+
+impl Stroke {
+    pub fn pace_string(&self) -> String {
+        duration_to_string(&self.pace)
+    }
+}
+
+/// Identifies a single workout, either by its 1-based index (as listed by
+/// list-workouts), by date or by date and time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkoutSelector {
+    Index(usize),
+    Date(chrono::NaiveDate),
+    DateTime(chrono::NaiveDateTime),
+}
+
+impl WorkoutSelector {
+    /// Returns the position of the selected workout in the given list. If
+    /// multiple workouts match a date, the last one is returned.
+    pub fn position(&self, workouts: &[Workout]) -> Option<usize> {
+        match self {
+            Self::Index(i) => {
+                if *i >= 1 && *i <= workouts.len() { Some(i - 1) } else { None }
+            },
+            Self::Date(date) => {
+                workouts.iter().rposition(|w| w.datetime.date() == *date)
+            },
+            Self::DateTime(datetime) => {
+                workouts.iter().rposition(|w| w.datetime == *datetime)
+            }
+        }
+    }
+
+    pub fn find<'a>(&self, workouts: &'a [Workout]) -> Option<&'a Workout> {
+        self.position(workouts).map(|i| &workouts[i])
+    }
+}
+
+impl FromStr for WorkoutSelector {
+    type Err = ParserError;
+
+    /// Purely numeric input is always treated as an index, so something like
+    /// `20240310` is workout #20240310, not a date. Dates need the dashes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(i) = s.parse() {
+                return Ok(Self::Index(i));
+            }
+        }
+
+        if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(Self::Date(date));
+        }
+
+        if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+            return Ok(Self::DateTime(datetime));
+        }
+
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!(
+            "Invalid workout \"{}\", expected one of: <id>, YYYY-MM-DD, \"YYYY-MM-DD HH:MM\"", s
+        )).into())
+    }
+}
+
+/// Distances in meters for which personal bests are tracked
+const PB_DISTANCES: [u32; 9] = [100, 500, 1000, 2000, 5000, 6000, 10000, 21097, 42195];
+
+/// Times in seconds for which personal bests are tracked
+const PB_TIMES: [u64; 4] = [60, 240, 1800, 3600];
+
+/// Standard event a personal best is recorded for, either a fixed distance or
+/// a fixed time, like in the Concept2 online logbook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PersonalBestCategory {
+    Distance(u32),
+    Time(Duration),
+}
+
+impl std::fmt::Display for PersonalBestCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Distance(distance) => write!(f, "{}m", distance),
+            Self::Time(duration) => write!(f, "{} min", duration.as_secs() / 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PersonalBest {
+    pub category: PersonalBestCategory,
+    /// Position of the workout in the list passed to `personal_bests`
+    pub index: usize,
+}
+
+/// Returns the personal bests for all standard events that have at least one
+/// workout, the fastest time for distances and the longest distance for
+/// times. Only single distance and single time workouts are considered. On
+/// ties, the earlier workout is kept.
+pub fn personal_bests(workouts: &[Workout]) -> Vec<PersonalBest> {
+    let distances = PB_DISTANCES.iter().filter_map(|distance| {
+        workouts.iter().enumerate()
+            .filter(|(_, w)| w.workout_type == WorkoutType::SingleDistance && w.total_distance == *distance)
+            .min_by_key(|(_, w)| w.total_work_duration)
+            .map(|(index, _)| PersonalBest { category: PersonalBestCategory::Distance(*distance), index })
+    });
+
+    let times = PB_TIMES.iter().filter_map(|secs| {
+        let duration = Duration::from_secs(*secs);
+        workouts.iter().enumerate()
+            .filter(|(_, w)| w.workout_type == WorkoutType::SingleTime && w.total_work_duration == duration)
+            .min_by_key(|(_, w)| std::cmp::Reverse(w.total_distance))
+            .map(|(index, _)| PersonalBest { category: PersonalBestCategory::Time(duration), index })
+    });
+
+    distances.chain(times).collect()
+}
+
+/// Removes duplicate workouts, e.g. after reading a backup and a drive, keeping
+/// the first occurrence of each. Workouts are duplicates if they have the same
+/// `key`. The order is preserved otherwise.
+pub fn dedup_workouts(workouts: Vec<Workout>) -> Vec<Workout> {
+    let mut seen = HashSet::with_capacity(workouts.len());
+    workouts.into_iter().filter(|w| seen.insert(w.key())).collect()
+}
+
+/// Period workouts are grouped by in `summarize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Granularity {
+    /// ISO week, starting on Monday
+    Week,
+    /// calendar month
+    Month,
+}
+
+impl Granularity {
+    /// Returns the first day of the period containing `date`.
+    pub fn period_start(self, date: chrono::NaiveDate) -> chrono::NaiveDate {
+        match self {
+            Self::Week => date - chrono::Duration::days(date.weekday().num_days_from_monday().into()),
+            Self::Month => date.with_day(1).unwrap(),
+        }
+    }
+}
+
+impl FromStr for Granularity {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!(
+                "Invalid period \"{}\", expected \"week\" or \"month\"", s.trim()
+            )).into())
+        }
+    }
+}
+
+/// Totals over all workouts in one week or month.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub granularity: Granularity,
+    /// first day of the period, always a Monday for weeks
+    pub start: chrono::NaiveDate,
+    pub workouts: usize,
+    pub total_distance: u32,
+    pub total_work_duration: Duration,
+}
+
+impl Summary {
+    /// Average pace per 500m over all workouts in the period.
+    pub fn average_pace_500m(&self) -> Duration {
+        if self.total_distance == 0 {
+            return Duration::from_secs(0);
+        }
+
+        self.total_work_duration.mul_f64(500.0 / self.total_distance as f64)
+    }
+
+    /// Watts per kg at the average pace, see `Workout::watts_per_kg`.
+    pub fn watts_per_kg(&self, weight: f64) -> Option<f64> {
+        watts_per_kg(watts_from_pace(self.average_pace_500m()), weight)
+    }
+
+    /// Returns the period as e.g. "2024-W09" for weeks or "2024-03" for
+    /// months.
+    pub fn period_string(&self) -> String {
+        match self.granularity {
+            Granularity::Week => {
+                let week = self.start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            },
+            Granularity::Month => self.start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// Groups workouts by week or month and sums them up. Periods are returned in
+/// chronological order, periods without workouts are left out.
+pub fn summarize(workouts: &[Workout], granularity: Granularity) -> Vec<Summary> {
+    let mut periods: BTreeMap<chrono::NaiveDate, Summary> = BTreeMap::new();
+
+    for workout in workouts {
+        let start = granularity.period_start(workout.datetime.date());
+        let summary = periods.entry(start).or_insert_with(|| Summary {
+            granularity,
+            start,
+            workouts: 0,
+            total_distance: 0,
+            total_work_duration: Duration::default(),
+        });
+
+        summary.workouts += 1;
+        summary.total_distance += workout.total_distance;
+        summary.total_work_duration += workout.total_work_duration;
+    }
+
+    periods.into_values().collect()
+}
+
+/// Distribution of paces over many workouts, see `pace_distribution`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Distribution {
+    /// sorted from fastest to slowest
+    paces: Vec<Duration>
+}
+
+impl Distribution {
+    pub fn len(&self) -> usize {
+        self.paces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paces.is_empty()
+    }
+
+    /// Fastest pace.
+    pub fn min(&self) -> Option<Duration> {
+        self.paces.first().copied()
+    }
+
+    /// Slowest pace.
+    pub fn max(&self) -> Option<Duration> {
+        self.paces.last().copied()
+    }
+
+    pub fn median(&self) -> Option<Duration> {
+        self.percentile(50.0)
+    }
+
+    /// Pace that `percent` percent of the workouts were as fast as or faster
+    /// than, interpolating linearly between the closest two workouts. Since
+    /// power rises with pace, the 10th percentile pace is the 90th
+    /// percentile power.
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        if self.paces.is_empty() {
+            return None;
+        }
+
+        let rank = percent.clamp(0.0, 100.0) / 100.0 * (self.paces.len() - 1) as f64;
+        let (lower, upper) = (self.paces[rank.floor() as usize], self.paces[rank.ceil() as usize]);
+        Some(lower + (upper - lower).mul_f64(rank.fract()))
+    }
+}
+
+/// Collects the average pace per 500m of every workout, see
+/// `Workout::average_pace_500m`. Workouts without distance are left out, to
+/// only look at e.g. 2k pieces, filter the workouts beforehand.
+pub fn pace_distribution(workouts: &[Workout]) -> Distribution {
+    let mut paces: Vec<Duration> = workouts.iter()
+        .filter(|w| w.total_distance > 0)
+        .map(|w| w.average_pace_500m())
+        .collect();
+    paces.sort();
+
+    Distribution { paces }
+}
+
+/// Serializers for types that don't implement `Serialize` the way we want.
+/// Durations are written as seconds, datetimes as ISO-8601.
+#[cfg(feature = "serde")]
+mod serialize {
+    use std::time::Duration;
+
+    use serde::Serializer;
+
+    pub fn datetime<S: Serializer>(datetime: &chrono::NaiveDateTime, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(&datetime.format("%Y-%m-%dT%H:%M:%S"))
+    }
+
+    pub fn duration<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn option_duration<S: Serializer>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(d) => s.serialize_some(&d.as_secs_f64()),
+            None => s.serialize_none()
+        }
+    }
+}
+
 pub fn duration_to_string(duration: &Duration) -> String {
-    if duration.as_secs() > 3600 {
-        format!("{}:{:02}:{:02}.{}",
+    duration_to_string_precision(duration, 1)
+}
+
+/// Like `duration_to_string`, but with the given number of fractional digits
+/// (0 to 3). The fraction is truncated, not rounded, same as on the monitor.
+pub fn duration_to_string_precision(duration: &Duration, precision: u32) -> String {
+    let precision = precision.min(3);
+    let fraction = if precision > 0 {
+        format!(".{:0width$}", duration.subsec_millis() / 10u32.pow(3 - precision), width = precision as usize)
+    } else {
+        String::new()
+    };
+
+    if duration.as_secs() >= 3600 {
+        format!("{}:{:02}:{:02}{}",
             duration.as_secs() / 3600,
             (duration.as_secs() / 60) % 60,
             duration.as_secs() % 60,
-            duration.subsec_millis() / 100
+            fraction
         )
     } else {
-        format!("{}:{:02}.{}",
+        format!("{}:{:02}{}",
             duration.as_secs() / 60,
             duration.as_secs() % 60,
-            duration.subsec_millis() / 100
+            fraction
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 0.1, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn bike_erg_power() {
+        let two_minutes = Duration::from_secs(120);
+
+        // 2:00/500m
+        assert_close(MachineType::Rower.power_model().watts(two_minutes, 500), 202.5);
+        assert_close(MachineType::SkiErg.power_model().watts(two_minutes, 500), 202.5);
+        // the BikeErg needs 2:00/1000m for the same power
+        assert_close(MachineType::BikeErg.power_model().watts(two_minutes, 1000), 202.5);
+        assert_close(MachineType::BikeErg.power_model().watts(two_minutes, 500), 25.3);
+    }
+
+    #[test]
+    fn workout_power_uses_machine() {
+        let rowed = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        let mut biked = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        biked.machine = MachineType::BikeErg;
+
+        assert_close(rowed.watts(), 202.5);
+        assert_close(biked.watts(), 25.3);
+        assert_close(biked.average_power(), 25.3);
+        assert_close(biked.peak_power().unwrap().watts(biked.machine), 25.3);
+
+        let frame = &biked.frames[0];
+        assert_close(frame.watts(MachineType::Rower), 202.5);
+        assert_close(frame.watts(MachineType::BikeErg), 25.3);
+        assert!(frame.calories(MachineType::BikeErg) < frame.calories(MachineType::Rower));
+    }
+
+    #[test]
+    fn workout_selector() {
+        assert_eq!("3".parse::<WorkoutSelector>().unwrap(), WorkoutSelector::Index(3));
+        // ambiguous, but without dashes it's an id
+        assert_eq!("20240310".parse::<WorkoutSelector>().unwrap(), WorkoutSelector::Index(20240310));
+        assert_eq!("2024-03-10".parse::<WorkoutSelector>().unwrap(),
+            WorkoutSelector::Date(datetime(2024, 3, 10, 0, 0).date()));
+        assert_eq!(" 2024-03-10 09:30 ".parse::<WorkoutSelector>().unwrap(),
+            WorkoutSelector::DateTime(datetime(2024, 3, 10, 9, 30)));
+
+        let error = "yesterday".parse::<WorkoutSelector>().unwrap_err().to_string();
+        assert!(error.contains("<id>, YYYY-MM-DD, \"YYYY-MM-DD HH:MM\""), "{}", error);
+        assert!("2024-13-01".parse::<WorkoutSelector>().is_err());
+        assert!("".parse::<WorkoutSelector>().is_err());
+    }
+
+    #[test]
+    fn workout_selector_position() {
+        let workouts = vec![
+            distance_workout(datetime(2024, 3, 10, 9, 30), 4),
+            distance_workout(datetime(2024, 3, 10, 18, 0), 4),
+            distance_workout(datetime(2024, 3, 11, 9, 30), 4)
+        ];
+
+        assert_eq!(WorkoutSelector::Index(1).position(&workouts), Some(0));
+        assert_eq!(WorkoutSelector::Index(0).position(&workouts), None);
+        assert_eq!(WorkoutSelector::Index(4).position(&workouts), None);
+        assert_eq!(WorkoutSelector::Index(20240310).position(&workouts), None);
+        // the last workout of the day
+        assert_eq!(WorkoutSelector::Date(datetime(2024, 3, 10, 0, 0).date()).position(&workouts), Some(1));
+        assert_eq!(WorkoutSelector::DateTime(datetime(2024, 3, 10, 9, 30)).position(&workouts), Some(0));
+        assert_eq!(WorkoutSelector::DateTime(datetime(2024, 3, 10, 9, 31)).position(&workouts), None);
+    }
+
+    #[test]
+    fn dedup() {
+        let first = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        let mut copy = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        copy.name = WorkoutName::from_bytes([1, 2]);
+        let other = distance_workout(datetime(2024, 3, 11, 9, 30), 4);
+
+        // the copy is the same workout, but only one of them has a name
+        assert_eq!(first.key(), copy.key());
+        assert_ne!(first, copy);
+        assert_eq!(first, distance_workout(datetime(2024, 3, 10, 9, 30), 4));
+
+        let deduped = dedup_workouts(vec![first, other, copy]);
+        assert_eq!(deduped.len(), 2);
+        assert_eq!(deduped[0].name, None);
+        assert_eq!(deduped[1].datetime, datetime(2024, 3, 11, 9, 30));
+    }
+
+    #[test]
+    fn zero_distance_power() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 1);
+        workout.total_distance = 0;
+        workout.frames.clear();
+
+        assert_eq!(workout.watts(), 0.0);
+        assert_eq!(workout.cal_hr(), 300.0);
+        assert!(workout.total_calories().is_finite());
+    }
+
+    #[test]
+    fn zero_duration_power() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 1);
+        workout.total_work_duration = Duration::ZERO;
+        workout.frames[0].work_duration = Duration::ZERO;
+
+        assert_eq!(workout.watts(), 0.0);
+        assert_eq!(workout.average_power(), 0.0);
+        assert_eq!(workout.total_calories(), 0.0);
+        assert_eq!(workout.frames[0].watts(workout.machine), 0.0);
+    }
+
+    #[test]
+    fn interval_pace() {
+        // 3x 2:00 with 1:00 rest
+        let mut workout = distance_workout(datetime(2024, 3, 12, 6, 15), 3);
+        workout.workout_type = WorkoutType::TimeInterval;
+        for (frame, distance) in workout.frames.iter_mut().zip(&[310, 305, 300]) {
+            frame.distance = *distance;
+            frame.rest_duration = Some(Duration::from_secs(60));
+        }
+        workout.total_distance = 915;
+        workout.total_rest_duration = Some(Duration::from_secs(180));
+
+        // less than 1000m counts as a single 500m split
+        assert_eq!(workout.pace(), Duration::from_secs(360));
+        assert_eq!(workout.average_pace_500m(), Duration::from_secs(360).mul_f64(500.0 / 915.0));
+
+        let single = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        assert_eq!(single.average_pace_500m(), single.pace());
+    }
+
+    #[test]
+    fn calorie_model() {
+        // 2000m at 2:00/500m, which the Concept2 calculator puts at 997 kcal/h
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        assert_close(workout.cal_hr(), 996.8);
+
+        // at 175lb, the weight-corrected rate is the same as the default
+        let model = CalorieModel { weight_kg: 79.38, age: None, sex: None };
+        assert_close(workout.calories(&model), workout.total_calories());
+        assert_close(workout.calories(&model), 132.9);
+
+        // heavier rowers burn more at the same power
+        let heavy = CalorieModel { weight_kg: 100.0, ..model };
+        assert_close(workout.calories(&heavy), 143.3);
+
+        // Keytel needs the heart rate, age and sex
+        for frame in workout.frames.iter_mut() {
+            frame.work_heart_rate = Some(150);
+        }
+        assert_close(workout.calories(&model), 132.9);
+        let male = CalorieModel { weight_kg: 80.0, age: Some(30), sex: Some(Sex::Male) };
+        assert_close(workout.calories(&male), 117.6);
+        let female = CalorieModel { weight_kg: 80.0, age: Some(30), sex: Some(Sex::Female) };
+        assert_close(workout.calories(&female), 74.2);
+        assert_close(workout.personal_cal_hr(&female), 556.3);
+
+        // the splits add up to the workout
+        let machine = workout.machine;
+        for model in &[model, heavy, male, female] {
+            let splits: f64 = workout.frames.iter().map(|f| f.personal_calories(machine, model)).sum();
+            assert_close(splits, workout.calories(model));
+        }
+        // splits without heart rate use the weight-corrected rate
+        workout.frames[0].work_heart_rate = None;
+        assert_close(workout.frames[0].personal_calories(machine, &male), 33.3);
+    }
+
+    #[test]
+    fn workout_type_from_str() {
+        for code in &[0x01, 0x03, 0x05, 0x06, 0x07, 0x08, 0x0A] {
+            let workout_type = WorkoutType::try_from(*code).unwrap();
+            assert_eq!(workout_type.to_string().parse::<WorkoutType>().unwrap(), workout_type);
+            assert_eq!(workout_type.to_string().to_uppercase().parse::<WorkoutType>().unwrap(), workout_type);
+        }
+        assert_eq!(" time interval ".parse::<WorkoutType>().unwrap(), WorkoutType::TimeInterval);
+
+        let error = "Marathon".parse::<WorkoutType>().unwrap_err().to_string();
+        assert!(error.contains("\"Marathon\""), "{}", error);
+        assert!(error.contains("\"Variable Interval\""), "{}", error);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn serialize() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        workout.total_work_duration = Duration::from_millis(480_500);
+
+        let json = serde_json::to_value(&workout).unwrap();
+        assert_eq!(json["workout_type"], "single_distance");
+        assert_eq!(json["machine"], "rower");
+        assert_eq!(json["datetime"], "2024-03-10T09:30:00");
+        assert_eq!(json["total_distance"], 2000);
+        assert_eq!(json["total_work_duration"], 480.5);
+        assert_eq!(json["total_rest_duration"], serde_json::Value::Null);
+        assert_eq!(json["frames"].as_array().unwrap().len(), 4);
+        assert_eq!(json["frames"][0]["work_duration"], 120.0);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn serialize_frames() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 3);
+        workout.frames[2].work_duration = Duration::from_millis(118_300);
+        workout.frames[2].work_heart_rate = Some(162);
+
+        let json = serde_json::to_value(&workout).unwrap();
+        let frames = json["frames"].as_array().unwrap();
+        assert_eq!(frames.len(), 3);
+
+        // the field names are part of the JSON output of show-workouts
+        let keys: Vec<&str> = frames[0].as_object().unwrap().keys().map(|k| k.as_str()).collect();
+        assert_eq!(keys, [
+            "distance", "drag_factor", "heart_rate_avg", "heart_rate_max", "heart_rate_min",
+            "rest_distance", "rest_duration", "rest_heart_rate", "spm", "work_duration",
+            "work_heart_rate"
+        ]);
+        assert_eq!(frames[2]["distance"], 500);
+        assert_eq!(frames[2]["work_duration"], 118.3);
+        assert_eq!(frames[2]["work_heart_rate"], 162);
+        assert_eq!(frames[1]["work_heart_rate"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn personal_bests() {
+        let timed = |distance: u32, minutes: u64| {
+            let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 1);
+            workout.workout_type = WorkoutType::SingleTime;
+            workout.total_distance = distance;
+            workout.total_work_duration = Duration::from_secs(minutes * 60);
+            workout
+        };
+        let mut fast = distance_workout(datetime(2024, 3, 11, 9, 30), 4);
+        fast.total_work_duration = Duration::from_secs(450);
+        let mut interval = distance_workout(datetime(2024, 3, 12, 9, 30), 4);
+        interval.workout_type = WorkoutType::DistanceInterval;
+        interval.total_work_duration = Duration::from_secs(400);
+
+        let workouts = vec![
+            distance_workout(datetime(2024, 3, 10, 9, 30), 4),
+            fast,
+            interval,
+            timed(7000, 30),
+            timed(7200, 30),
+            // 500m in 2:00 twice, the earlier one is kept
+            distance_workout(datetime(2024, 3, 13, 9, 30), 1),
+            distance_workout(datetime(2024, 3, 14, 9, 30), 1),
+            timed(1000, 4),
+        ];
+
+        assert_eq!(super::personal_bests(&workouts), [
+            PersonalBest { category: PersonalBestCategory::Distance(500), index: 5 },
+            PersonalBest { category: PersonalBestCategory::Distance(2000), index: 1 },
+            PersonalBest { category: PersonalBestCategory::Time(Duration::from_secs(240)), index: 7 },
+            PersonalBest { category: PersonalBestCategory::Time(Duration::from_secs(1800)), index: 4 },
+        ]);
+        assert!(super::personal_bests(&[]).is_empty());
+        assert_eq!(PersonalBestCategory::Time(Duration::from_secs(1800)).to_string(), "30 min");
+    }
+
+    #[test]
+    fn duration_strings() {
+        assert_eq!(duration_to_string(&Duration::ZERO), "0:00.0");
+        assert_eq!(duration_to_string(&Duration::from_millis(59_990)), "0:59.9");
+        assert_eq!(duration_to_string(&Duration::from_secs(3599)), "59:59.0");
+        assert_eq!(duration_to_string(&Duration::from_secs(3600)), "1:00:00.0");
+        assert_eq!(duration_to_string(&Duration::from_millis(3_723_450)), "1:02:03.4");
+
+        // fractions are truncated like on the monitor
+        let split = Duration::from_millis(118_769);
+        assert_eq!(duration_to_string_precision(&split, 0), "1:58");
+        assert_eq!(duration_to_string_precision(&split, 1), "1:58.7");
+        assert_eq!(duration_to_string_precision(&split, 2), "1:58.76");
+        assert_eq!(duration_to_string_precision(&split, 3), "1:58.769");
+        assert_eq!(duration_to_string_precision(&split, 9), "1:58.769");
+        assert_eq!(duration_to_string_precision(&Duration::ZERO, 2), "0:00.00");
+    }
+
+    #[test]
+    fn heart_rate_zones() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 8);
+        let heart_rates = [Some(90), Some(110), Some(130), Some(150), Some(170), Some(190), Some(210), None];
+        for (frame, hr) in workout.frames.iter_mut().zip(&heart_rates) {
+            frame.work_heart_rate = *hr;
+        }
+
+        let two_minutes = Duration::from_secs(120);
+        assert_eq!(workout.heart_rate_zones(200), [
+            two_minutes * 2, two_minutes, two_minutes, two_minutes, two_minutes * 2
+        ]);
+        assert_eq!(workout.duration_without_heart_rate(), two_minutes);
+        // 135 is 90% of 150, the higher heart rates don't get a zone of their own
+        assert_eq!(workout.heart_rate_zones(150)[4], two_minutes * 4);
+        assert_eq!(workout.heart_rate_zones(0), [Duration::ZERO; 5]);
+    }
+
+    #[test]
+    fn summarize_boundaries() {
+        let workouts = [
+            distance_workout(datetime(2024, 2, 29, 18, 0), 4),
+            distance_workout(datetime(2024, 3, 1, 7, 0), 2),
+            // Sunday and the following Monday
+            distance_workout(datetime(2024, 3, 10, 23, 59), 1),
+            distance_workout(datetime(2024, 3, 11, 0, 0), 1),
+            // ISO week 1 of 2025 starts on Monday 2024-12-30
+            distance_workout(datetime(2024, 12, 30, 9, 0), 1),
+            distance_workout(datetime(2025, 1, 1, 9, 0), 1),
+        ];
+
+        let weeks = summarize(&workouts, Granularity::Week);
+        let periods: Vec<(String, usize, u32)> = weeks.iter()
+            .map(|s| (s.period_string(), s.workouts, s.total_distance))
+            .collect();
+        assert_eq!(periods, [
+            ("2024-W09".to_string(), 2, 3000),
+            ("2024-W10".to_string(), 1, 500),
+            ("2024-W11".to_string(), 1, 500),
+            ("2025-W01".to_string(), 2, 1000),
+        ]);
+        assert_eq!(weeks[0].start, chrono::NaiveDate::from_ymd_opt(2024, 2, 26).unwrap());
+        assert_eq!(weeks[3].start, chrono::NaiveDate::from_ymd_opt(2024, 12, 30).unwrap());
+        assert_eq!(weeks[0].total_work_duration, Duration::from_secs(720));
+        assert_eq!(weeks[0].average_pace_500m(), Duration::from_secs(120));
+
+        let months = summarize(&workouts, Granularity::Month);
+        let periods: Vec<(String, usize)> = months.iter()
+            .map(|s| (s.period_string(), s.workouts))
+            .collect();
+        assert_eq!(periods, [
+            ("2024-02".to_string(), 1),
+            ("2024-03".to_string(), 3),
+            ("2024-12".to_string(), 1),
+            ("2025-01".to_string(), 1),
+        ]);
+
+        assert!(summarize(&[], Granularity::Week).is_empty());
+    }
+
+    #[test]
+    fn pace_watts_round_trip() {
+        for millis in &[85_000, 100_000, 120_000, 150_000, 240_000] {
+            let pace = Duration::from_millis(*millis);
+            let round_trip = pace_from_watts(watts_from_pace(pace));
+            assert!((round_trip.as_secs_f64() - pace.as_secs_f64()).abs() < 1e-6, "{:?} != {:?}", round_trip, pace);
+        }
+
+        assert_close(watts_from_pace(Duration::from_secs(120)), 202.5);
+        // 200W is just under 2:00/500m
+        assert_eq!(duration_to_string(&pace_from_watts(200.0)), "2:00.5");
+        assert_eq!(pace_from_watts(0.0), Duration::from_secs(0));
+        assert_eq!(pace_from_watts(-10.0), Duration::from_secs(0));
+        assert_eq!(watts_from_pace(Duration::from_secs(0)), 0.0);
+    }
+
+    #[test]
+    fn projected_time() {
+        let workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        assert_eq!(project_time(&workout, 2000), Duration::from_secs(480));
+        // longer distances are rowed at a slower pace
+        let projected = project_time(&workout, 5000);
+        assert_close(projected.as_secs_f64(), 480.0 * 2.5f64.powf(1.06));
+        assert!(projected > Duration::from_secs(1200));
+        assert!(project_time(&workout, 1000) < Duration::from_secs(240));
+
+        let empty = distance_workout(datetime(2024, 3, 10, 9, 30), 0);
+        assert_eq!(project_time(&empty, 2000), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn average_and_peak_power() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 3);
+        // 2:00, 1:40 and 2:00 per 500m
+        workout.frames[1].work_duration = Duration::from_secs(100);
+        workout.total_work_duration = Duration::from_secs(340);
+
+        assert_close(workout.frames[1].watts(workout.machine), 350.0);
+        // weighted by how long each power was held
+        assert_close(workout.average_power(), (202.5 * 240.0 + 350.0 * 100.0) / 340.0);
+        // the total based figure is lower, since power isn't linear in pace
+        assert!(workout.watts() < workout.average_power());
+
+        let peak = workout.peak_power().unwrap();
+        assert_eq!(peak.work_duration, Duration::from_secs(100));
+
+        // without frames, the totals are all there is
+        workout.frames.clear();
+        assert_close(workout.average_power(), workout.watts());
+        assert!(workout.peak_power().is_none());
+    }
+
+    #[test]
+    fn partial_heart_rate() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        assert_eq!(workout.heart_rate(), None);
+        assert_eq!(workout.max_heart_rate(), None);
+        assert_eq!(workout.min_heart_rate(), None);
+
+        // the chest strap dropped out in the second split
+        workout.frames[0].work_heart_rate = Some(140);
+        workout.frames[2].work_heart_rate = Some(151);
+        workout.frames[3].work_heart_rate = Some(160);
+        assert_eq!(workout.heart_rate(), Some(150));
+        assert_eq!(workout.max_heart_rate(), Some(160));
+        assert_eq!(workout.min_heart_rate(), Some(140));
+
+        // per-frame extremes are preferred over the frame averages
+        workout.frames[0].heart_rate_min = Some(118);
+        workout.frames[2].heart_rate_max = Some(172);
+        workout.frames[1].heart_rate_max = Some(175);
+        assert_eq!(workout.heart_rate(), Some(150));
+        assert_eq!(workout.max_heart_rate(), Some(175));
+        assert_eq!(workout.min_heart_rate(), Some(118));
+    }
+
+    #[test]
+    fn resplit() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 5);
+        let splits = [(120, 20, Some(140)), (110, 30, None), (100, 24, Some(150)), (130, 24, Some(160)), (125, 24, None)];
+        for (frame, (secs, spm, hr)) in workout.frames.iter_mut().zip(&splits) {
+            frame.work_duration = Duration::from_secs(*secs);
+            frame.spm = *spm;
+            frame.work_heart_rate = *hr;
+        }
+
+        let segments = workout.resplit(1000);
+        let summary: Vec<(usize, u32, Duration, Duration)> = segments.iter()
+            .map(|s| (s.index, s.distance, s.duration, s.pace))
+            .collect();
+        assert_eq!(summary, [
+            (1, 1000, Duration::from_secs(230), Duration::from_secs(115)),
+            (2, 1000, Duration::from_secs(230), Duration::from_secs(115)),
+            // the partial final split
+            (3, 500, Duration::from_secs(125), Duration::from_secs(125)),
+        ]);
+        // weighted by time, missing heart rates are left out
+        assert_eq!(segments.iter().map(|s| s.spm).collect::<Vec<_>>(), [25, 24, 24]);
+        assert_eq!(segments.iter().map(|s| s.heart_rate).collect::<Vec<_>>(), [Some(140), Some(156), None]);
+
+        // frames crossing a boundary are divided proportionally
+        let segments = workout.resplit(750);
+        assert_eq!(segments.iter().map(|s| s.distance).collect::<Vec<_>>(), [750, 750, 750, 250]);
+        assert_eq!(segments.iter().map(|s| s.duration.as_secs_f64()).collect::<Vec<_>>(), [175.0, 155.0, 192.5, 62.5]);
+        assert_eq!(segments[3].pace, Duration::from_secs(125));
+
+        assert!(workout.resplit(0).is_empty());
+        assert_eq!(workout.resplit(5000).len(), 1);
+    }
+
+    #[test]
+    fn splits() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        for (frame, secs) in workout.frames.iter_mut().zip(&[118, 121, 122, 119]) {
+            frame.work_duration = Duration::from_secs(*secs);
+        }
+        workout.frames[2].work_heart_rate = Some(160);
+
+        let splits = workout.splits();
+        assert_eq!(splits.len(), workout.frames.len());
+        for (split, frame) in splits.iter().zip(&workout.frames) {
+            assert_eq!(split.distance, frame.distance);
+            assert_eq!(split.duration, frame.work_duration);
+            assert_eq!(split.pace, frame.pace());
+            assert_eq!(split.spm, frame.spm);
+            assert_eq!(split.heart_rate, frame.work_heart_rate);
+        }
+        assert_eq!(splits.iter().map(|s| s.index).collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(splits[1].pace_string(), "2:01.0");
+
+        assert_eq!(workout.fastest_split().unwrap().index, 1);
+        assert_eq!(workout.slowest_split().unwrap().index, 3);
+
+        workout.frames.clear();
+        assert!(workout.splits().is_empty());
+        assert!(workout.fastest_split().is_none());
+    }
+
+    #[test]
+    fn split_pace_spread() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 5);
+        for (frame, secs) in workout.frames.iter_mut().zip(&[115, 125, 115, 125, 0]) {
+            frame.work_duration = Duration::from_secs(*secs);
+        }
+        // e.g. a split aborted right after it started
+        workout.frames[4].distance = 0;
+
+        assert_eq!(workout.fastest_split().unwrap().index, 1);
+        assert_eq!(workout.fastest_split().unwrap().pace_string(), "1:55.0");
+        assert_eq!(workout.slowest_split().unwrap().index, 2);
+        // 5s around 2:00 in every split
+        assert_eq!(workout.pace_std_dev(), Some(Duration::from_secs(5)));
+
+        // splits of different length, 500m in 1:40 and 1000m in 4:00
+        workout.frames.truncate(2);
+        workout.frames[0].work_duration = Duration::from_secs(100);
+        workout.frames[1].distance = 1000;
+        workout.frames[1].work_duration = Duration::from_secs(240);
+        assert_eq!(workout.slowest_split().unwrap().pace_string(), "2:00.0");
+        assert_eq!(workout.pace_std_dev(), Some(Duration::from_secs(10)));
+
+        workout.frames.truncate(1);
+        assert_eq!(workout.fastest_split(), workout.slowest_split());
+        assert!(workout.pace_std_dev().is_none());
+    }
+
+    #[test]
+    fn total_calories() {
+        // 2:00/500m for 8:00, at 996.8 kcal/h
+        let workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        assert_close(workout.cal_hr(), 996.8);
+        assert_close(workout.total_calories(), 996.8 * 8.0 / 60.0);
+
+        // rest between intervals doesn't burn anything
+        let mut interval = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        interval.workout_type = WorkoutType::DistanceInterval;
+        for frame in &mut interval.frames {
+            frame.rest_duration = Some(Duration::from_secs(60));
+        }
+        interval.total_rest_duration = Some(Duration::from_secs(240));
+        assert_close(interval.total_calories(), workout.total_calories());
+    }
+
+    #[test]
+    fn split_calories() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        workout.workout_type = WorkoutType::DistanceInterval;
+        for (frame, secs) in workout.frames.iter_mut().zip(&[110, 120, 130, 125]) {
+            frame.work_duration = Duration::from_secs(*secs);
+            frame.rest_duration = Some(Duration::from_secs(90));
+        }
+        workout.total_work_duration = Duration::from_secs(485);
+        workout.total_rest_duration = Some(Duration::from_secs(360));
+
+        // 2:00/500m is 996.8 kcal/h, without the rest
+        assert_close(workout.frames[1].calories(MachineType::Rower), 996.8 * 2.0 / 60.0);
+
+        // power isn't linear in pace, so they only add up roughly
+        let sum: f64 = workout.frames.iter().map(|f| f.calories(workout.machine)).sum();
+        let total = workout.total_calories();
+        assert!((sum - total).abs() < total * 0.01, "{} != {}", sum, total);
+        assert!(sum > total);
+    }
+
+    #[test]
+    fn watts_per_kg() {
+        // 1:40/500m is 350W
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        workout.total_work_duration = Duration::from_secs(400);
+        assert_close(workout.watts(), 350.0);
+        assert_close(workout.watts_per_kg(70.0).unwrap(), 5.0);
+        assert_close(workout.watts_per_kg(87.5).unwrap(), 4.0);
+        for weight in &[0.0, -70.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(workout.watts_per_kg(*weight), None);
+        }
+
+        // at the average pace of the period, not the average power, so for
+        // workouts at 1:40 and 2:00, i.e. 1:50 and 263W, not 276W
+        let slower = distance_workout(datetime(2024, 3, 11, 9, 30), 4);
+        let summaries = summarize(&[workout, slower], Granularity::Month);
+        assert_eq!(summaries.len(), 1);
+        assert_close(summaries[0].watts_per_kg(70.0).unwrap() * 70.0, 2.8 / 0.22f64.powi(3));
+        assert_eq!(summaries[0].watts_per_kg(0.0), None);
+    }
+
+    #[test]
+    fn power_uses_tenths() {
+        // 1:58.7/500m, not 1:58
+        let duration = Duration::from_millis(118_700);
+        let model = PowerModel::default();
+        assert_close(model.watts(duration, 500), 209.3);
+        assert_close(model.watts(duration, 500), watts_from_pace(duration));
+        // less than a second is still a pace
+        assert!(model.watts(Duration::from_millis(500), 1) > 0.0);
+        assert_eq!(model.watts(Duration::ZERO, 500), 0.0);
+
+        // workouts and summaries agree on the same data
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 4);
+        workout.total_work_duration = Duration::from_millis(4 * 118_700);
+        let summaries = summarize(std::slice::from_ref(&workout), Granularity::Month);
+        assert_close(workout.watts_per_kg(70.0).unwrap(), summaries[0].watts_per_kg(70.0).unwrap());
+        assert_close(workout.watts_per_kg(70.0).unwrap() * 70.0, 209.3);
+    }
+
+    #[test]
+    fn interval_spm() {
+        let mut workout = distance_workout(datetime(2024, 3, 10, 9, 30), 3);
+        workout.workout_type = WorkoutType::TimeInterval;
+        workout.spm = None;
+        // 4:00 at 20, 1:00 at 32 and 2:00 at 26
+        for (frame, (secs, spm)) in workout.frames.iter_mut().zip(&[(240, 20), (60, 32), (120, 26)]) {
+            frame.work_duration = Duration::from_secs(*secs);
+            frame.spm = *spm;
+            frame.rest_duration = Some(Duration::from_secs(60));
+        }
+
+        // (4 * 20 + 32 + 2 * 26) / 7 = 23.4, while the plain mean is 26
+        assert_eq!(workout.average_spm(), Some(23));
+        assert_eq!(workout.max_spm(), Some(32));
+
+        workout.frames.clear();
+        assert_eq!(workout.average_spm(), None);
+        assert_eq!(workout.max_spm(), None);
+    }
+
+    #[test]
+    fn pace_percentiles() {
+        let workout = |seconds: u64| Workout {
+            total_distance: 2000,
+            total_work_duration: Duration::from_secs(seconds),
+            ..distance_workout(datetime(2024, 3, 10, 9, 30), 4)
+        };
+        // 2k pieces at 1:45, 1:50, 1:55, 2:00 and 2:05, in any order
+        let mut workouts: Vec<Workout> = [480, 420, 500, 440, 460].iter().map(|s| workout(*s)).collect();
+        workouts.push(Workout { total_distance: 0, ..workout(60) });
+
+        let paces = pace_distribution(&workouts);
+        assert_eq!(paces.len(), 5);
+        assert_eq!(paces.min(), Some(Duration::from_secs(105)));
+        assert_eq!(paces.max(), Some(Duration::from_secs(125)));
+        assert_eq!(paces.median(), Some(Duration::from_secs(115)));
+        assert_eq!(paces.percentile(25.0), Some(Duration::from_secs(110)));
+        assert_eq!(paces.percentile(75.0), Some(Duration::from_secs(120)));
+        // between two workouts
+        assert_eq!(paces.percentile(10.0), Some(Duration::from_secs(107)));
+        assert_eq!(paces.percentile(90.0), Some(Duration::from_secs(123)));
+        assert_eq!(paces.percentile(150.0), paces.max());
+
+        // even number of workouts, without the one at 2:00
+        let paces = pace_distribution(&workouts[1..5]);
+        assert_eq!(paces.median(), Some(Duration::from_millis(112_500)));
+
+        let paces = pace_distribution(&workouts[5..]);
+        assert!(paces.is_empty());
+        assert_eq!((paces.min(), paces.median(), paces.max()), (None, None, None));
+    }
+}